@@ -1,12 +1,21 @@
 use custom_error::custom_error;
 use sqlx::Pool;
 use sqlx::Sqlite;
+use sqlx::Transaction;
 use tokio::sync::RwLock;
 
 pub mod account;
+pub mod cdn;
+pub mod interaction;
+pub mod interruption;
+pub mod keyword;
+pub mod marker;
+pub mod media;
 pub mod message;
+pub mod metrics;
 pub mod record;
 pub mod recorder;
+pub mod statistics;
 pub mod video;
 
 pub struct Database {
@@ -44,4 +53,10 @@ impl Database {
     pub async fn set(&self, p: Pool<Sqlite>) {
         *self.db.write().await = Some(p);
     }
+
+    /// Changes spanning several tables, nothing is kept unless it's committed
+    pub async fn begin(&self) -> Result<Transaction<'static, Sqlite>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(lock.begin().await?)
+    }
 }
@@ -10,11 +10,14 @@ pub struct AccountRow {
     pub csrf: String,
     pub cookies: String,
     pub created_at: String,
+    /// cookies are kept from idle expiry by a periodic request
+    pub keepalive: bool,
+    pub last_keepalive: Option<String>,
 }
 
 // accounts
 impl Database {
-    // CREATE TABLE accounts (uid INTEGER PRIMARY KEY, name TEXT, avatar TEXT, csrf TEXT, cookies TEXT, created_at TEXT);
+    // CREATE TABLE accounts (uid INTEGER PRIMARY KEY, name TEXT, avatar TEXT, csrf TEXT, cookies TEXT, created_at TEXT, keepalive INTEGER DEFAULT 1, last_keepalive TEXT);
     pub async fn add_account(&self, cookies: &str) -> Result<AccountRow, DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
         // parse cookies
@@ -54,6 +57,8 @@ impl Database {
             csrf: csrf.unwrap(),
             cookies: cookies.into(),
             created_at: Utc::now().to_rfc3339(),
+            keepalive: true,
+            last_keepalive: None,
         };
 
         sqlx::query("INSERT INTO accounts (uid, name, avatar, csrf, cookies, created_at) VALUES ($1, $2, $3, $4, $5, $6)").bind(account.uid as i64).bind(&account.name).bind(&account.avatar).bind(&account.csrf).bind(&account.cookies).bind(&account.created_at).execute(&lock).await?;
@@ -92,6 +97,33 @@ impl Database {
         Ok(())
     }
 
+    pub async fn set_account_keepalive(
+        &self,
+        uid: u64,
+        enabled: bool,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = sqlx::query("UPDATE accounts SET keepalive = $1 WHERE uid = $2")
+            .bind(enabled)
+            .bind(uid as i64)
+            .execute(&lock)
+            .await?;
+        if sql.rows_affected() != 1 {
+            return Err(DatabaseError::NotFoundError);
+        }
+        Ok(())
+    }
+
+    pub async fn update_last_keepalive(&self, uid: u64) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("UPDATE accounts SET last_keepalive = $1 WHERE uid = $2")
+            .bind(Utc::now().to_rfc3339())
+            .bind(uid as i64)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_accounts(&self) -> Result<Vec<AccountRow>, DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
         Ok(sqlx::query_as::<_, AccountRow>("SELECT * FROM accounts")
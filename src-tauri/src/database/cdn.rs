@@ -0,0 +1,65 @@
+use super::Database;
+use super::DatabaseError;
+use chrono::Utc;
+
+/// Result of latest cdn speedtest, speed is in bytes/s and 0 means host is unavailable
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct CdnSpeedRow {
+    pub platform: String,
+    pub host: String,
+    pub speed: i64,
+    pub created_at: String,
+}
+
+// CREATE TABLE cdn_speeds (platform TEXT, host TEXT, speed INTEGER, created_at TEXT, PRIMARY KEY (platform, host));
+impl Database {
+    /// replace previous speedtest results of platform
+    pub async fn set_cdn_speeds(
+        &self,
+        platform: &str,
+        results: &[(String, u64)],
+    ) -> Result<Vec<CdnSpeedRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        // readers see either old results or new ones, never an empty ranking
+        let mut tx = lock.begin().await?;
+        sqlx::query("DELETE FROM cdn_speeds WHERE platform = $1")
+            .bind(platform)
+            .execute(&mut *tx)
+            .await?;
+        let created_at = Utc::now().to_rfc3339();
+        for (host, speed) in results {
+            sqlx::query(
+                "INSERT OR REPLACE INTO cdn_speeds (platform, host, speed, created_at) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(platform)
+            .bind(host)
+            .bind(*speed as i64)
+            .bind(&created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.get_cdn_speeds(platform).await
+    }
+
+    pub async fn get_cdn_speeds(&self, platform: &str) -> Result<Vec<CdnSpeedRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, CdnSpeedRow>(
+            "SELECT * FROM cdn_speeds WHERE platform = $1 ORDER BY speed DESC",
+        )
+        .bind(platform)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    /// available hosts of platform, fastest first
+    pub async fn get_cdn_ranking(&self, platform: &str) -> Result<Vec<String>, DatabaseError> {
+        Ok(self
+            .get_cdn_speeds(platform)
+            .await?
+            .into_iter()
+            .filter(|r| r.speed > 0)
+            .map(|r| r.host)
+            .collect())
+    }
+}
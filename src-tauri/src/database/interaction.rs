@@ -0,0 +1,143 @@
+use super::Database;
+use super::DatabaseError;
+use sqlx::{Sqlite, Transaction};
+
+/// SuperChat, gift and guard events received during a live.
+/// value is counted in gold coins (1000 = 1 CNY).
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct InteractionRow {
+    pub id: i64,
+    pub live_id: u64,
+    pub room_id: u64,
+    pub kind: String,
+    pub uid: u64,
+    pub user_name: String,
+    pub content: String,
+    pub gift_name: String,
+    pub num: i64,
+    pub value: i64,
+    pub ts: i64,
+}
+
+/// Revenue in gold coins of a period, period is live id or month like 2024-01
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct RevenueStatRow {
+    pub period: String,
+    pub superchat: i64,
+    pub gift: i64,
+    pub guard: i64,
+    pub total: i64,
+}
+
+/// Gifts of the same name summed up, count is the number of gifts and value is in gold coins
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct GiftSummaryRow {
+    pub gift_name: String,
+    pub count: i64,
+    pub value: i64,
+}
+
+const REVENUE_COLUMNS: &str = "SUM(CASE WHEN kind = 'superchat' THEN value ELSE 0 END) AS superchat, SUM(CASE WHEN kind = 'gift' THEN value ELSE 0 END) AS gift, SUM(CASE WHEN kind = 'guard' THEN value ELSE 0 END) AS guard, SUM(value) AS total";
+
+// CREATE TABLE interactions (id INTEGER PRIMARY KEY AUTOINCREMENT, live_id INTEGER, room_id INTEGER, kind TEXT, uid INTEGER, user_name TEXT, content TEXT, gift_name TEXT, num INTEGER, value INTEGER, ts INTEGER);
+impl Database {
+    pub async fn add_interaction(
+        &self,
+        interaction: &InteractionRow,
+    ) -> Result<InteractionRow, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = sqlx::query("INSERT INTO interactions (live_id, room_id, kind, uid, user_name, content, gift_name, num, value, ts) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)")
+            .bind(interaction.live_id as i64)
+            .bind(interaction.room_id as i64)
+            .bind(&interaction.kind)
+            .bind(interaction.uid as i64)
+            .bind(&interaction.user_name)
+            .bind(&interaction.content)
+            .bind(&interaction.gift_name)
+            .bind(interaction.num)
+            .bind(interaction.value)
+            .bind(interaction.ts)
+            .execute(&lock)
+            .await?;
+        Ok(InteractionRow {
+            id: sql.last_insert_rowid(),
+            ..interaction.clone()
+        })
+    }
+
+    pub async fn get_interactions(
+        &self,
+        live_id: u64,
+    ) -> Result<Vec<InteractionRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, InteractionRow>(
+            "SELECT * FROM interactions WHERE live_id = $1 ORDER BY ts",
+        )
+        .bind(live_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    pub async fn get_revenue_by_live(
+        &self,
+        room_id: u64,
+    ) -> Result<Vec<RevenueStatRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, RevenueStatRow>(&format!(
+            "SELECT CAST(live_id AS TEXT) AS period, {} FROM interactions WHERE room_id = $1 GROUP BY live_id ORDER BY live_id DESC",
+            REVENUE_COLUMNS
+        ))
+        .bind(room_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    /// Months are in local time
+    pub async fn get_revenue_by_month(
+        &self,
+        room_id: u64,
+    ) -> Result<Vec<RevenueStatRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, RevenueStatRow>(&format!(
+            "SELECT strftime('%Y-%m', ts / 1000, 'unixepoch', 'localtime') AS period, {} FROM interactions WHERE room_id = $1 GROUP BY period ORDER BY period DESC",
+            REVENUE_COLUMNS
+        ))
+        .bind(room_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    /// Gift summary of a room, or only of one live if live_id is given
+    pub async fn get_gift_summary(
+        &self,
+        room_id: u64,
+        live_id: Option<u64>,
+    ) -> Result<Vec<GiftSummaryRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = match live_id {
+            Some(_) => "SELECT gift_name, SUM(num) AS count, SUM(value) AS value FROM interactions WHERE kind = 'gift' AND room_id = $1 AND live_id = $2 GROUP BY gift_name ORDER BY value DESC",
+            None => "SELECT gift_name, SUM(num) AS count, SUM(value) AS value FROM interactions WHERE kind = 'gift' AND room_id = $1 GROUP BY gift_name ORDER BY value DESC",
+        };
+        let mut query = sqlx::query_as::<_, GiftSummaryRow>(sql).bind(room_id as i64);
+        if let Some(live_id) = live_id {
+            query = query.bind(live_id as i64);
+        }
+        Ok(query.fetch_all(&lock).await?)
+    }
+
+    pub async fn move_interactions(
+        &self,
+        tx: &mut Transaction<'static, Sqlite>,
+        room_id: u64,
+        from: u64,
+        to: u64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE interactions SET live_id = $1 WHERE room_id = $2 and live_id = $3")
+            .bind(to as i64)
+            .bind(room_id as i64)
+            .bind(from as i64)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
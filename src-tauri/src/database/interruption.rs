@@ -0,0 +1,71 @@
+use super::Database;
+use super::DatabaseError;
+use chrono::Utc;
+use sqlx::{Sqlite, Transaction};
+
+/// Range of a live taken by an ad or technical difficulty slate, in seconds relative to playback start
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct InterruptionRow {
+    pub id: i64,
+    pub room_id: u64,
+    pub live_id: u64,
+    pub start: f64,
+    pub end: f64,
+    pub created_at: String,
+}
+
+// CREATE TABLE interruptions (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, start REAL, end REAL, created_at TEXT);
+impl Database {
+    pub async fn add_interruption(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        start: f64,
+        end: f64,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("INSERT INTO interruptions (room_id, live_id, start, end, created_at) VALUES ($1, $2, $3, $4, $5)")
+            .bind(room_id as i64)
+            .bind(live_id as i64)
+            .bind(start)
+            .bind(end)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_interruptions(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<InterruptionRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, InterruptionRow>(
+            "SELECT * FROM interruptions WHERE room_id = $1 and live_id = $2 ORDER BY start",
+        )
+        .bind(room_id as i64)
+        .bind(live_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    /// Move interruptions into another live, ranges are shifted by `shift` seconds
+    pub async fn move_interruptions(
+        &self,
+        tx: &mut Transaction<'static, Sqlite>,
+        room_id: u64,
+        from: u64,
+        to: u64,
+        shift: f64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE interruptions SET live_id = $1, start = start + $2, end = end + $2 WHERE room_id = $3 and live_id = $4")
+            .bind(to as i64)
+            .bind(shift)
+            .bind(room_id as i64)
+            .bind(from as i64)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use super::Database;
+use super::DatabaseError;
+use chrono::Utc;
+
+/// Danmu matching pattern triggers a keyword hit, pattern is a regex if is_regex is set,
+/// otherwise it is matched as plain text.
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct KeywordRuleRow {
+    pub id: i64,
+    pub room_id: u64,
+    pub pattern: String,
+    pub is_regex: bool,
+    pub created_at: String,
+}
+
+// CREATE TABLE keyword_rules (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, pattern TEXT, is_regex INTEGER, created_at TEXT);
+impl Database {
+    pub async fn add_keyword_rule(
+        &self,
+        room_id: u64,
+        pattern: &str,
+        is_regex: bool,
+    ) -> Result<KeywordRuleRow, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let created_at = Utc::now().to_rfc3339();
+        let sql = sqlx::query(
+            "INSERT INTO keyword_rules (room_id, pattern, is_regex, created_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(room_id as i64)
+        .bind(pattern)
+        .bind(is_regex)
+        .bind(&created_at)
+        .execute(&lock)
+        .await?;
+        Ok(KeywordRuleRow {
+            id: sql.last_insert_rowid(),
+            room_id,
+            pattern: pattern.into(),
+            is_regex,
+            created_at,
+        })
+    }
+
+    pub async fn remove_keyword_rule(&self, id: i64) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = sqlx::query("DELETE FROM keyword_rules WHERE id = $1")
+            .bind(id)
+            .execute(&lock)
+            .await?;
+        if sql.rows_affected() != 1 {
+            return Err(DatabaseError::NotFoundError);
+        }
+        Ok(())
+    }
+
+    pub async fn get_keyword_rule(&self, id: i64) -> Result<KeywordRuleRow, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(
+            sqlx::query_as::<_, KeywordRuleRow>("SELECT * FROM keyword_rules WHERE id = $1")
+                .bind(id)
+                .fetch_one(&lock)
+                .await?,
+        )
+    }
+
+    pub async fn get_keyword_rules(
+        &self,
+        room_id: u64,
+    ) -> Result<Vec<KeywordRuleRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(
+            sqlx::query_as::<_, KeywordRuleRow>("SELECT * FROM keyword_rules WHERE room_id = $1")
+                .bind(room_id as i64)
+                .fetch_all(&lock)
+                .await?,
+        )
+    }
+}
@@ -0,0 +1,99 @@
+use super::Database;
+use super::DatabaseError;
+use chrono::Utc;
+use sqlx::{Sqlite, Transaction};
+
+/// Timestamp marker on a record, offset is relative to playback start and realtime is in seconds
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct MarkerRow {
+    pub id: i64,
+    pub room_id: u64,
+    pub live_id: u64,
+    pub offset: f64,
+    pub realtime: i64,
+    pub content: String,
+    pub created_at: String,
+}
+
+// CREATE TABLE markers (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, offset REAL, realtime INTEGER, content TEXT, created_at TEXT);
+impl Database {
+    pub async fn add_marker(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        offset: f64,
+        realtime: i64,
+        content: &str,
+    ) -> Result<MarkerRow, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let created_at = Utc::now().to_rfc3339();
+        let sql = sqlx::query("INSERT INTO markers (room_id, live_id, offset, realtime, content, created_at) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(room_id as i64)
+            .bind(live_id as i64)
+            .bind(offset)
+            .bind(realtime)
+            .bind(content)
+            .bind(&created_at)
+            .execute(&lock)
+            .await?;
+        Ok(MarkerRow {
+            id: sql.last_insert_rowid(),
+            room_id,
+            live_id,
+            offset,
+            realtime,
+            content: content.into(),
+            created_at,
+        })
+    }
+
+    pub async fn remove_markers(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        content: &str,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("DELETE FROM markers WHERE room_id = $1 and live_id = $2 and content = $3")
+            .bind(room_id as i64)
+            .bind(live_id as i64)
+            .bind(content)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_markers(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<MarkerRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, MarkerRow>(
+            "SELECT * FROM markers WHERE room_id = $1 and live_id = $2 ORDER BY offset",
+        )
+        .bind(room_id as i64)
+        .bind(live_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    /// Move markers into another live, offsets are shifted by `shift` seconds
+    pub async fn move_markers(
+        &self,
+        tx: &mut Transaction<'static, Sqlite>,
+        room_id: u64,
+        from: u64,
+        to: u64,
+        shift: f64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE markers SET live_id = $1, offset = offset + $2 WHERE room_id = $3 and live_id = $4")
+            .bind(to as i64)
+            .bind(shift)
+            .bind(room_id as i64)
+            .bind(from as i64)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
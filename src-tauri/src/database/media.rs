@@ -0,0 +1,70 @@
+use super::Database;
+use super::DatabaseError;
+
+/// Cached ffprobe result of a file, valid while mtime of the file is unchanged
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct MediaInfoRow {
+    pub path: String,
+    pub mtime: i64,
+    pub container: String,
+    pub codec: String,
+    pub width: i64,
+    pub height: i64,
+}
+
+// CREATE TABLE media_info (path TEXT PRIMARY KEY, mtime INTEGER, container TEXT, codec TEXT, width INTEGER, height INTEGER);
+impl Database {
+    pub async fn get_media_info(
+        &self,
+        path: &str,
+        mtime: i64,
+    ) -> Result<MediaInfoRow, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let row = sqlx::query_as::<_, MediaInfoRow>(
+            "SELECT * FROM media_info WHERE path = $1 AND mtime = $2",
+        )
+        .bind(path)
+        .bind(mtime)
+        .fetch_optional(&lock)
+        .await?;
+        row.ok_or(DatabaseError::NotFoundError)
+    }
+
+    /// one row is kept for each path, older result is replaced
+    pub async fn set_media_info(&self, row: &MediaInfoRow) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query(
+            "INSERT OR REPLACE INTO media_info (path, mtime, container, codec, width, height) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&row.path)
+        .bind(row.mtime)
+        .bind(&row.container)
+        .bind(&row.codec)
+        .bind(row.width)
+        .bind(row.height)
+        .execute(&lock)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_media_info(&self, path: &str) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("DELETE FROM media_info WHERE path = $1")
+            .bind(path)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_media_info_paths(&self) -> Result<Vec<String>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(
+            sqlx::query_as::<_, (String,)>("SELECT path FROM media_info")
+                .fetch_all(&lock)
+                .await?
+                .into_iter()
+                .map(|(path,)| path)
+                .collect(),
+        )
+    }
+}
@@ -1,5 +1,6 @@
 use super::Database;
 use super::DatabaseError;
+use crate::locale::{Locale, MessageKey};
 use chrono::Utc;
 
 #[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
@@ -9,19 +10,33 @@ pub struct MessageRow {
     pub content: String,
     pub read: u8,
     pub created_at: String,
+    /// message key and json params, title and content are rendered from them when read.
+    /// Messages saved before keys were added have none, and keep their original text
+    #[serde(skip)]
+    pub key: Option<String>,
+    #[serde(skip)]
+    pub params: Option<String>,
 }
 
 // messages
-// CREATE TABLE messages (id INTEGER PRIMARY KEY, title TEXT, content TEXT, read INTEGER, created_at TEXT);
+// CREATE TABLE messages (id INTEGER PRIMARY KEY, title TEXT, content TEXT, read INTEGER, created_at TEXT, key TEXT, params TEXT);
 impl Database {
-    pub async fn new_message(&self, title: &str, content: &str) -> Result<(), DatabaseError> {
+    /// Title and content are also saved rendered in default locale, as a fallback
+    pub async fn new_message(
+        &self,
+        key: MessageKey,
+        params: &[String],
+    ) -> Result<(), DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
+        let (title, content) = key.render(Locale::default(), params);
         sqlx::query(
-            "INSERT INTO messages (title, content, read, created_at) VALUES ($1, $2, 0, $3)",
+            "INSERT INTO messages (title, content, read, created_at, key, params) VALUES ($1, $2, 0, $3, $4, $5)",
         )
         .bind(title)
         .bind(content)
         .bind(Utc::now().to_rfc3339())
+        .bind(serde_json::to_value(key).unwrap().as_str().unwrap_or_default().to_string())
+        .bind(serde_json::to_string(params).unwrap_or_default())
         .execute(&lock)
         .await?;
         Ok(())
@@ -46,10 +61,25 @@ impl Database {
         Ok(())
     }
 
-    pub async fn get_messages(&self) -> Result<Vec<MessageRow>, DatabaseError> {
+    pub async fn get_messages(&self, locale: Locale) -> Result<Vec<MessageRow>, DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
-        Ok(sqlx::query_as::<_, MessageRow>("SELECT * FROM messages;")
+        let mut messages = sqlx::query_as::<_, MessageRow>("SELECT * FROM messages;")
             .fetch_all(&lock)
-            .await?)
+            .await?;
+        for m in messages.iter_mut() {
+            let key = m
+                .key
+                .as_ref()
+                .and_then(|k| serde_json::from_value::<MessageKey>(k.as_str().into()).ok());
+            if let Some(key) = key {
+                let params: Vec<String> = m
+                    .params
+                    .as_deref()
+                    .and_then(|p| serde_json::from_str(p).ok())
+                    .unwrap_or_default();
+                (m.title, m.content) = key.render(locale, &params);
+            }
+        }
+        Ok(messages)
     }
 }
@@ -0,0 +1,67 @@
+use super::Database;
+use super::DatabaseError;
+use sqlx::{Sqlite, Transaction};
+
+/// Online count reported by the platform during a live, ts is in seconds
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct RoomMetricRow {
+    pub id: i64,
+    pub room_id: u64,
+    pub live_id: u64,
+    pub online: i64,
+    pub ts: i64,
+}
+
+// CREATE TABLE room_metrics (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, online INTEGER, ts INTEGER);
+impl Database {
+    pub async fn add_room_metric(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        online: i64,
+        ts: i64,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query(
+            "INSERT INTO room_metrics (room_id, live_id, online, ts) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(room_id as i64)
+        .bind(live_id as i64)
+        .bind(online)
+        .bind(ts)
+        .execute(&lock)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_room_metrics(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<RoomMetricRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, RoomMetricRow>(
+            "SELECT * FROM room_metrics WHERE room_id = $1 and live_id = $2 ORDER BY ts",
+        )
+        .bind(room_id as i64)
+        .bind(live_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    pub async fn move_room_metrics(
+        &self,
+        tx: &mut Transaction<'static, Sqlite>,
+        room_id: u64,
+        from: u64,
+        to: u64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE room_metrics SET live_id = $1 WHERE room_id = $2 and live_id = $3")
+            .bind(to as i64)
+            .bind(room_id as i64)
+            .bind(from as i64)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
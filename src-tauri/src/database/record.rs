@@ -10,9 +10,11 @@ pub struct RecordRow {
     pub length: i64,
     pub size: i64,
     pub created_at: String,
+    /// url path of cover picked by `set_archive_cover`, platform room cover is used if not set
+    pub cover: Option<String>,
 }
 
-// CREATE TABLE records (live_id INTEGER PRIMARY KEY, room_id INTEGER, title TEXT, length INTEGER, size INTEGER, created_at TEXT);
+// CREATE TABLE records (live_id INTEGER PRIMARY KEY, room_id INTEGER, title TEXT, length INTEGER, size INTEGER, created_at TEXT, cover TEXT);
 impl Database {
     pub async fn get_records(&self, room_id: u64) -> Result<Vec<RecordRow>, DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
@@ -49,6 +51,7 @@ impl Database {
             length: 0,
             size: 0,
             created_at: Utc::now().to_rfc3339(),
+            cover: None,
         };
         if let Err(e) = sqlx::query("INSERT INTO records (live_id, room_id, title, length, size, created_at) VALUES ($1, $2, $3, $4, $5, $6)").bind(record.live_id as i64)
             .bind(record.room_id as i64).bind(&record.title).bind(0).bind(0).bind(&record.created_at).execute(&lock).await {
@@ -69,6 +72,16 @@ impl Database {
         Ok(())
     }
 
+    pub async fn set_record_cover(&self, live_id: u64, cover: &str) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("UPDATE records SET cover = $1 WHERE live_id = $2")
+            .bind(cover)
+            .bind(live_id as i64)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
     pub async fn update_record(
         &self,
         live_id: u64,
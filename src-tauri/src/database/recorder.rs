@@ -1,5 +1,7 @@
 use super::Database;
 use super::DatabaseError;
+use crate::recorder::bilibili::QN_ORIGINAL;
+use crate::recorder::RecordWindow;
 use chrono::Utc;
 
 /// Recorder in database is pretty simple
@@ -8,6 +10,11 @@ use chrono::Utc;
 pub struct RecorderRow {
     pub room_id: u64,
     pub created_at: String,
+    /// qn requested for play url, see `bilibili::QN_ORIGINAL`
+    pub quality: u64,
+    /// json of `RecordWindow` list, None for always recording
+    pub schedule: Option<String>,
+    pub priority: i64,
 }
 
 // recorders
@@ -17,6 +24,9 @@ impl Database {
         let recorder = RecorderRow {
             room_id,
             created_at: Utc::now().to_rfc3339(),
+            quality: QN_ORIGINAL,
+            schedule: None,
+            priority: 0,
         };
         let _ = sqlx::query("INSERT INTO recorders (room_id, created_at) VALUES ($1, $2)")
             .bind(room_id as i64)
@@ -44,4 +54,97 @@ impl Database {
             .fetch_all(&lock)
             .await?)
     }
+
+    /// Quality of a room, original if room is not saved yet
+    pub async fn get_recorder_quality(&self, room_id: u64) -> Result<u64, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let row: Option<(i64,)> =
+            sqlx::query_as("SELECT quality FROM recorders WHERE room_id = $1")
+                .bind(room_id as i64)
+                .fetch_optional(&lock)
+                .await?;
+        Ok(row.map(|r| r.0 as u64).unwrap_or(QN_ORIGINAL))
+    }
+
+    pub async fn set_recorder_quality(
+        &self,
+        room_id: u64,
+        quality: u64,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = sqlx::query("UPDATE recorders SET quality = $1 WHERE room_id = $2")
+            .bind(quality as i64)
+            .bind(room_id as i64)
+            .execute(&lock)
+            .await?;
+        if sql.rows_affected() != 1 {
+            return Err(DatabaseError::NotFoundError);
+        }
+        Ok(())
+    }
+
+    pub async fn get_recorder_priority(&self, room_id: u64) -> Result<i64, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let row: Option<(i64,)> =
+            sqlx::query_as("SELECT priority FROM recorders WHERE room_id = $1")
+                .bind(room_id as i64)
+                .fetch_optional(&lock)
+                .await?;
+        Ok(row.map(|r| r.0).unwrap_or(0))
+    }
+
+    pub async fn set_recorder_priority(
+        &self,
+        room_id: u64,
+        priority: i64,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let sql = sqlx::query("UPDATE recorders SET priority = $1 WHERE room_id = $2")
+            .bind(priority)
+            .bind(room_id as i64)
+            .execute(&lock)
+            .await?;
+        if sql.rows_affected() != 1 {
+            return Err(DatabaseError::NotFoundError);
+        }
+        Ok(())
+    }
+
+    pub async fn get_recorder_schedule(
+        &self,
+        room_id: u64,
+    ) -> Result<Vec<RecordWindow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT schedule FROM recorders WHERE room_id = $1")
+                .bind(room_id as i64)
+                .fetch_optional(&lock)
+                .await?;
+        Ok(row
+            .and_then(|r| r.0)
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_recorder_schedule(
+        &self,
+        room_id: u64,
+        schedule: &[RecordWindow],
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        let value = if schedule.is_empty() {
+            None
+        } else {
+            serde_json::to_string(schedule).ok()
+        };
+        let sql = sqlx::query("UPDATE recorders SET schedule = $1 WHERE room_id = $2")
+            .bind(value)
+            .bind(room_id as i64)
+            .execute(&lock)
+            .await?;
+        if sql.rows_affected() != 1 {
+            return Err(DatabaseError::NotFoundError);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,65 @@
+use super::Database;
+use super::DatabaseError;
+use sqlx::{Sqlite, Transaction};
+
+/// Danmu count of one minute in a live, time_point is the start of the minute
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct DanmuStatisticRow {
+    pub id: i64,
+    pub live_id: u64,
+    pub room_id: u64,
+    pub value: i64,
+    pub time_point: String,
+}
+
+// CREATE TABLE danmu_statistics (id INTEGER PRIMARY KEY AUTOINCREMENT, live_id INTEGER, room_id INTEGER, value INTEGER, time_point TEXT);
+impl Database {
+    pub async fn add_danmu_statistic(
+        &self,
+        live_id: u64,
+        room_id: u64,
+        value: i64,
+        time_point: &str,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("INSERT INTO danmu_statistics (live_id, room_id, value, time_point) VALUES ($1, $2, $3, $4)")
+            .bind(live_id as i64)
+            .bind(room_id as i64)
+            .bind(value)
+            .bind(time_point)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_danmu_statistics(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<DanmuStatisticRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, DanmuStatisticRow>(
+            "SELECT * FROM danmu_statistics WHERE room_id = $1 and live_id = $2 ORDER BY time_point",
+        )
+        .bind(room_id as i64)
+        .bind(live_id as i64)
+        .fetch_all(&lock)
+        .await?)
+    }
+
+    pub async fn move_danmu_statistics(
+        &self,
+        tx: &mut Transaction<'static, Sqlite>,
+        room_id: u64,
+        from: u64,
+        to: u64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE danmu_statistics SET live_id = $1 WHERE room_id = $2 and live_id = $3")
+            .bind(to as i64)
+            .bind(room_id as i64)
+            .bind(from as i64)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+}
@@ -30,6 +30,13 @@ impl Database {
         )
     }
 
+    pub async fn get_all_videos(&self) -> Result<Vec<VideoRow>, DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        Ok(sqlx::query_as::<_, VideoRow>("SELECT * FROM videos;")
+            .fetch_all(&lock)
+            .await?)
+    }
+
     pub async fn get_video(&self, id: i64) -> Result<VideoRow, DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
         Ok(
@@ -55,6 +62,22 @@ impl Database {
         Ok(())
     }
 
+    pub async fn update_video_file(
+        &self,
+        id: i64,
+        file: &str,
+        size: i64,
+    ) -> Result<(), DatabaseError> {
+        let lock = self.db.read().await.clone().unwrap();
+        sqlx::query("UPDATE videos SET file = $1, size = $2 WHERE id = $3")
+            .bind(file)
+            .bind(size)
+            .bind(id)
+            .execute(&lock)
+            .await?;
+        Ok(())
+    }
+
     pub async fn delete_video(&self, id: i64) -> Result<(), DatabaseError> {
         let lock = self.db.read().await.clone().unwrap();
         sqlx::query("DELETE FROM videos WHERE id = $1")
@@ -0,0 +1,68 @@
+use tauri::Url;
+
+/// Places in the app a link can point to, links look like
+/// `bsr://live/{room_id}/{live_id}?t=01:23:45`, where t is optional and may also be seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeepLink {
+    Live {
+        room_id: u64,
+        live_id: u64,
+        offset: Option<f64>,
+    },
+}
+
+/// Accepts HH:MM:SS, MM:SS or plain seconds
+fn parse_time(time: &str) -> Option<f64> {
+    let mut secs = 0.0;
+    for part in time.split(':') {
+        secs = secs * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(secs)
+}
+
+fn format_time(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl DeepLink {
+    pub fn parse(link: &str) -> Result<DeepLink, String> {
+        let url = Url::parse(link).map_err(|e| e.to_string())?;
+        if url.scheme() != "bsr" {
+            return Err(format!("Unsupported link: {}", link));
+        }
+        let segs: Vec<&str> = url.path_segments().map(|s| s.collect()).unwrap_or_default();
+        match (url.host_str(), segs.as_slice()) {
+            (Some("live"), [room_id, live_id]) => {
+                let offset = url
+                    .query_pairs()
+                    .find(|(k, _)| k == "t")
+                    .and_then(|(_, v)| parse_time(&v));
+                Ok(DeepLink::Live {
+                    room_id: room_id.parse().map_err(|_| "Invalid room id")?,
+                    live_id: live_id.parse().map_err(|_| "Invalid live id")?,
+                    offset,
+                })
+            }
+            _ => Err(format!("Unsupported link: {}", link)),
+        }
+    }
+
+    pub fn to_link(&self) -> String {
+        match self {
+            DeepLink::Live {
+                room_id,
+                live_id,
+                offset,
+            } => match offset {
+                Some(offset) => format!(
+                    "bsr://live/{}/{}?t={}",
+                    room_id,
+                    live_id,
+                    format_time(*offset)
+                ),
+                None => format!("bsr://live/{}/{}", room_id, live_id),
+            },
+        }
+    }
+}
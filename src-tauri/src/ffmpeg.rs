@@ -0,0 +1,953 @@
+use crate::recorder::danmu::export::Chapter;
+use ffmpeg_sidecar::child::FfmpegChild;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
+use ffmpeg_sidecar::ffprobe::ffprobe_path;
+use ffmpeg_sidecar::paths::ffmpeg_path;
+use ffmpeg_sidecar::version::ffmpeg_version_with_path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock, RwLock};
+use tokio::sync::Semaphore;
+
+pub mod hwaccel;
+
+/// Default number of short jobs running at once, like chunks and snapshots of recordings,
+/// set by config. Two keep a couple of rooms going without taking all cores.
+pub const DEFAULT_WORKERS: usize = 2;
+
+/// Default number of long jobs running at once, set by config
+pub const DEFAULT_LONG_WORKERS: usize = 1;
+
+static WORKERS: OnceLock<Semaphore> = OnceLock::new();
+static LIMIT: Mutex<usize> = Mutex::new(DEFAULT_WORKERS);
+static LONG_WORKERS: OnceLock<Semaphore> = OnceLock::new();
+static LONG_LIMIT: Mutex<usize> = Mutex::new(DEFAULT_LONG_WORKERS);
+
+fn workers() -> &'static Semaphore {
+    WORKERS.get_or_init(|| Semaphore::new(DEFAULT_WORKERS))
+}
+
+fn long_workers() -> &'static Semaphore {
+    LONG_WORKERS.get_or_init(|| Semaphore::new(DEFAULT_LONG_WORKERS))
+}
+
+/// Change how many short jobs run at once, see `set_long_workers`
+pub fn set_workers(limit: usize) {
+    resize(workers(), &LIMIT, limit);
+}
+
+/// Change how many long jobs run at once. Running ones keep going when it's lowered, new
+/// ones wait till enough of them finish.
+pub fn set_long_workers(limit: usize) {
+    resize(long_workers(), &LONG_LIMIT, limit);
+}
+
+fn resize(workers: &'static Semaphore, current: &Mutex<usize>, limit: usize) {
+    let limit = limit.max(1);
+    let mut current = current.lock().unwrap();
+    if limit > *current {
+        workers.add_permits(limit - *current);
+    } else if limit < *current {
+        let shrink = (*current - limit) as u32;
+        tauri::async_runtime::spawn(async move {
+            if let Ok(permits) = workers.acquire_many(shrink).await {
+                permits.forget();
+            }
+        });
+    }
+    *current = limit;
+}
+
+/// Binaries set in config, ffmpeg-sidecar looks for them next to the executable
+/// and then in PATH when they are not set
+static FFMPEG_PATH: RwLock<Option<String>> = RwLock::new(None);
+static FFPROBE_PATH: RwLock<Option<String>> = RwLock::new(None);
+
+pub fn set_paths(ffmpeg: Option<String>, ffprobe: Option<String>) {
+    *FFMPEG_PATH.write().unwrap() = ffmpeg.filter(|p| !p.is_empty());
+    *FFPROBE_PATH.write().unwrap() = ffprobe.filter(|p| !p.is_empty());
+    hwaccel::reset();
+}
+
+fn command() -> FfmpegCommand {
+    match FFMPEG_PATH.read().unwrap().as_ref() {
+        Some(path) => FfmpegCommand::new_with_path(path),
+        None => FfmpegCommand::new(),
+    }
+}
+
+fn ffprobe() -> PathBuf {
+    match FFPROBE_PATH.read().unwrap().as_ref() {
+        Some(path) => PathBuf::from(path),
+        None => ffprobe_path(),
+    }
+}
+
+/// Version of the ffmpeg in use, error if it can not be run
+pub fn version() -> Result<String, String> {
+    let path = match FFMPEG_PATH.read().unwrap().as_ref() {
+        Some(path) => PathBuf::from(path),
+        None => ffmpeg_path(),
+    };
+    ffmpeg_version_with_path(path).map_err(|e| e.to_string())
+}
+
+/// Download a static ffmpeg build next to the executable, where it's found when no path is
+/// configured. This blocks, call it from a blocking thread.
+pub fn download() -> Result<(), String> {
+    ffmpeg_sidecar::download::auto_download().map_err(|e| e.to_string())
+}
+
+/// Run an ffmpeg job on a blocking thread once a worker is free, jobs start in fifo order.
+/// Many recordings doing probes, thumbnails and chunks at the same time are kept from
+/// starting dozens of processes together.
+pub async fn execute<T, F>(job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    run_on(workers(), job).await
+}
+
+/// Like `execute`, for jobs going through whole archives or videos like encoding. They have
+/// workers of their own, so chunks and snapshots of recordings don't wait behind them.
+pub async fn execute_long<T, F>(job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    run_on(long_workers(), job).await
+}
+
+async fn run_on<T, F>(workers: &Semaphore, job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    let _permit = workers.acquire().await.map_err(|e| e.to_string())?;
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// EBU R128 target used by loudnorm
+const LOUDNORM_TARGET: &str = "I=-16:TP=-1.5:LRA=11";
+
+/// Run ffmpeg till it exits and return all lines it logged, errors are returned if it fails.
+/// This blocks, call it through `execute` from async code.
+fn run(command: FfmpegCommand) -> Result<Vec<String>, String> {
+    let finished = run_to_exit(command)?;
+    if !finished.success {
+        return Err(finished.errors.join("\n"));
+    }
+    Ok(finished.logs)
+}
+
+/// How an ffmpeg run ended
+struct Finished {
+    success: bool,
+    logs: Vec<String>,
+    errors: Vec<String>,
+}
+
+/// Like `run`, but ffmpeg failing is not an error, only being unable to run it is
+fn run_to_exit(mut command: FfmpegCommand) -> Result<Finished, String> {
+    let child = command.spawn().map_err(|e| e.to_string())?;
+    wait_for(child)
+}
+
+fn wait_for(mut child: FfmpegChild) -> Result<Finished, String> {
+    let mut logs = Vec::new();
+    let mut errors = Vec::new();
+    // stderr has to be drained, or ffmpeg blocks once the pipe is full
+    for event in child.iter().map_err(|e| e.to_string())? {
+        match event {
+            FfmpegEvent::Log(LogLevel::Error, msg) | FfmpegEvent::Error(msg) => errors.push(msg),
+            FfmpegEvent::Log(_, msg) => logs.push(msg),
+            _ => {}
+        }
+    }
+    let status = child.wait().map_err(|e| e.to_string())?;
+    Ok(Finished {
+        success: status.success(),
+        logs,
+        errors,
+    })
+}
+
+/// Transcode input into a small mpegts chunk for monitoring. Chunks are too short to be
+/// measured first, so audio goes through single pass loudnorm.
+pub fn transcode_monitor(
+    input: &str,
+    output: &str,
+    profile: &ArchiveProfile,
+) -> Result<(), String> {
+    let mut command = command();
+    command.input(input);
+    if let Some(scale) = profile.scale_filter() {
+        command.args(["-vf", scale.as_str()]);
+    }
+    command
+        .args(["-af", format!("loudnorm={}", LOUDNORM_TARGET).as_str()])
+        .args(profile.video_args()?)
+        .args(profile.audio_args())
+        .args(["-f", "mpegts"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Two-pass loudness normalization of audio, the first pass measures input so the second
+/// can normalize linearly. Video is copied as is, audio is encoded at `audio_bitrate`.
+pub fn loudnorm(input: &str, output: &str, audio_bitrate: &str) -> Result<(), String> {
+    let mut measure = command();
+    measure
+        .input(input)
+        .args([
+            "-af",
+            format!("loudnorm={}:print_format=json", LOUDNORM_TARGET).as_str(),
+        ])
+        .args(["-vn", "-f", "null"])
+        .output("-");
+    let logs = run(measure)?.join("\n");
+    // measured values are printed as a json block at the end
+    let (Some(begin), Some(end)) = (logs.rfind('{'), logs.rfind('}')) else {
+        return Err("loudnorm measurement not found".into());
+    };
+    let measured: serde_json::Value =
+        serde_json::from_str(&logs[begin..=end]).map_err(|e| e.to_string())?;
+    let value = |key: &str| measured[key].as_str().unwrap_or("0").to_string();
+    let filter = format!(
+        "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        LOUDNORM_TARGET,
+        value("input_i"),
+        value("input_tp"),
+        value("input_lra"),
+        value("input_thresh"),
+        value("target_offset")
+    );
+    let mut normalize = command();
+    normalize
+        .input(input)
+        .args(["-c:v", "copy"])
+        .args(["-af", filter.as_str()])
+        .args(["-c:a", "aac", "-b:a", audio_bitrate])
+        .overwrite()
+        .output(output);
+    run(normalize).map(|_| ())
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum WatermarkContent {
+    /// path of a png or any image ffmpeg can read
+    Image {
+        path: String,
+    },
+    Text {
+        text: String,
+        font_size: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Watermark {
+    pub content: WatermarkContent,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+    /// 0.0 - 1.0
+    pub opacity: f64,
+    /// pixels from the edges
+    #[serde(default)]
+    pub margin: u32,
+}
+
+impl Watermark {
+    pub fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.opacity)
+            && match &self.content {
+                WatermarkContent::Image { path } => std::path::Path::new(path).is_file(),
+                WatermarkContent::Text { text, font_size } => !text.is_empty() && *font_size > 0,
+            }
+    }
+
+    /// Overlay position expressions, `main` and `mark` are size prefixes used by the filter
+    fn xy(&self, main: &str, mark: &str) -> String {
+        let m = self.margin;
+        let x = match self.position {
+            WatermarkPosition::TopLeft | WatermarkPosition::BottomLeft => m.to_string(),
+            _ => format!("{main}w-{mark}w-{m}"),
+        };
+        let y = match self.position {
+            WatermarkPosition::TopLeft | WatermarkPosition::TopRight => m.to_string(),
+            _ => format!("{main}h-{mark}h-{m}"),
+        };
+        format!("x={}:y={}", x, y)
+    }
+}
+
+/// Quote text for drawtext filter option, quotes inside are closed, escaped and reopened
+fn quote_drawtext(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Burn watermark into video, this re-encodes video with profile and copies audio
+pub fn watermark(
+    input: &str,
+    output: &str,
+    watermark: &Watermark,
+    profile: &ArchiveProfile,
+) -> Result<(), String> {
+    let scale = profile
+        .scale_filter()
+        .map(|s| format!(",{}", s))
+        .unwrap_or_default();
+    let mut command = command();
+    command.input(input);
+    match &watermark.content {
+        WatermarkContent::Image { path } => {
+            let filter = format!(
+                "[1:v]format=rgba,colorchannelmixer=aa={}[wm];[0:v][wm]overlay={}{}",
+                watermark.opacity,
+                watermark.xy("main_", "overlay_"),
+                scale
+            );
+            command
+                .input(path)
+                .args(["-filter_complex", filter.as_str()]);
+        }
+        WatermarkContent::Text { text, font_size } => {
+            let filter = format!(
+                "drawtext=expansion=none:text={}:fontsize={}:fontcolor=white@{}:{}{}",
+                quote_drawtext(text),
+                font_size,
+                watermark.opacity,
+                watermark.xy("", "text_"),
+                scale
+            );
+            command.args(["-vf", filter.as_str()]);
+        }
+    }
+    command
+        .args(profile.video_args()?)
+        .args(profile.mp4_args())
+        .args(["-c:a", "copy"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Container of clips, clips are generated as mp4 and remuxed if another one is chosen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    #[default]
+    Mp4,
+    Mkv,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv",
+        }
+    }
+}
+
+/// Copy all streams, chapters and metadata into another container, decided by output extension
+pub fn remux(input: &str, output: &str) -> Result<(), String> {
+    let mut command = command();
+    command
+        .input(input)
+        .args(["-map", "0", "-c", "copy"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Escape value for ffmetadata file
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Write chapters into container metadata, streams are copied.
+/// Each chapter ends where the next begins and the last one ends at `length`.
+pub fn embed_chapters(
+    input: &str,
+    output: &str,
+    chapters: &[Chapter],
+    length: f64,
+) -> Result<(), String> {
+    let mut chapters = chapters.to_vec();
+    chapters.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    let mut metadata = ";FFMETADATA1\n".to_string();
+    for (i, c) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map(|n| n.offset).unwrap_or(length);
+        if end <= c.offset {
+            continue;
+        }
+        metadata += &format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (c.offset.max(0.0) * 1000.0) as u64,
+            (end * 1000.0) as u64,
+            escape_metadata(&c.title)
+        );
+    }
+    let metadata_file = format!("{}.ffmetadata", output);
+    std::fs::write(&metadata_file, metadata).map_err(|e| e.to_string())?;
+    let mut command = command();
+    command
+        .input(input)
+        .input(&metadata_file)
+        .args(["-map", "0", "-map_metadata", "1", "-map_chapters", "1"])
+        .args(["-c", "copy"])
+        .overwrite()
+        .output(output);
+    let result = run(command).map(|_| ());
+    let _ = std::fs::remove_file(&metadata_file);
+    result
+}
+
+/// First frame of input as a small labeled thumbnail
+pub fn thumbnail(input: &str, output: &str, label: &str) -> Result<(), String> {
+    let filter = format!(
+        "scale=320:-2,drawtext=expansion=none:text={}:fontsize=20:fontcolor=white:box=1:boxcolor=black@0.5:x=4:y=4",
+        quote_drawtext(label)
+    );
+    let mut command = command();
+    command
+        .input(input)
+        .args(["-vf", filter.as_str()])
+        .args(["-frames:v", "1"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// First frame of input as a jpg no wider than 1280, input can also be an image or data url
+pub fn snapshot(input: &str, output: &str) -> Result<(), String> {
+    let mut command = command();
+    command
+        .input(input)
+        .args(["-vf", "scale='min(1280,iw)':-2"])
+        .args(["-frames:v", "1"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Tile images matching pattern, like `thumb-%d.png`, into a cols x rows sheet
+pub fn tile(pattern: &str, output: &str, cols: u32, rows: u32) -> Result<(), String> {
+    let mut command = command();
+    command
+        .input(pattern)
+        .args(["-vf", format!("tile={}x{}", cols, rows).as_str()])
+        .args(["-frames:v", "1"])
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Decode input completely, false if ffmpeg stopped at a broken packet.
+/// Errors are only for ffmpeg not being able to run.
+pub fn verify(input: &str) -> Result<bool, String> {
+    let mut command = command();
+    command
+        .args(["-xerror"])
+        .input(input)
+        .args(["-f", "null"])
+        .output("-");
+    Ok(run_to_exit(command)?.success)
+}
+
+/// Whether video stays frozen for at least `duration` seconds
+pub fn is_static(input: &str, duration: f64) -> Result<bool, String> {
+    let mut command = command();
+    command
+        .input(input)
+        .args([
+            "-vf",
+            format!("freezedetect=n=0.003:d={:.1}", duration).as_str(),
+        ])
+        .args(["-an", "-f", "null"])
+        .output("-");
+    let logs = run(command)?;
+    Ok(logs.iter().any(|l| l.contains("freeze_start")))
+}
+
+/// Slates inserted for ads or technical difficulties hold a static frame over silence,
+/// both have to last `duration` seconds
+pub fn is_slate(input: &str, duration: f64) -> Result<bool, String> {
+    let mut command = command();
+    command
+        .input(input)
+        .args([
+            "-vf",
+            format!("freezedetect=n=0.003:d={:.1}", duration).as_str(),
+        ])
+        .args([
+            "-af",
+            format!("silencedetect=n=-50dB:d={:.1}", duration).as_str(),
+        ])
+        .args(["-f", "null"])
+        .output("-");
+    let logs = run(command)?;
+    Ok(logs.iter().any(|l| l.contains("freeze_start"))
+        && logs.iter().any(|l| l.contains("silence_start")))
+}
+
+/// Timestamps in seconds where picture changes more than `threshold` (0 to 1) from the
+/// previous frame. Files are joined in order, like header and segments of an archive, and
+/// piped into ffmpeg so they never have to be copied into one.
+pub fn detect_scenes(files: Vec<String>, threshold: f64) -> Result<Vec<f64>, String> {
+    let mut command = command();
+    command
+        .input("pipe:0")
+        .args([
+            "-vf",
+            format!("select='gt(scene,{:.2})',showinfo", threshold).as_str(),
+        ])
+        .args(["-an", "-f", "null"])
+        .output("-");
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+    let mut stdin = child
+        .take_stdin()
+        .ok_or_else(|| "stdin of ffmpeg is not piped".to_string())?;
+    // written from another thread, stderr has to be drained meanwhile or both sides block
+    let writer = std::thread::spawn(move || -> std::io::Result<()> {
+        for file in files {
+            std::io::copy(&mut std::fs::File::open(file)?, &mut stdin)?;
+        }
+        Ok(())
+    });
+    let finished = wait_for(child)?;
+    let written = writer
+        .join()
+        .map_err(|_| "writing into ffmpeg panicked".to_string())?;
+    if !finished.success {
+        return Err(finished.errors.join("\n"));
+    }
+    written.map_err(|e| e.to_string())?;
+    let logs = finished.logs;
+    // showinfo logs a line per selected frame like "n: 0 pts: 1234 pts_time:1.234 ..."
+    Ok(logs
+        .iter()
+        .filter_map(|l| l.split("pts_time:").nth(1))
+        .filter_map(|t| t.split_whitespace().next()?.parse::<f64>().ok())
+        .collect())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Mp3,
+    Aac,
+    Flac,
+}
+
+impl AudioFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "m4a",
+            AudioFormat::Flac => "flac",
+        }
+    }
+}
+
+/// Drop video and encode audio into format, aac is encoded at `audio_bitrate`
+pub fn extract_audio(
+    input: &str,
+    output: &str,
+    format: AudioFormat,
+    audio_bitrate: &str,
+) -> Result<(), String> {
+    let codec: &[&str] = match format {
+        AudioFormat::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2"],
+        AudioFormat::Aac => &["-c:a", "aac", "-b:a", audio_bitrate],
+        AudioFormat::Flac => &["-c:a", "flac"],
+    };
+    let mut command = command();
+    command
+        .input(input)
+        .args(["-vn"])
+        .args(codec)
+        .overwrite()
+        .output(output);
+    run(command).map(|_| ())
+}
+
+/// Container and first video stream of a media file
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct MediaInfo {
+    /// ffprobe format names, like `mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: String,
+    pub codec: String,
+    pub width: u64,
+    pub height: u64,
+}
+
+pub fn probe(file: &str) -> Result<MediaInfo, String> {
+    let output = Command::new(ffprobe())
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args([
+            "-show_entries",
+            "format=format_name:stream=codec_name,width,height",
+        ])
+        .args(["-of", "json", file])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    let info: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())?;
+    let stream = &info["streams"][0];
+    Ok(MediaInfo {
+        container: info["format"]["format_name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        codec: stream["codec_name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        width: stream["width"].as_u64().unwrap_or(0),
+        height: stream["height"].as_u64().unwrap_or(0),
+    })
+}
+
+fn encoder_of(codec: &str) -> Result<&'static str, String> {
+    match codec {
+        "h264" => Ok("libx264"),
+        "hevc" => Ok("libx265"),
+        "av1" => Ok("libsvtav1"),
+        _ => Err(format!("Unsupported codec: {}", codec)),
+    }
+}
+
+/// Encoding uploads to fit a size limit, see `two_pass`
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct UploadEncode {
+    /// h264 or hevc
+    pub codec: String,
+    /// videos larger than this many MB are re-encoded to this size before upload
+    pub target_mb: u64,
+}
+
+/// Audio bitrate of two pass encoding, in kbps
+const TWO_PASS_AUDIO_KBPS: u64 = 128;
+
+/// Escape value in a `key=value:key=value` option list like `-x265-params`, Windows paths
+/// have both ':' and '\' in them
+fn escape_option(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+/// Encode video in two passes so output lands close to `target_bytes`, bitrate comes from
+/// duration in seconds. Pass logs are written next to output and removed after.
+pub fn two_pass(
+    input: &str,
+    output: &str,
+    codec: &str,
+    preset: &str,
+    target_bytes: u64,
+    duration: f64,
+) -> Result<(), String> {
+    if duration <= 0.0 {
+        return Err("Video duration is unknown".into());
+    }
+    // a few percent is left for container overhead
+    let total_kbps = (target_bytes as f64 * 8.0 * 0.97 / duration / 1000.0) as u64;
+    if total_kbps <= TWO_PASS_AUDIO_KBPS {
+        return Err(format!("Target size is too small for {:.0}s", duration));
+    }
+    let bitrate = format!("{}k", total_kbps - TWO_PASS_AUDIO_KBPS);
+    let audio_bitrate = format!("{}k", TWO_PASS_AUDIO_KBPS);
+    let passlog = format!("{}.passlog", output);
+    let pass_args = |pass: u32| -> Vec<String> {
+        let mut args: Vec<String> = match codec {
+            "hevc" => vec![
+                "-c:v".into(),
+                "libx265".into(),
+                "-x265-params".into(),
+                format!("pass={}:stats={}", pass, escape_option(&passlog)),
+            ],
+            _ => vec![
+                "-c:v".into(),
+                "libx264".into(),
+                "-pass".into(),
+                pass.to_string(),
+                "-passlogfile".into(),
+                passlog.clone(),
+            ],
+        };
+        if !preset.is_empty() {
+            args.extend(["-preset".into(), preset.into()]);
+        }
+        args
+    };
+    if codec != "h264" && codec != "hevc" {
+        return Err(format!("Unsupported codec: {}", codec));
+    }
+    let mut first = command();
+    first
+        .input(input)
+        .args(pass_args(1))
+        .args(["-b:v", bitrate.as_str(), "-an", "-f", "null"])
+        .overwrite()
+        .output("-");
+    let mut result = run(first).map(|_| ());
+    if result.is_ok() {
+        let mut second = command();
+        second
+            .input(input)
+            .args(pass_args(2))
+            .args(["-b:v", bitrate.as_str()])
+            .args(["-c:a", "aac", "-b:a", audio_bitrate.as_str()])
+            .overwrite()
+            .output(output);
+        result = run(second).map(|_| ());
+    }
+    // x264 writes {passlog}-0.log with .mbtree, x265 writes {passlog} with .cutree
+    let log_name = PathBuf::from(&passlog);
+    if let (Some(dir), Some(prefix)) = (log_name.parent(), log_name.file_name()) {
+        let prefix = prefix.to_string_lossy().to_string();
+        if let Ok(files) = std::fs::read_dir(dir) {
+            for file in files.flatten() {
+                if file.file_name().to_string_lossy().starts_with(&prefix) {
+                    let _ = std::fs::remove_file(file.path());
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Re-encode video in profile, scaled down to its max height if it is higher.
+/// Audio is copied, container follows the extension of output.
+pub fn encode(input: &str, output: &str, profile: &ArchiveProfile) -> Result<(), String> {
+    let mut command = command();
+    command.input(input).args(profile.video_args()?);
+    if output.ends_with(".mp4") {
+        command.args(profile.mp4_args());
+    }
+    if let Some(scale) = profile.scale_filter() {
+        command.args(["-vf", scale.as_str()]);
+    }
+    command.args(["-c:a", "copy"]).overwrite().output(output);
+    run(command).map(|_| ())
+}
+
+/// Encoder settings, like 720p hevc at 2M. Config has one for archive compression, one for
+/// steps re-encoding clips and one for the monitor stream.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ArchiveProfile {
+    /// h264, hevc or av1
+    pub codec: String,
+    pub max_height: Option<u64>,
+    /// video bitrate passed to ffmpeg, like "2M" or "1500k", constant quality `crf` if empty
+    #[serde(default)]
+    pub bitrate: String,
+    /// constant quality when there is no bitrate, lower is better
+    #[serde(default = "default_crf")]
+    pub crf: u32,
+    /// cap of video bitrate, like "4M", keeps constant quality from spiking on busy scenes.
+    /// Not capped if empty.
+    #[serde(default)]
+    pub max_bitrate: String,
+    /// speed preset of encoder, like veryfast or slow for x264 and x265, 0 to 13 for av1
+    #[serde(default = "default_preset")]
+    pub preset: String,
+    /// aac bitrate, like "128k"
+    #[serde(default = "default_audio_bitrate")]
+    pub audio_bitrate: String,
+    /// use a hardware encoder of codec if one is found, see `hwaccel`
+    #[serde(default)]
+    pub hwaccel: bool,
+}
+
+fn default_crf() -> u32 {
+    23
+}
+
+fn default_preset() -> String {
+    "medium".into()
+}
+
+fn default_audio_bitrate() -> String {
+    "128k".into()
+}
+
+impl Default for ArchiveProfile {
+    fn default() -> Self {
+        ArchiveProfile {
+            codec: "hevc".into(),
+            max_height: Some(720),
+            bitrate: "2M".into(),
+            crf: default_crf(),
+            preset: default_preset(),
+            audio_bitrate: default_audio_bitrate(),
+            max_bitrate: String::new(),
+            hwaccel: false,
+        }
+    }
+}
+
+impl ArchiveProfile {
+    /// Default of clip steps that re-encode, like watermark
+    pub fn clip() -> Self {
+        ArchiveProfile {
+            codec: "h264".into(),
+            max_height: None,
+            bitrate: String::new(),
+            crf: 20,
+            preset: "veryfast".into(),
+            audio_bitrate: "192k".into(),
+            max_bitrate: String::new(),
+            hwaccel: false,
+        }
+    }
+
+    /// Default of monitor stream, small enough for mobile data
+    pub fn monitor() -> Self {
+        ArchiveProfile {
+            codec: "h264".into(),
+            max_height: Some(360),
+            bitrate: "300k".into(),
+            crf: default_crf(),
+            preset: "veryfast".into(),
+            audio_bitrate: "48k".into(),
+            max_bitrate: String::new(),
+            hwaccel: false,
+        }
+    }
+
+    /// Encoder, rate control and preset of video, scaling is in `scale_filter`
+    fn video_args(&self) -> Result<Vec<String>, String> {
+        let mut args = self.encoder_args()?;
+        if !self.max_bitrate.is_empty() {
+            // a buffer of one second at the cap
+            args.extend([
+                "-maxrate".to_string(),
+                self.max_bitrate.clone(),
+                "-bufsize".to_string(),
+                self.max_bitrate.clone(),
+            ]);
+        }
+        Ok(args)
+    }
+
+    fn encoder_args(&self) -> Result<Vec<String>, String> {
+        let encoder = encoder_of(&self.codec)?;
+        if let Some(args) = self.hardware_args() {
+            return Ok(args);
+        }
+        let mut args = vec!["-c:v".to_string(), encoder.to_string()];
+        if self.bitrate.is_empty() {
+            args.extend(["-crf".to_string(), self.crf.to_string()]);
+        } else {
+            args.extend(["-b:v".to_string(), self.bitrate.clone()]);
+        }
+        // svt-av1 only takes numbers, named presets of x264 are skipped for it
+        if !self.preset.is_empty() && (self.codec != "av1" || self.preset.parse::<u8>().is_ok()) {
+            args.extend(["-preset".to_string(), self.preset.clone()]);
+        }
+        Ok(args)
+    }
+
+    /// Args of hardware encoder if it's enabled and found. Presets differ between vendors,
+    /// so they use their default one. Encoders without constant quality need a bitrate,
+    /// x264 and the like are used without it.
+    fn hardware_args(&self) -> Option<Vec<String>> {
+        if !self.hwaccel {
+            return None;
+        }
+        let encoder = hwaccel::encoder(&self.codec)?;
+        let rate = if self.bitrate.is_empty() {
+            hwaccel::quality_args(encoder, self.crf)?
+        } else {
+            vec!["-b:v".to_string(), self.bitrate.clone()]
+        };
+        let mut args = vec!["-c:v".to_string(), encoder.to_string()];
+        args.extend(rate);
+        Some(args)
+    }
+
+    /// Tag of video in mp4 outputs
+    fn mp4_args(&self) -> &'static [&'static str] {
+        if self.codec == "hevc" {
+            // players of apple need hvc1 tag for hevc in mp4
+            &["-tag:v", "hvc1"]
+        } else {
+            &[]
+        }
+    }
+
+    fn audio_args(&self) -> [&str; 4] {
+        ["-c:a", "aac", "-b:a", self.audio_bitrate.as_str()]
+    }
+
+    fn scale_filter(&self) -> Option<String> {
+        self.max_height
+            .map(|max_height| format!("scale=-2:'min({},ih)'", max_height))
+    }
+}
+
+/// Re-encode input into fmp4 HLS in `dir`, as `init.m4s`, `seg-N.m4s` and `index.m3u8`.
+/// A keyframe is forced every `segment_secs`, so segments are as long as the archive ones.
+pub fn encode_hls(
+    input: &str,
+    dir: &str,
+    profile: &ArchiveProfile,
+    segment_secs: u64,
+) -> Result<(), String> {
+    let mut command = command();
+    command
+        .input(input)
+        .args(profile.video_args()?)
+        .args(profile.mp4_args());
+    if let Some(scale) = profile.scale_filter() {
+        command.args(["-vf", scale.as_str()]);
+    }
+    command
+        .args(profile.audio_args())
+        .args([
+            "-force_key_frames",
+            format!("expr:gte(t,n_forced*{})", segment_secs).as_str(),
+        ])
+        .args(["-f", "hls", "-hls_playlist_type", "vod"])
+        .args(["-hls_time", segment_secs.to_string().as_str()])
+        .args([
+            "-hls_segment_type",
+            "fmp4",
+            "-hls_fmp4_init_filename",
+            "init.m4s",
+        ])
+        .args([
+            "-hls_segment_filename",
+            format!("{}/seg-%d.m4s", dir).as_str(),
+        ])
+        .overwrite()
+        .output(format!("{}/index.m3u8", dir));
+    run(command).map(|_| ())
+}
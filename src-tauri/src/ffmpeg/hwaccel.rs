@@ -0,0 +1,77 @@
+use super::{command, run_to_exit};
+use std::sync::RwLock;
+
+/// Hardware encoders of each codec, the first usable one is picked
+const CANDIDATES: [(&str, &[&str]); 3] = [
+    (
+        "h264",
+        &["h264_nvenc", "h264_qsv", "h264_videotoolbox", "h264_amf"],
+    ),
+    (
+        "hevc",
+        &["hevc_nvenc", "hevc_qsv", "hevc_videotoolbox", "hevc_amf"],
+    ),
+    ("av1", &["av1_nvenc", "av1_qsv", "av1_amf"]),
+];
+
+/// Detected once per ffmpeg binary, cleared when its path changes
+static AVAILABLE: RwLock<Option<Vec<&'static str>>> = RwLock::new(None);
+
+pub(super) fn reset() {
+    *AVAILABLE.write().unwrap() = None;
+}
+
+/// Hardware encoders usable on this machine. Builds list encoders whose device or driver is
+/// missing, so each one is tried on a single frame. This blocks, call it through `execute`.
+pub fn available() -> Vec<&'static str> {
+    if let Some(available) = AVAILABLE.read().unwrap().as_ref() {
+        return available.clone();
+    }
+    let available: Vec<_> = CANDIDATES
+        .iter()
+        .flat_map(|(_, encoders)| encoders.iter().copied())
+        .filter(|encoder| works(encoder))
+        .collect();
+    *AVAILABLE.write().unwrap() = Some(available.clone());
+    available
+}
+
+/// First usable hardware encoder of codec
+pub fn encoder(codec: &str) -> Option<&'static str> {
+    let available = available();
+    CANDIDATES
+        .iter()
+        .find(|(c, _)| *c == codec)
+        .and_then(|(_, encoders)| encoders.iter().copied().find(|e| available.contains(e)))
+}
+
+fn works(encoder: &str) -> bool {
+    let mut command = command();
+    command
+        .args(["-f", "lavfi", "-i", "color=black:s=256x256:d=1"])
+        .args(["-frames:v", "1", "-c:v", encoder, "-f", "null"])
+        .output("-");
+    run_to_exit(command).map_or(false, |finished| finished.success)
+}
+
+/// Constant quality args of hardware encoder at about the same level as x264 crf, none if
+/// the encoder only runs at a bitrate
+pub(super) fn quality_args(encoder: &str, crf: u32) -> Option<Vec<String>> {
+    let crf = crf.to_string();
+    if encoder.ends_with("_nvenc") {
+        Some(vec!["-rc".into(), "vbr".into(), "-cq".into(), crf])
+    } else if encoder.ends_with("_qsv") {
+        Some(vec!["-global_quality".into(), crf])
+    } else if encoder.ends_with("_amf") {
+        Some(vec![
+            "-rc".into(),
+            "cqp".into(),
+            "-qp_i".into(),
+            crf.clone(),
+            "-qp_p".into(),
+            crf,
+        ])
+    } else {
+        None
+    }
+}
@@ -0,0 +1,239 @@
+use crate::database::media::MediaInfoRow;
+use crate::database::Database;
+use crate::ffmpeg::{self, ArchiveProfile, MediaInfo};
+use crate::notifier::{self, TaskFinished, TaskKind};
+use crate::Config;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::RwLock;
+
+/// Format videos in library should be in, container is a file extension like mp4 or mkv
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct FormatPolicy {
+    pub codec: String,
+    pub container: String,
+    pub max_height: Option<u64>,
+    /// ids of videos never re-encoded
+    #[serde(default)]
+    pub skip: Vec<i64>,
+}
+
+impl FormatPolicy {
+    pub fn matches(&self, info: &MediaInfo) -> bool {
+        let container = match self.container.as_str() {
+            "mkv" => "matroska",
+            c => c,
+        };
+        info.codec == self.codec
+            && info.container.split(',').any(|f| f == container)
+            && self.max_height.map_or(true, |h| info.height <= h)
+    }
+}
+
+/// Probe a file through the media info cache in db, ffprobe only runs when the file is new
+/// or modified since it was cached
+pub async fn probe(db: &Database, file: &str) -> Result<MediaInfo, String> {
+    let mtime = tokio::fs::metadata(file)
+        .await
+        .and_then(|m| m.modified())
+        .map_err(|e| e.to_string())?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    if let Ok(row) = db.get_media_info(file, mtime).await {
+        return Ok(MediaInfo {
+            container: row.container,
+            codec: row.codec,
+            width: row.width as u64,
+            height: row.height as u64,
+        });
+    }
+    let input = file.to_string();
+    let info = ffmpeg::execute(move || ffmpeg::probe(&input)).await?;
+    let row = MediaInfoRow {
+        path: file.to_string(),
+        mtime,
+        container: info.container.clone(),
+        codec: info.codec.clone(),
+        width: info.width as i64,
+        height: info.height as i64,
+    };
+    if let Err(e) = db.set_media_info(&row).await {
+        log::warn!("Cache media info of {} failed: {}", file, e);
+    }
+    Ok(info)
+}
+
+/// Remove cached media info of files that are gone, like videos deleted outside the app or
+/// headers of removed archives
+pub async fn prune_media_cache(db: &Database) {
+    let paths = match db.get_media_info_paths().await {
+        Ok(paths) => paths,
+        Err(e) => {
+            log::error!("Get media info cache failed: {}", e);
+            return;
+        }
+    };
+    let mut pruned = 0;
+    for path in paths {
+        if tokio::fs::metadata(&path).await.is_err() && db.remove_media_info(&path).await.is_ok() {
+            pruned += 1;
+        }
+    }
+    if pruned > 0 {
+        log::info!("Pruned media info of {} missing files", pruned);
+    }
+}
+
+/// Probe every video in output dir once, so listing and previews find them in cache.
+/// Rows of missing files are pruned first.
+pub async fn warm_up_media_cache(db: Arc<Database>, output: String) {
+    prune_media_cache(&db).await;
+    let videos = match db.get_all_videos().await {
+        Ok(videos) => videos,
+        Err(e) => {
+            log::error!("Get videos for media cache failed: {}", e);
+            return;
+        }
+    };
+    let mut probed = 0;
+    for video in videos {
+        let file = format!("{}/{}", output, video.file);
+        match probe(&db, &file).await {
+            Ok(_) => probed += 1,
+            Err(e) => log::warn!("Probe video {} failed: {}", video.id, e),
+        }
+    }
+    log::info!("Media info cache warmed up, {} videos", probed);
+}
+
+/// Emitted as `library:reencode` after each video
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct ReencodeProgress {
+    pub total: usize,
+    pub done: usize,
+    pub current: Option<i64>,
+    pub failed: Vec<i64>,
+}
+
+/// Re-encode every video in output dir not matching policy. Runs until all videos are checked
+/// or `running` is cleared, which stops after the current video.
+pub async fn reencode_library(
+    app_handle: AppHandle,
+    db: Arc<Database>,
+    config: Arc<RwLock<Config>>,
+    output: String,
+    policy: FormatPolicy,
+    running: Arc<AtomicBool>,
+) {
+    let videos = match db.get_all_videos().await {
+        Ok(videos) => videos,
+        Err(e) => {
+            log::error!("Get videos for re-encoding failed: {}", e);
+            running.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    let videos: Vec<_> = videos
+        .into_iter()
+        .filter(|v| !policy.skip.contains(&v.id))
+        .collect();
+    // rate control and hwaccel follow clip encoder, codec and height the policy
+    let profile = ArchiveProfile {
+        codec: policy.codec.clone(),
+        max_height: policy.max_height,
+        ..config.read().await.clip_profile.clone()
+    };
+    let mut progress = ReencodeProgress {
+        total: videos.len(),
+        ..Default::default()
+    };
+    for video in videos {
+        if !running.load(Ordering::SeqCst) {
+            log::info!("Library re-encoding cancelled");
+            break;
+        }
+        progress.current = Some(video.id);
+        let _ = app_handle.emit("library:reencode", progress.clone());
+        let file = format!("{}/{}", output, video.file);
+        // videos may be in sub dirs of output, re-encoded ones stay next to them
+        let new_file = Path::new(&video.file)
+            .with_extension(&policy.container)
+            .to_string_lossy()
+            .to_string();
+        let temp = format!(
+            "{}/{}",
+            output,
+            Path::new(&video.file)
+                .with_extension(format!("reencode.{}", policy.container))
+                .to_string_lossy()
+        );
+        let result = match probe(&db, &file).await {
+            Ok(info) if policy.matches(&info) => Ok(false),
+            Ok(_) => {
+                let (p, input, temp_clone) = (profile.clone(), file.clone(), temp.clone());
+                ffmpeg::execute_long(move || ffmpeg::encode(&input, &temp_clone, &p).map(|_| true))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(true) => {
+                let target = format!("{}/{}", output, new_file);
+                if let Err(e) = tokio::fs::rename(&temp, &target).await {
+                    log::error!("Replace video {} failed: {}", video.id, e);
+                    let _ = tokio::fs::remove_file(&temp).await;
+                    progress.failed.push(video.id);
+                } else {
+                    let size = tokio::fs::metadata(&target)
+                        .await
+                        .map(|m| m.len() as i64)
+                        .unwrap_or(video.size);
+                    // old file is only removed once db points to the new one
+                    if let Err(e) = db.update_video_file(video.id, &new_file, size).await {
+                        log::error!("Update video {} failed: {}", video.id, e);
+                        if target != file {
+                            let _ = tokio::fs::remove_file(&target).await;
+                        }
+                        progress.failed.push(video.id);
+                    } else {
+                        if target != file {
+                            let _ = tokio::fs::remove_file(&file).await;
+                            let _ = db.remove_media_info(&file).await;
+                        }
+                        log::info!("Video {} re-encoded into {}", video.id, new_file);
+                    }
+                }
+            }
+            Ok(false) => {}
+            Err(e) => {
+                log::error!("Re-encode video {} failed: {}", video.id, e);
+                let _ = tokio::fs::remove_file(&temp).await;
+                progress.failed.push(video.id);
+            }
+        }
+        progress.done += 1;
+    }
+    progress.current = None;
+    let _ = app_handle.emit("library:reencode", progress.clone());
+    running.store(false, Ordering::SeqCst);
+    notifier::notify_task(
+        &app_handle,
+        &*config.read().await,
+        TaskFinished {
+            kind: TaskKind::Reencode,
+            room_id: None,
+            ok: progress.failed.is_empty(),
+            message: format!(
+                "重新编码完成 {}/{}，失败 {} 个",
+                progress.done,
+                progress.total,
+                progress.failed.len()
+            ),
+            target: None,
+        },
+        "BiliShadowReplay - 重新编码完成",
+    );
+}
@@ -0,0 +1,116 @@
+/// Language of text generated by backend, like messages in notification center
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Zh,
+    En,
+}
+
+/// Messages are stored as key and params, and rendered in configured locale when read
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKey {
+    RecorderAdded,
+    RecorderRemoved,
+    CacheMoving,
+    CacheMoved,
+    ClipCreated,
+    VideoUploaded,
+    ArchivesMerged,
+    ArchiveDeleted,
+    ConfigRecovered,
+    PipelineBehind,
+    RecordingResumed,
+}
+
+impl MessageKey {
+    /// Title and content templates, `{0}`, `{1}`... are replaced by params
+    fn templates(&self, locale: Locale) -> (&'static str, &'static str) {
+        match (self, locale) {
+            (MessageKey::RecorderAdded, Locale::Zh) => ("添加直播间", "添加了新直播间 {0}"),
+            (MessageKey::RecorderAdded, Locale::En) => ("Room added", "Added room {0}"),
+            (MessageKey::RecorderRemoved, Locale::Zh) => ("移除直播间", "移除了直播间 {0}"),
+            (MessageKey::RecorderRemoved, Locale::En) => ("Room removed", "Removed room {0}"),
+            (MessageKey::CacheMoving, Locale::Zh) => (
+                "缓存目录切换",
+                "缓存正在迁移中，根据数据量情况可能花费较长时间，在此期间流预览功能不可用",
+            ),
+            (MessageKey::CacheMoving, Locale::En) => (
+                "Cache folder changed",
+                "Cache is being moved, this may take a while depending on its size. Stream preview is unavailable meanwhile",
+            ),
+            (MessageKey::CacheMoved, Locale::Zh) => ("缓存目录切换", "缓存切换完成"),
+            (MessageKey::CacheMoved, Locale::En) => ("Cache folder changed", "Cache moved"),
+            (MessageKey::ClipCreated, Locale::Zh) => {
+                ("生成新切片", "生成了房间 {0} 的切片，长度 {1}s：{2}")
+            }
+            (MessageKey::ClipCreated, Locale::En) => {
+                ("Clip created", "Created a {1}s clip of room {0}: {2}")
+            }
+            (MessageKey::VideoUploaded, Locale::Zh) => ("投稿成功", "投稿了房间 {0} 的切片：{1}"),
+            (MessageKey::VideoUploaded, Locale::En) => {
+                ("Video uploaded", "Uploaded clip of room {0}: {1}")
+            }
+            (MessageKey::ArchivesMerged, Locale::Zh) => {
+                ("合并历史缓存", "合并了房间 {0} 的历史缓存 {1}")
+            }
+            (MessageKey::ArchivesMerged, Locale::En) => {
+                ("Archives merged", "Merged archives {1} of room {0}")
+            }
+            (MessageKey::ArchiveDeleted, Locale::Zh) => {
+                ("删除历史缓存", "删除了房间 {0} 的历史缓存 {1}")
+            }
+            (MessageKey::ArchiveDeleted, Locale::En) => {
+                ("Archive deleted", "Deleted archive {1} of room {0}")
+            }
+            (MessageKey::ConfigRecovered, Locale::Zh) => ("配置恢复", "配置文件损坏，已从备份恢复"),
+            (MessageKey::ConfigRecovered, Locale::En) => (
+                "Config recovered",
+                "Config file was broken and has been restored from backup",
+            ),
+            (MessageKey::PipelineBehind, Locale::Zh) => (
+                "录制积压",
+                "直播间 {0} 的 {1} 持续积压（当前 {2}），录制可能出现缺失",
+            ),
+            (MessageKey::PipelineBehind, Locale::En) => (
+                "Recording falling behind",
+                "{1} of room {0} stays high (now {2}), recording may get gaps",
+            ),
+            (MessageKey::RecordingResumed, Locale::Zh) => (
+                "录制已恢复",
+                "直播间 {0} 的录制 {1} 曾中断，已从 {2} 个片段处继续录制",
+            ),
+            (MessageKey::RecordingResumed, Locale::En) => (
+                "Recording resumed",
+                "Recording {1} of room {0} was interrupted, continued after {2} segments",
+            ),
+        }
+    }
+
+    /// Placeholders are replaced in one pass, so a param containing `{1}` is kept as is
+    pub fn render(&self, locale: Locale, params: &[String]) -> (String, String) {
+        let (title, template) = self.templates(locale);
+        let mut content = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            content += &rest[..start];
+            let param = rest[start + 1..].find('}').and_then(|end| {
+                let index = rest[start + 1..start + 1 + end].parse::<usize>().ok()?;
+                Some((params.get(index)?, start + end + 2))
+            });
+            match param {
+                Some((param, next)) => {
+                    content += param;
+                    rest = &rest[next..];
+                }
+                None => {
+                    content.push('{');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        content += rest;
+        (title.to_string(), content)
+    }
+}
@@ -2,30 +2,59 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod database;
+mod deeplink;
+mod ffmpeg;
+mod library;
+mod locale;
+mod nfo;
+mod notifier;
+mod offload;
 mod recorder;
 mod recorder_manager;
+mod torrent;
 mod tray;
 
 use chrono::Utc;
 use custom_error::custom_error;
 use database::account::AccountRow;
+use database::cdn::CdnSpeedRow;
+use database::interaction::{GiftSummaryRow, InteractionRow, RevenueStatRow};
+use database::interruption::InterruptionRow;
+use database::keyword::KeywordRuleRow;
+use database::marker::MarkerRow;
 use database::message::MessageRow;
+use database::metrics::RoomMetricRow;
 use database::record::RecordRow;
 use database::recorder::RecorderRow;
+use database::statistics::DanmuStatisticRow;
 use database::video::VideoRow;
 use database::Database;
+use deeplink::DeepLink;
+use ffmpeg::{ArchiveProfile, AudioFormat, Container, UploadEncode, Watermark};
+use library::FormatPolicy;
+use locale::{Locale, MessageKey};
+use notifier::{QuietHours, TaskFinished, TaskKind, TaskNotify};
+use offload::S3Config;
+use recorder::backoff::BackoffPolicy;
 use recorder::bilibili::errors::BiliClientError;
 use recorder::bilibili::profile::Profile;
-use recorder::bilibili::{BiliClient, QrInfo, QrStatus};
-use recorder::danmu::DanmuEntry;
+use recorder::bilibili::{BiliClient, PlatformCapabilities, QrInfo, QrStatus, StreamCodec};
+use recorder::danmu::export::{Chapter, ExportDanmuOptions};
+use recorder::danmu::heatmap::{HeatmapBucket, Highlight};
+use recorder::danmu::translate::TranslationConfig;
+use recorder::danmu::{DanmuEntry, DanmuSyncPolicy};
+use recorder::writer::WritePolicy;
+use recorder::{CoverSource, RecordWindow, RecordingMode, RepairReport, Snapshot, SplitLimit};
 use recorder_manager::{RecorderInfo, RecorderList, RecorderManager};
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tauri::utils::config::WindowEffectsConfig;
 use tauri::{Manager, Theme, WindowEvent};
-use tauri_plugin_notification::NotificationExt;
 use tauri_plugin_sql::{Migration, MigrationKind};
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
@@ -97,16 +126,199 @@ pub struct Config {
     live_end_notify: bool,
     clip_notify: bool,
     post_notify: bool,
+    #[serde(default = "default_danmu_flush_interval")]
+    danmu_flush_interval: u64,
+    #[serde(default)]
+    danmu_sync: DanmuSyncPolicy,
+    /// open live windows on the platform page with a web profile of their own, so account
+    /// cookies of main window are not sent
+    #[serde(default)]
+    live_window_isolated: bool,
+    #[serde(default = "default_keyword_notify")]
+    keyword_notify: bool,
+    #[serde(default)]
+    quiet_hours: Option<QuietHours>,
+    /// quiet hours overriding the global one, keyed by room id
+    #[serde(default)]
+    room_quiet_hours: HashMap<String, QuietHours>,
+    #[serde(default)]
+    translation: Option<TranslationConfig>,
+    /// seconds in which repeated danmu are collapsed in exports, 0 disables it
+    #[serde(default)]
+    danmu_collapse_window: u64,
+    /// normalize loudness of clips (EBU R128), this re-encodes audio
+    #[serde(default)]
+    clip_loudnorm: bool,
+    /// embed markers and danmu peaks into clips and exported archives as chapters
+    #[serde(default)]
+    clip_chapters: bool,
+    /// container of clips and exported archives, they are remuxed from mp4 into it
+    #[serde(default)]
+    clip_container: Container,
+    /// ffmpeg and ffprobe binaries, found next to the executable or in PATH if not set
+    #[serde(default)]
+    ffmpeg_path: Option<String>,
+    #[serde(default)]
+    ffprobe_path: Option<String>,
+    /// ffmpeg jobs going through whole archives or videos that run at once
+    #[serde(default = "default_ffmpeg_long_workers")]
+    ffmpeg_long_workers: usize,
+    /// short ffmpeg jobs of recordings that run at once, like monitor chunks and snapshots
+    #[serde(default = "default_ffmpeg_workers")]
+    ffmpeg_workers: usize,
+    /// move finished segments into S3-compatible storage while recording
+    #[serde(default)]
+    offload: Option<S3Config>,
+    /// storages replaced in `offload` or disabled, segments offloaded before are still in them
+    #[serde(default)]
+    offload_retired: Vec<S3Config>,
+    /// public base url of hls server put into torrents as web seed, like https://example.com/hls
+    #[serde(default)]
+    torrent_web_seed: Option<String>,
+    /// two pass encode of clips exceeding a size before upload
+    #[serde(default)]
+    upload_encode: Option<UploadEncode>,
+    /// write nfo sidecars for media servers along with exported archives
+    #[serde(default)]
+    archive_nfo: bool,
+    /// mark ad and technical difficulty slates of lives while recording
+    #[serde(default)]
+    detect_interruptions: bool,
+    /// leave marked interruptions out of clips and packaged archives
+    #[serde(default)]
+    clip_skip_interruptions: bool,
+    /// profile archives are re-encoded into by compress_archive
+    #[serde(default)]
+    archive_profile: ArchiveProfile,
+    /// encoder of clip steps that re-encode, like watermark, loudnorm and audio export
+    #[serde(default = "ArchiveProfile::clip")]
+    clip_profile: ArchiveProfile,
+    /// h264, hevc or av1 clips are re-encoded into with `clip_profile`, codec of stream is
+    /// kept if not set
+    #[serde(default)]
+    clip_codec: Option<String>,
+    /// encoder of the monitor stream
+    #[serde(default = "ArchiveProfile::monitor")]
+    monitor_profile: ArchiveProfile,
+    /// transcode a low bitrate monitor stream while recording
+    #[serde(default)]
+    monitor_stream: bool,
+    /// language of messages generated by backend
+    #[serde(default)]
+    locale: Locale,
+    /// minutes a recording queue stays above threshold before it is warned
+    #[serde(default = "default_backpressure_alert_minutes")]
+    backpressure_alert_minutes: u64,
+    /// seconds between record length and size updates in db while recording
+    #[serde(default = "default_record_update_interval")]
+    record_update_interval: u64,
+    #[serde(default)]
+    recording_mode: RecordingMode,
+    /// minutes kept in rolling mode before record is pressed
+    #[serde(default = "default_rolling_buffer_minutes")]
+    rolling_buffer_minutes: u64,
+    /// notification switches of finished tasks, kinds not listed notify both done and failed
+    #[serde(default)]
+    task_notify: HashMap<TaskKind, TaskNotify>,
+    /// pause segment downloads while room loops static filler without danmu
+    #[serde(default)]
+    filler_pause: bool,
+    /// watermark burned into clips
+    #[serde(default)]
+    watermark: Option<Watermark>,
+    /// watermark overriding the global one, keyed by room id
+    #[serde(default)]
+    room_watermark: HashMap<String, Watermark>,
+    /// retry delays of room status, playlist and segment requests
+    #[serde(default)]
+    backoff: BackoffPolicy,
+    /// video codec preferred for recording, applies from next live
+    #[serde(default)]
+    stream_codec: StreamCodec,
+    /// minutes between frames saved while recording, 0 for none
+    #[serde(default)]
+    snapshot_interval_minutes: u64,
+    /// split long lives into parts by length or size
+    #[serde(default)]
+    split: Option<SplitLimit>,
+    /// split limit overriding the global one, keyed by room id
+    #[serde(default)]
+    room_split: HashMap<String, SplitLimit>,
+    /// proxy for requests of recorders and account apis, like http://127.0.0.1:7890
+    #[serde(default)]
+    proxy: Option<String>,
+    /// proxy overriding the global one, keyed by room id
+    #[serde(default)]
+    room_proxy: HashMap<String, String>,
+    /// rooms recorded at the same time, more live rooms wait by priority; 0 for unlimited
+    #[serde(default)]
+    max_recordings: usize,
+    /// download rate limit of all rooms together in KB/s, 0 for unlimited
+    #[serde(default)]
+    download_limit: u64,
+    /// download rate limit in KB/s keyed by room id, applies along with the global one
+    #[serde(default)]
+    room_download_limit: HashMap<String, u64>,
+    /// how segment writes of recordings are coalesced, for many recordings on one disk
+    #[serde(default)]
+    segment_writes: WritePolicy,
+    /// set when primary config file is broken and backup is loaded instead
+    #[serde(skip)]
+    recovered_from_backup: bool,
+}
+
+fn default_danmu_flush_interval() -> u64 {
+    5
+}
+
+fn default_keyword_notify() -> bool {
+    true
+}
+
+fn default_rolling_buffer_minutes() -> u64 {
+    30
+}
+
+fn default_record_update_interval() -> u64 {
+    10
+}
+
+fn default_backpressure_alert_minutes() -> u64 {
+    3
+}
+
+fn default_ffmpeg_long_workers() -> usize {
+    ffmpeg::DEFAULT_LONG_WORKERS
+}
+
+fn default_ffmpeg_workers() -> usize {
+    ffmpeg::DEFAULT_WORKERS
 }
 
 impl Config {
+    fn read_from(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        match toml::from_str(&content) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                log::error!("Parse config {} failed: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
     pub fn load() -> Self {
         let app_dirs = AppDirs::new(Some("cn.vjoi.bili-shadowreplay"), false).unwrap();
         let config_path = app_dirs.config_dir.join("Conf.toml");
-        if let Ok(content) = std::fs::read_to_string(config_path) {
-            if let Ok(config) = toml::from_str(&content) {
-                return config;
-            }
+        if let Some(config) = Self::read_from(&config_path) {
+            return config;
+        }
+        let backup_path = app_dirs.config_dir.join("Conf.toml.bak");
+        if let Some(mut config) = Self::read_from(&backup_path) {
+            log::warn!("Config file is broken, recovered from backup");
+            config.recovered_from_backup = true;
+            config.save();
+            return config;
         }
         let config = Config {
             webid: "".to_string(),
@@ -128,6 +340,54 @@ impl Config {
             live_end_notify: true,
             clip_notify: true,
             post_notify: true,
+            danmu_flush_interval: default_danmu_flush_interval(),
+            danmu_sync: DanmuSyncPolicy::default(),
+            live_window_isolated: false,
+            keyword_notify: default_keyword_notify(),
+            quiet_hours: None,
+            room_quiet_hours: HashMap::new(),
+            translation: None,
+            danmu_collapse_window: 0,
+            clip_loudnorm: false,
+            clip_chapters: false,
+            clip_container: Container::default(),
+            archive_profile: ArchiveProfile::default(),
+            clip_profile: ArchiveProfile::clip(),
+            clip_codec: None,
+            monitor_profile: ArchiveProfile::monitor(),
+            archive_nfo: false,
+            detect_interruptions: false,
+            clip_skip_interruptions: false,
+            upload_encode: None,
+            torrent_web_seed: None,
+            offload: None,
+            offload_retired: Vec::new(),
+            ffmpeg_path: None,
+            ffprobe_path: None,
+            ffmpeg_long_workers: default_ffmpeg_long_workers(),
+            ffmpeg_workers: default_ffmpeg_workers(),
+            monitor_stream: false,
+            locale: Locale::default(),
+            record_update_interval: default_record_update_interval(),
+            backpressure_alert_minutes: default_backpressure_alert_minutes(),
+            recording_mode: RecordingMode::default(),
+            rolling_buffer_minutes: default_rolling_buffer_minutes(),
+            task_notify: HashMap::new(),
+            filler_pause: false,
+            watermark: None,
+            room_watermark: HashMap::new(),
+            stream_codec: StreamCodec::default(),
+            backoff: BackoffPolicy::default(),
+            snapshot_interval_minutes: 0,
+            split: None,
+            room_split: HashMap::new(),
+            proxy: None,
+            room_proxy: HashMap::new(),
+            max_recordings: 0,
+            download_limit: 0,
+            room_download_limit: HashMap::new(),
+            segment_writes: WritePolicy::default(),
+            recovered_from_backup: false,
         };
         config.save();
         config
@@ -139,7 +399,15 @@ impl Config {
         // Create app dirs if not exists
         std::fs::create_dir_all(&app_dirs.config_dir).unwrap();
         let config_path = app_dirs.config_dir.join("Conf.toml");
-        std::fs::write(config_path, content).unwrap();
+        // write into temp file then rename, so a crash never leaves a truncated config
+        let temp_path = app_dirs.config_dir.join("Conf.toml.tmp");
+        let mut file = File::create(&temp_path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file.sync_all().unwrap();
+        if Self::read_from(&config_path).is_some() {
+            let _ = std::fs::copy(&config_path, app_dirs.config_dir.join("Conf.toml.bak"));
+        }
+        std::fs::rename(temp_path, config_path).unwrap();
     }
 
     pub fn set_cache_path(&mut self, path: &str) {
@@ -158,6 +426,23 @@ impl Config {
         self.save();
     }
 
+    /// Storages offloaded segments may be in, the current one and ones replaced since
+    pub fn offload_storages(&self) -> Vec<S3Config> {
+        self.offload
+            .iter()
+            .chain(self.offload_retired.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Room proxy takes precedence over the global one
+    pub fn proxy_for(&self, room_id: u64) -> Option<String> {
+        self.room_proxy
+            .get(&room_id.to_string())
+            .or(self.proxy.as_ref())
+            .cloned()
+    }
+
     pub fn webid_expired(&self) -> bool {
         let now = chrono::Utc::now().timestamp();
         // expire in 20 hours
@@ -189,6 +474,8 @@ struct State {
     config: Arc<RwLock<Config>>,
     recorder_manager: Arc<RecorderManager>,
     app_handle: tauri::AppHandle,
+    /// set while library re-encoding is running
+    reencoding: Arc<AtomicBool>,
 }
 
 impl State {
@@ -342,7 +629,7 @@ async fn add_recorder(state: tauri::State<'_, State>, room_id: u64) -> Result<Re
             let room = state.db.add_recorder(room_id).await?;
             state
                 .db
-                .new_message("添加直播间", &format!("添加了新直播间 {}", room_id))
+                .new_message(MessageKey::RecorderAdded, &[room_id.to_string()])
                 .await?;
             Ok(room)
         }
@@ -350,13 +637,55 @@ async fn add_recorder(state: tauri::State<'_, State>, room_id: u64) -> Result<Re
     }
 }
 
+/// Recording quality (qn) of a room, takes effect from next live
+#[tauri::command]
+async fn set_recorder_quality(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    quality: u64,
+) -> Result<(), String> {
+    Ok(state
+        .recorder_manager
+        .set_quality(&state.db, room_id, quality)
+        .await?)
+}
+
+/// Rooms with higher priority take free recording slots first
+#[tauri::command]
+async fn set_recorder_priority(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    priority: i64,
+) -> Result<(), String> {
+    Ok(state
+        .recorder_manager
+        .set_priority(&state.db, room_id, priority)
+        .await?)
+}
+
+/// Windows a room is recorded in, status is still checked out of them
+#[tauri::command]
+async fn set_recorder_schedule(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    schedule: Vec<RecordWindow>,
+) -> Result<(), String> {
+    if let Some(window) = schedule.iter().find(|w| !w.is_valid()) {
+        return Err(format!("Invalid schedule: {:?}", window));
+    }
+    Ok(state
+        .recorder_manager
+        .set_schedule(&state.db, room_id, schedule)
+        .await?)
+}
+
 #[tauri::command]
 async fn remove_recorder(state: tauri::State<'_, State>, room_id: u64) -> Result<(), String> {
     match state.recorder_manager.remove_recorder(room_id).await {
         Ok(()) => {
             state
                 .db
-                .new_message("移除直播间", &format!("移除了直播间 {}", room_id))
+                .new_message(MessageKey::RecorderRemoved, &[room_id.to_string()])
                 .await?;
             Ok(state.db.remove_recorder(room_id).await?)
         }
@@ -364,6 +693,138 @@ async fn remove_recorder(state: tauri::State<'_, State>, room_id: u64) -> Result
     }
 }
 
+/// A room in shared recorder list, account data and cookies are never included
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SharedRoom {
+    platform: String,
+    room_id: u64,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    quiet_hours: Option<QuietHours>,
+    #[serde(default)]
+    watermark: Option<Watermark>,
+    #[serde(default)]
+    keyword_rules: Vec<SharedKeywordRule>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct SharedKeywordRule {
+    pattern: String,
+    is_regex: bool,
+}
+
+#[derive(serde::Serialize)]
+struct ImportResult {
+    added: Vec<u64>,
+    skipped: Vec<u64>,
+    failed: Vec<u64>,
+    /// rooms added without some of their keyword rules
+    incomplete: Vec<u64>,
+}
+
+/// Portable json list of all rooms with their settings
+#[tauri::command]
+async fn export_recorders(state: tauri::State<'_, State>) -> Result<String, String> {
+    let config = state.config.read().await.clone();
+    let mut rooms = Vec::new();
+    for recorder in state.db.get_recorders().await? {
+        let room_id = recorder.room_id;
+        let name = state
+            .recorder_manager
+            .get_recorder_info(room_id)
+            .await
+            .map(|info| info.user_info.user_name)
+            .unwrap_or_default();
+        let keyword_rules = state
+            .db
+            .get_keyword_rules(room_id)
+            .await?
+            .into_iter()
+            .map(|r| SharedKeywordRule {
+                pattern: r.pattern,
+                is_regex: r.is_regex,
+            })
+            .collect();
+        rooms.push(SharedRoom {
+            platform: "bilibili".into(),
+            room_id,
+            name,
+            quiet_hours: config.room_quiet_hours.get(&room_id.to_string()).cloned(),
+            watermark: config.room_watermark.get(&room_id.to_string()).cloned(),
+            keyword_rules,
+        });
+    }
+    serde_json::to_string_pretty(&rooms).map_err(|e| e.to_string())
+}
+
+/// Add rooms from a list made by `export_recorders`, rooms already added are skipped
+#[tauri::command]
+async fn import_recorders(
+    state: tauri::State<'_, State>,
+    json: String,
+) -> Result<ImportResult, String> {
+    let rooms: Vec<SharedRoom> = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+    let existing: Vec<u64> = state
+        .db
+        .get_recorders()
+        .await?
+        .into_iter()
+        .map(|r| r.room_id)
+        .collect();
+    let mut result = ImportResult {
+        added: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+        incomplete: Vec::new(),
+    };
+    for room in rooms {
+        if room.platform != "bilibili" || existing.contains(&room.room_id) {
+            result.skipped.push(room.room_id);
+            continue;
+        }
+        if let Err(e) = add_recorder(state.clone(), room.room_id).await {
+            log::error!("Import room {} failed: {}", room.room_id, e);
+            result.failed.push(room.room_id);
+            continue;
+        }
+        {
+            let mut config = state.config.write().await;
+            let key = room.room_id.to_string();
+            if let Some(quiet_hours) = room.quiet_hours.filter(|q| q.is_valid()) {
+                config.room_quiet_hours.insert(key.clone(), quiet_hours);
+            }
+            if let Some(watermark) = room.watermark.filter(|w| w.is_valid()) {
+                config.room_watermark.insert(key, watermark);
+            }
+            config.save();
+        }
+        for rule in room.keyword_rules {
+            if let Err(e) = state
+                .db
+                .add_keyword_rule(room.room_id, &rule.pattern, rule.is_regex)
+                .await
+            {
+                log::error!(
+                    "Import keyword rule {} of room {} failed: {}",
+                    rule.pattern,
+                    room.room_id,
+                    e
+                );
+                if !result.incomplete.contains(&room.room_id) {
+                    result.incomplete.push(room.room_id);
+                }
+            }
+        }
+        let _ = state
+            .recorder_manager
+            .reload_keyword_rules(room.room_id)
+            .await;
+        result.added.push(room.room_id);
+    }
+    Ok(result)
+}
+
 #[tauri::command]
 async fn get_config(state: tauri::State<'_, State>) -> Result<Config, ()> {
     Ok(state.config.read().await.clone())
@@ -379,18 +840,12 @@ async fn set_cache_path(state: tauri::State<'_, State>, cache_path: String) -> R
     std::thread::sleep(std::time::Duration::from_secs(2));
     // Copy old cache to new cache
     log::info!("Start copy old cache to new cache");
-    state
-        .db
-        .new_message(
-            "缓存目录切换",
-            "缓存正在迁移中，根据数据量情况可能花费较长时间，在此期间流预览功能不可用",
-        )
-        .await?;
+    state.db.new_message(MessageKey::CacheMoving, &[]).await?;
     if let Err(e) = copy_dir_all(&old_cache_path, &cache_path) {
         log::error!("Copy old cache to new cache error: {}", e);
     }
     log::info!("Copy old cache to new cache done");
-    state.db.new_message("缓存目录切换", "缓存切换完成").await?;
+    state.db.new_message(MessageKey::CacheMoved, &[]).await?;
     // Remove old cache
     if old_cache_path != cache_path {
         if let Err(e) = std::fs::remove_dir_all(old_cache_path) {
@@ -416,6 +871,487 @@ async fn update_notify(
     Ok(())
 }
 
+#[tauri::command]
+async fn set_task_notify(
+    state: tauri::State<'_, State>,
+    kind: TaskKind,
+    notify: TaskNotify,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.task_notify.insert(kind, notify);
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_live_window_isolated(
+    state: tauri::State<'_, State>,
+    isolated: bool,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.live_window_isolated = isolated;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_quiet_hours(
+    state: tauri::State<'_, State>,
+    quiet_hours: Option<QuietHours>,
+) -> Result<(), String> {
+    if quiet_hours.as_ref().is_some_and(|q| !q.is_valid()) {
+        return Err("Invalid quiet hours, expect HH:MM".into());
+    }
+    let mut config = state.config.write().await;
+    config.quiet_hours = quiet_hours;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_room_quiet_hours(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    quiet_hours: Option<QuietHours>,
+) -> Result<(), String> {
+    let mut config = state.config.write().await;
+    match quiet_hours {
+        Some(quiet_hours) => {
+            if !quiet_hours.is_valid() {
+                return Err("Invalid quiet hours, expect HH:MM".into());
+            }
+            config
+                .room_quiet_hours
+                .insert(room_id.to_string(), quiet_hours);
+        }
+        None => {
+            config.room_quiet_hours.remove(&room_id.to_string());
+        }
+    }
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_danmu_collapse_window(state: tauri::State<'_, State>, window: u64) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.danmu_collapse_window = window;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_watermark(
+    state: tauri::State<'_, State>,
+    watermark: Option<Watermark>,
+) -> Result<(), String> {
+    if watermark.as_ref().is_some_and(|w| !w.is_valid()) {
+        return Err("Invalid watermark".into());
+    }
+    let mut config = state.config.write().await;
+    config.watermark = watermark;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_room_watermark(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    watermark: Option<Watermark>,
+) -> Result<(), String> {
+    let mut config = state.config.write().await;
+    match watermark {
+        Some(watermark) => {
+            if !watermark.is_valid() {
+                return Err("Invalid watermark".into());
+            }
+            config.room_watermark.insert(room_id.to_string(), watermark);
+        }
+        None => {
+            config.room_watermark.remove(&room_id.to_string());
+        }
+    }
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_split_limit(
+    state: tauri::State<'_, State>,
+    limit: Option<SplitLimit>,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.split = limit;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_room_split_limit(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    limit: Option<SplitLimit>,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    match limit {
+        Some(limit) => {
+            config.room_split.insert(room_id.to_string(), limit);
+        }
+        None => {
+            config.room_split.remove(&room_id.to_string());
+        }
+    }
+    config.save();
+    Ok(())
+}
+
+/// Recorders switch to the new proxy right away, account apis after restart
+#[tauri::command]
+async fn set_proxy(state: tauri::State<'_, State>, proxy: Option<String>) -> Result<(), String> {
+    if let Some(proxy) = &proxy {
+        reqwest::Proxy::all(proxy).map_err(|e| e.to_string())?;
+    }
+    {
+        let mut config = state.config.write().await;
+        config.proxy = proxy;
+        config.save();
+    }
+    Ok(state.recorder_manager.apply_proxy().await?)
+}
+
+#[tauri::command]
+async fn set_room_proxy(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    proxy: Option<String>,
+) -> Result<(), String> {
+    {
+        let mut config = state.config.write().await;
+        match proxy {
+            Some(proxy) => {
+                reqwest::Proxy::all(&proxy).map_err(|e| e.to_string())?;
+                config.room_proxy.insert(room_id.to_string(), proxy);
+            }
+            None => {
+                config.room_proxy.remove(&room_id.to_string());
+            }
+        }
+        config.save();
+    }
+    Ok(state.recorder_manager.apply_proxy().await?)
+}
+
+#[tauri::command]
+async fn set_max_recordings(state: tauri::State<'_, State>, max: usize) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.max_recordings = max;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_download_limit(state: tauri::State<'_, State>, limit: u64) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.download_limit = limit;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_segment_writes(state: tauri::State<'_, State>, policy: WritePolicy) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.segment_writes = policy;
+    config.save();
+    Ok(())
+}
+
+/// Limit of 0 removes room limit
+#[tauri::command]
+async fn set_room_download_limit(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    limit: u64,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    if limit == 0 {
+        config.room_download_limit.remove(&room_id.to_string());
+    } else {
+        config
+            .room_download_limit
+            .insert(room_id.to_string(), limit);
+    }
+    config.save();
+    Ok(())
+}
+
+/// Mode is applied to lives starting after this
+#[tauri::command]
+async fn set_recording_mode(
+    state: tauri::State<'_, State>,
+    mode: RecordingMode,
+    buffer_minutes: u64,
+) -> Result<(), String> {
+    if buffer_minutes == 0 {
+        return Err("Rolling buffer must be at least 1 minute".into());
+    }
+    let mut config = state.config.write().await;
+    config.recording_mode = mode;
+    config.rolling_buffer_minutes = buffer_minutes;
+    config.save();
+    Ok(())
+}
+
+/// Keep rolling buffer of current live as a real archive from now on
+#[tauri::command]
+async fn start_record(state: tauri::State<'_, State>, room_id: u64) -> Result<(), String> {
+    Ok(state.recorder_manager.promote_buffer(room_id).await?)
+}
+
+/// Stop segment downloads of a room, current live goes on when resumed
+#[tauri::command]
+async fn pause_recording(state: tauri::State<'_, State>, room_id: u64) -> Result<(), String> {
+    Ok(state.recorder_manager.pause_recording(room_id).await?)
+}
+
+#[tauri::command]
+async fn resume_recording(state: tauri::State<'_, State>, room_id: u64) -> Result<(), String> {
+    Ok(state.recorder_manager.resume_recording(room_id).await?)
+}
+
+#[tauri::command]
+async fn set_clip_chapters(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_chapters = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_clip_container(
+    state: tauri::State<'_, State>,
+    container: Container,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_container = container;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_archive_profile(
+    state: tauri::State<'_, State>,
+    profile: ArchiveProfile,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.archive_profile = profile;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_clip_profile(
+    state: tauri::State<'_, State>,
+    profile: ArchiveProfile,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_profile = profile;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_clip_codec(state: tauri::State<'_, State>, codec: Option<String>) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_codec = codec.filter(|c| !c.is_empty());
+    config.save();
+    Ok(())
+}
+
+/// Hardware encoders found, profiles with hwaccel use them
+#[tauri::command]
+async fn get_hwaccel_encoders() -> Result<Vec<String>, String> {
+    ffmpeg::execute(|| {
+        Ok(ffmpeg::hwaccel::available()
+            .into_iter()
+            .map(String::from)
+            .collect())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_monitor_profile(
+    state: tauri::State<'_, State>,
+    profile: ArchiveProfile,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.monitor_profile = profile;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_ffmpeg_path(
+    state: tauri::State<'_, State>,
+    ffmpeg_path: Option<String>,
+    ffprobe_path: Option<String>,
+) -> Result<String, String> {
+    ffmpeg::set_paths(ffmpeg_path.clone(), ffprobe_path.clone());
+    let mut config = state.config.write().await;
+    config.ffmpeg_path = ffmpeg_path.filter(|p| !p.is_empty());
+    config.ffprobe_path = ffprobe_path.filter(|p| !p.is_empty());
+    config.save();
+    drop(config);
+    get_ffmpeg_version().await
+}
+
+#[tauri::command]
+async fn set_ffmpeg_workers(state: tauri::State<'_, State>, workers: usize) -> Result<(), ()> {
+    ffmpeg::set_workers(workers);
+    let mut config = state.config.write().await;
+    config.ffmpeg_workers = workers.max(1);
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_ffmpeg_long_workers(state: tauri::State<'_, State>, workers: usize) -> Result<(), ()> {
+    ffmpeg::set_long_workers(workers);
+    let mut config = state.config.write().await;
+    config.ffmpeg_long_workers = workers.max(1);
+    config.save();
+    Ok(())
+}
+
+/// Version of ffmpeg in use, error if it's not found
+#[tauri::command]
+async fn get_ffmpeg_version() -> Result<String, String> {
+    // not queued behind running jobs, it only prints version
+    tokio::task::spawn_blocking(ffmpeg::version)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Download a static ffmpeg build for users without one, configured paths are cleared so
+/// the downloaded one is used
+#[tauri::command]
+async fn download_ffmpeg(state: tauri::State<'_, State>) -> Result<String, String> {
+    tokio::task::spawn_blocking(ffmpeg::download)
+        .await
+        .map_err(|e| e.to_string())??;
+    set_ffmpeg_path(state, None, None).await
+}
+
+#[tauri::command]
+async fn set_offload(state: tauri::State<'_, State>, offload: Option<S3Config>) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    if let Some(old) = config.offload.take() {
+        let location = old.location();
+        config.offload_retired.retain(|s| s.location() != location);
+        config.offload_retired.push(old);
+    }
+    if let Some(new) = &offload {
+        let location = new.location();
+        config.offload_retired.retain(|s| s.location() != location);
+    }
+    config.offload = offload;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_upload_encode(
+    state: tauri::State<'_, State>,
+    encode: Option<UploadEncode>,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.upload_encode = encode;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_archive_nfo(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.archive_nfo = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_detect_interruptions(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.detect_interruptions = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_clip_skip_interruptions(
+    state: tauri::State<'_, State>,
+    enabled: bool,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_skip_interruptions = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_torrent_web_seed(
+    state: tauri::State<'_, State>,
+    web_seed: Option<String>,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.torrent_web_seed = web_seed.filter(|s| !s.is_empty());
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_locale(state: tauri::State<'_, State>, locale: Locale) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.locale = locale;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_record_update_interval(
+    state: tauri::State<'_, State>,
+    interval: u64,
+) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.record_update_interval = interval;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_filler_pause(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.filler_pause = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_clip_loudnorm(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.clip_loudnorm = enabled;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_monitor_stream(state: tauri::State<'_, State>, enabled: bool) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.monitor_stream = enabled;
+    config.save();
+    Ok(())
+}
+
 #[tauri::command]
 async fn set_output_path(state: tauri::State<'_, State>, output_path: String) -> Result<(), ()> {
     let mut config = state.config.write().await;
@@ -439,6 +1375,108 @@ async fn clip(state: tauri::State<'_, State>, room_id: u64, len: f64) -> Result<
     state.clip(room_id, len).await
 }
 
+#[derive(serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum AudioSource {
+    Live { room_id: u64, live_id: u64 },
+    Video { id: i64 },
+}
+
+/// Generate a cols x rows thumbnail sheet of a live, returns path of the jpg
+#[tauri::command]
+async fn get_contact_sheet(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    cols: u32,
+    rows: u32,
+) -> Result<String, String> {
+    if cols == 0 || rows == 0 {
+        return Err("Grid must not be empty".into());
+    }
+    Ok(state
+        .recorder_manager
+        .generate_contact_sheet(room_id, live_id, cols, rows)
+        .await?)
+}
+
+/// Detect scene changes of a finished live and save them as markers, returns their offsets
+#[tauri::command]
+async fn detect_archive_scenes(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<f64>, String> {
+    Ok(state
+        .recorder_manager
+        .detect_scenes(room_id, live_id)
+        .await?)
+}
+
+/// Export audio of a live or a clip into output folder, returns path of audio file
+#[tauri::command]
+async fn export_audio(
+    state: tauri::State<'_, State>,
+    source: AudioSource,
+    format: AudioFormat,
+) -> Result<String, String> {
+    let (output, audio_bitrate) = {
+        let config = state.config.read().await;
+        (
+            config.output.clone(),
+            config.clip_profile.audio_bitrate.clone(),
+        )
+    };
+    let (room_id, result) = match source {
+        AudioSource::Live { room_id, live_id } => (
+            Some(room_id),
+            state
+                .recorder_manager
+                .export_audio(&output, room_id, live_id, format)
+                .await
+                .map_err(|e| e.to_string()),
+        ),
+        AudioSource::Video { id } => {
+            let video = state.db.get_video(id).await?;
+            let input = format!("{}/{}", output, video.file);
+            let file = Path::new(&input)
+                .with_extension(format.extension())
+                .to_string_lossy()
+                .to_string();
+            let output = file.clone();
+            let result = ffmpeg::execute_long(move || {
+                ffmpeg::extract_audio(&input, &output, format, &audio_bitrate)
+            })
+            .await
+            .map(|_| file);
+            (Some(video.room_id), result)
+        }
+    };
+    let (message, title) = match &result {
+        Ok(file) => (
+            format!("音频导出完成: {}", file),
+            "BiliShadowReplay - 导出完成",
+        ),
+        Err(e) => (
+            format!("音频导出失败: {}", e),
+            "BiliShadowReplay - 导出失败",
+        ),
+    };
+    notifier::notify_task(
+        &state.app_handle,
+        &*state.config.read().await,
+        TaskFinished {
+            kind: TaskKind::Export,
+            room_id,
+            ok: result.is_ok(),
+            message,
+            target: result.as_ref().ok().cloned(),
+        },
+        title,
+    );
+    result
+}
+
 #[tauri::command]
 async fn clip_range(
     state: tauri::State<'_, State>,
@@ -447,18 +1485,39 @@ async fn clip_range(
     ts: u64,
     x: f64,
     y: f64,
+    codec: Option<String>,
 ) -> Result<VideoRow, String> {
     log::info!(
-        "Clip room_id: {}, ts: {}, start: {}, end: {}",
+        "Clip room_id: {}, ts: {}, start: {}, end: {}, codec: {:?}",
         room_id,
         ts,
         x,
-        y
+        y,
+        codec
     );
-    let file = state
+    let output = state.config.read().await.output.clone();
+    let file = match state
         .recorder_manager
-        .clip_range(&state.config.read().await.output, room_id, ts, x, y)
-        .await?;
+        .clip_range(&output, room_id, ts, x, y, codec)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            notifier::notify_task(
+                &state.app_handle,
+                &*state.config.read().await,
+                TaskFinished {
+                    kind: TaskKind::Clip,
+                    room_id: Some(room_id),
+                    ok: false,
+                    message: format!("房间 {} 的切片生成失败: {}", room_id, e),
+                    target: None,
+                },
+                "BiliShadowReplay - 切片失败",
+            );
+            return Err(e.to_string());
+        }
+    };
     // get file metadata from fs
     let metadata = std::fs::metadata(&file).map_err(|e| e.to_string())?;
     // get filename from path
@@ -489,29 +1548,40 @@ async fn clip_range(
     state
         .db
         .new_message(
-            "生成新切片",
-            &format!(
-                "生成了房间 {} 的切片，长度 {:.1}s：{}",
-                room_id,
-                y - x,
-                filename
-            ),
+            MessageKey::ClipCreated,
+            &[
+                room_id.to_string(),
+                format!("{:.1}", y - x),
+                filename.to_string(),
+            ],
         )
         .await?;
-    if state.config.read().await.clip_notify {
-        state
-            .app_handle
-            .notification()
-            .builder()
-            .title("BiliShadowReplay - 切片完成")
-            .body(format!("生成了房间 {} 的切片: {}", room_id, filename))
-            .show()
-            .unwrap();
-    }
+    notifier::notify_task(
+        &state.app_handle,
+        &*state.config.read().await,
+        TaskFinished {
+            kind: TaskKind::Clip,
+            room_id: Some(room_id),
+            ok: true,
+            message: format!("生成了房间 {} 的切片: {}", room_id, filename),
+            target: Some(file.clone()),
+        },
+        "BiliShadowReplay - 切片完成",
+    );
     Ok(video)
 }
 
+/// Fill part title template, `{index}` is the part number and `{title}` the clip title
+fn render_part_title(template: &str, index: usize, title: &str) -> String {
+    template
+        .replace("{index}", &index.to_string())
+        .replace("{title}", title)
+}
+
+/// Submit the clip as a new video, or as a new part of `append_to` (bvid) if given.
+/// `part_title` is a template for the title of the part, see `render_part_title`.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn upload_procedure(
     state: tauri::State<'_, State>,
     uid: u64,
@@ -519,81 +1589,410 @@ async fn upload_procedure(
     video_id: i64,
     cover: String,
     mut profile: Profile,
+    append_to: Option<String>,
+    part_title: Option<String>,
 ) -> Result<String, String> {
     let account = state.db.get_account(uid).await?;
     // get video info from dbs
     let mut video_row = state.db.get_video(video_id).await?;
     // construct file path
-    let output = state.config.read().await.output.clone();
+    let (output, upload_encode, preset) = {
+        let config = state.config.read().await;
+        (
+            config.output.clone(),
+            config.upload_encode.clone(),
+            config.clip_profile.preset.clone(),
+        )
+    };
     let file = format!("{}/{}", output, video_row.file);
-    let path = Path::new(&file);
-    let cover_url = state.client.upload_cover(&account, &cover);
-    if let Ok(video) = state.client.prepare_video(&account, path).await {
-        profile.cover = cover_url.await.unwrap_or("".to_string());
-        if let Ok(ret) = state.client.submit_video(&account, &profile, &video).await {
-            // update video status and details
-            // 1 means uploaded
-            video_row.status = 1;
-            video_row.bvid = ret.bvid.clone();
-            video_row.title = profile.title;
-            video_row.desc = profile.desc;
-            video_row.tags = profile.tag;
-            video_row.area = profile.tid as i64;
-            state.db.update_video(&video_row).await?;
-            state
-                .db
-                .new_message(
-                    "投稿成功",
-                    &format!("投稿了房间 {} 的切片：{}", room_id, ret.bvid),
+    let encoded = match upload_encode {
+        Some(encode) if video_row.size as u64 > encode.target_mb * 1024 * 1024 => {
+            let encoded = format!("{}.upload.mp4", file);
+            let (input, target, length) = (file.clone(), encoded.clone(), video_row.length as f64);
+            log::info!("Encode {} into {}MB for upload", file, encode.target_mb);
+            let result = ffmpeg::execute_long(move || {
+                ffmpeg::two_pass(
+                    &input,
+                    &target,
+                    &encode.codec,
+                    &preset,
+                    encode.target_mb * 1024 * 1024,
+                    length,
                 )
-                .await?;
-            if state.config.read().await.post_notify {
-                state
-                    .app_handle
-                    .notification()
-                    .builder()
-                    .title("BiliShadowReplay - 投稿成功")
-                    .body(format!("投稿了房间 {} 的切片: {}", room_id, ret.bvid))
-                    .show()
-                    .unwrap();
+            })
+            .await;
+            if let Err(e) = result {
+                // second pass leaves a partial output when it fails
+                let _ = tokio::fs::remove_file(&encoded).await;
+                return Err(e);
             }
-            Ok(ret.bvid)
-        } else {
-            Err("Submit video failed".to_string())
+            Some(encoded)
+        }
+        _ => None,
+    };
+    let path = Path::new(encoded.as_ref().unwrap_or(&file));
+    let prepared = state.client.prepare_video(&account, path).await;
+    if let Some(encoded) = &encoded {
+        let _ = tokio::fs::remove_file(encoded).await;
+    }
+    let mut video = match prepared {
+        Ok(video) => video,
+        Err(_) => return Err("Preload video failed".to_string()),
+    };
+    let submitted = match &append_to {
+        Some(bvid) => {
+            let archive = state.client.get_archive(&account, bvid).await?;
+            let index = archive["videos"].as_array().map_or(0, |v| v.len()) + 1;
+            video.title = render_part_title(
+                part_title.as_deref().unwrap_or("P{index}"),
+                index,
+                &profile.title,
+            );
+            state.client.append_video(&account, &archive, &video).await
+        }
+        None => {
+            if let Some(template) = &part_title {
+                video.title = render_part_title(template, 1, &profile.title);
+            }
+            profile.cover = state
+                .client
+                .upload_cover(&account, &cover)
+                .await
+                .unwrap_or("".to_string());
+            state.client.submit_video(&account, &profile, &video).await
         }
+    };
+    if let Ok(ret) = submitted {
+        // update video status and details
+        // 1 means uploaded
+        video_row.status = 1;
+        video_row.bvid = ret.bvid.clone();
+        video_row.title = profile.title;
+        video_row.desc = profile.desc;
+        video_row.tags = profile.tag;
+        video_row.area = profile.tid as i64;
+        state.db.update_video(&video_row).await?;
+        state
+            .db
+            .new_message(
+                MessageKey::VideoUploaded,
+                &[room_id.to_string(), ret.bvid.clone()],
+            )
+            .await?;
+        notifier::notify_task(
+            &state.app_handle,
+            &*state.config.read().await,
+            TaskFinished {
+                kind: TaskKind::Upload,
+                room_id: Some(room_id),
+                ok: true,
+                message: format!("投稿了房间 {} 的切片: {}", room_id, ret.bvid),
+                target: Some(format!("https://www.bilibili.com/video/{}", ret.bvid)),
+            },
+            "BiliShadowReplay - 投稿成功",
+        );
+        Ok(ret.bvid)
+    } else {
+        notifier::notify_task(
+            &state.app_handle,
+            &*state.config.read().await,
+            TaskFinished {
+                kind: TaskKind::Upload,
+                room_id: Some(room_id),
+                ok: false,
+                message: format!("房间 {} 的切片投稿失败", room_id),
+                target: None,
+            },
+            "BiliShadowReplay - 投稿失败",
+        );
+        Err("Submit video failed".to_string())
+    }
+}
+
+/// Re-encode videos not matching policy in background, progress is emitted as `library:reencode`
+#[tauri::command]
+async fn reencode_library(
+    state: tauri::State<'_, State>,
+    policy: FormatPolicy,
+) -> Result<(), String> {
+    if state.reencoding.swap(true, Ordering::SeqCst) {
+        return Err("Re-encoding is already running".into());
+    }
+    let output = state.config.read().await.output.clone();
+    tauri::async_runtime::spawn(library::reencode_library(
+        state.app_handle.clone(),
+        state.db.clone(),
+        state.config.clone(),
+        output,
+        policy,
+        state.reencoding.clone(),
+    ));
+    Ok(())
+}
+
+/// Stop library re-encoding after the current video
+#[tauri::command]
+async fn cancel_reencode_library(state: tauri::State<'_, State>) -> Result<(), ()> {
+    state.reencoding.store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Capabilities of every compiled platform
+#[tauri::command]
+async fn get_platform_capabilities() -> Result<Vec<PlatformCapabilities>, ()> {
+    Ok(vec![recorder::bilibili::capabilities()])
+}
+
+#[tauri::command]
+async fn get_room_info(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+) -> Result<RecorderInfo, String> {
+    if let Some(info) = state.recorder_manager.get_recorder_info(room_id).await {
+        Ok(info)
     } else {
-        Err("Preload video failed".to_string())
+        Err("Not found".to_string())
     }
 }
 
 #[tauri::command]
-async fn get_room_info(
+async fn get_archives(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+) -> Result<Vec<RecordRow>, String> {
+    log::debug!("Get archives for {}", room_id);
+    Ok(state.recorder_manager.get_archives(room_id).await?)
+}
+
+#[tauri::command]
+async fn get_archive(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<RecordRow, String> {
+    Ok(state.recorder_manager.get_archive(room_id, live_id).await?)
+}
+
+/// Frames taken while recording a live, oldest first
+#[tauri::command]
+async fn get_archive_snapshots(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<Snapshot>, String> {
+    Ok(state
+        .recorder_manager
+        .get_snapshots(room_id, live_id)
+        .await?)
+}
+
+#[tauri::command]
+async fn set_backoff(state: tauri::State<'_, State>, policy: BackoffPolicy) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.backoff = policy;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_stream_codec(state: tauri::State<'_, State>, codec: StreamCodec) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.stream_codec = codec;
+    config.save();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_snapshot_interval(state: tauri::State<'_, State>, minutes: u64) -> Result<(), ()> {
+    let mut config = state.config.write().await;
+    config.snapshot_interval_minutes = minutes;
+    config.save();
+    Ok(())
+}
+
+/// Pick archive cover from a frame of the recording or an image, returns url path of the cover
+#[tauri::command]
+async fn set_archive_cover(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    source: CoverSource,
+) -> Result<String, String> {
+    Ok(state
+        .recorder_manager
+        .set_archive_cover(room_id, live_id, source)
+        .await?)
+}
+
+/// Fix an archive interrupted by a crash or full disk
+#[tauri::command]
+async fn repair_archive(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<RepairReport, String> {
+    Ok(state
+        .recorder_manager
+        .repair_archive(room_id, live_id)
+        .await?)
+}
+
+/// Remove the package of an archive kept for its torrent web seed
+#[tauri::command]
+async fn remove_archive_package(
     state: tauri::State<'_, State>,
     room_id: u64,
-) -> Result<RecorderInfo, String> {
-    if let Some(info) = state.recorder_manager.get_recorder_info(room_id).await {
-        Ok(info)
-    } else {
-        Err("Not found".to_string())
-    }
+    live_id: u64,
+) -> Result<(), String> {
+    Ok(state
+        .recorder_manager
+        .remove_archive_package(room_id, live_id)
+        .await?)
 }
 
+/// Package an archive into output folder as a single video, returns path of the video
 #[tauri::command]
-async fn get_archives(
+async fn export_archive(
     state: tauri::State<'_, State>,
     room_id: u64,
-) -> Result<Vec<RecordRow>, String> {
-    log::debug!("Get archives for {}", room_id);
-    Ok(state.recorder_manager.get_archives(room_id).await?)
+    live_id: u64,
+) -> Result<String, String> {
+    let (output, nfo) = {
+        let config = state.config.read().await;
+        (config.output.clone(), config.archive_nfo)
+    };
+    let result = state
+        .recorder_manager
+        .export_archive(&output, room_id, live_id, nfo)
+        .await
+        .map_err(|e| e.to_string());
+    let (message, title) = match &result {
+        Ok(file) => (
+            format!("直播导出完成: {}", file),
+            "BiliShadowReplay - 导出完成",
+        ),
+        Err(e) => (
+            format!("直播导出失败: {}", e),
+            "BiliShadowReplay - 导出失败",
+        ),
+    };
+    notifier::notify_task(
+        &state.app_handle,
+        &*state.config.read().await,
+        TaskFinished {
+            kind: TaskKind::Export,
+            room_id: Some(room_id),
+            ok: result.is_ok(),
+            message,
+            target: result.as_ref().ok().cloned(),
+        },
+        title,
+    );
+    result
 }
 
+/// Package an archive into one file and create a torrent with hls server as its web seed,
+/// `torrent_web_seed` has to be set as hls server only listens on localhost
 #[tauri::command]
-async fn get_archive(
+async fn create_archive_torrent(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<String, String> {
+    let (output, web_seed) = {
+        let config = state.config.read().await;
+        (config.output.clone(), config.torrent_web_seed.clone())
+    };
+    let Some(web_seed) = web_seed else {
+        return Err("Web seed url is not set".into());
+    };
+    let result = state
+        .recorder_manager
+        .create_archive_torrent(&output, room_id, live_id, &web_seed)
+        .await
+        .map_err(|e| e.to_string());
+    let (message, title) = match &result {
+        Ok(file) => (
+            format!("种子创建完成: {}", file),
+            "BiliShadowReplay - 导出完成",
+        ),
+        Err(e) => (
+            format!("种子创建失败: {}", e),
+            "BiliShadowReplay - 导出失败",
+        ),
+    };
+    notifier::notify_task(
+        &state.app_handle,
+        &*state.config.read().await,
+        TaskFinished {
+            kind: TaskKind::Export,
+            room_id: Some(room_id),
+            ok: result.is_ok(),
+            message,
+            target: result.as_ref().ok().cloned(),
+        },
+        title,
+    );
+    result
+}
+
+/// Re-encode an archive with the configured profile, originals are kept if it fails
+#[tauri::command]
+async fn compress_archive(
     state: tauri::State<'_, State>,
     room_id: u64,
     live_id: u64,
 ) -> Result<RecordRow, String> {
-    Ok(state.recorder_manager.get_archive(room_id, live_id).await?)
+    let profile = state.config.read().await.archive_profile.clone();
+    let result = state
+        .recorder_manager
+        .compress_archive(room_id, live_id, &profile)
+        .await
+        .map_err(|e| e.to_string());
+    let (message, title) = match &result {
+        Ok(record) => (
+            format!("直播 {} 压缩完成: {} 字节", live_id, record.size),
+            "BiliShadowReplay - 压缩完成",
+        ),
+        Err(e) => (
+            format!("直播 {} 压缩失败: {}", live_id, e),
+            "BiliShadowReplay - 压缩失败",
+        ),
+    };
+    notifier::notify_task(
+        &state.app_handle,
+        &*state.config.read().await,
+        TaskFinished {
+            kind: TaskKind::Reencode,
+            room_id: Some(room_id),
+            ok: result.is_ok(),
+            message,
+            target: None,
+        },
+        title,
+    );
+    result
+}
+
+/// Merge archives of a stream that dropped and restarted, into the earliest one
+#[tauri::command]
+async fn merge_archives(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_ids: Vec<u64>,
+) -> Result<RecordRow, String> {
+    let record = state
+        .recorder_manager
+        .merge_archives(room_id, live_ids.clone())
+        .await?;
+    state
+        .db
+        .new_message(
+            MessageKey::ArchivesMerged,
+            &[room_id.to_string(), format!("{:?}", live_ids)],
+        )
+        .await?;
+    Ok(record)
 }
 
 #[tauri::command]
@@ -606,8 +2005,8 @@ async fn delete_archive(
     state
         .db
         .new_message(
-            "删除历史缓存",
-            &format!("删除了房间 {} 的历史缓存 {}", room_id, ts),
+            MessageKey::ArchiveDeleted,
+            &[room_id.to_string(), ts.to_string()],
         )
         .await?;
     Ok(())
@@ -637,6 +2036,287 @@ async fn get_danmu_record(
     Ok(state.recorder_manager.get_danmu(room_id, ts).await?)
 }
 
+#[tauri::command]
+async fn get_danmu_heatmap(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    bucket_secs: u64,
+) -> Result<Vec<HeatmapBucket>, String> {
+    Ok(state
+        .recorder_manager
+        .get_danmu_heatmap(room_id, live_id, bucket_secs)
+        .await?)
+}
+
+/// Suggested clip ranges from danmu peaks, hottest first
+#[tauri::command]
+async fn get_danmu_highlights(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    bucket_secs: u64,
+    count: usize,
+) -> Result<Vec<Highlight>, String> {
+    Ok(state
+        .recorder_manager
+        .get_danmu_highlights(room_id, live_id, bucket_secs, count)
+        .await?)
+}
+
+#[tauri::command]
+async fn export_danmu(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    options: ExportDanmuOptions,
+) -> Result<String, String> {
+    Ok(state
+        .recorder_manager
+        .export_danmu(room_id, live_id, &options)
+        .await?)
+}
+
+#[tauri::command]
+async fn set_translation(
+    state: tauri::State<'_, State>,
+    translation: Option<TranslationConfig>,
+) -> Result<(), String> {
+    if let Some(translation) = &translation {
+        // language is part of the track file name
+        if translation.target_lang.is_empty()
+            || !translation
+                .target_lang
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err("Invalid target language".into());
+        }
+    }
+    let mut config = state.config.write().await;
+    config.translation = translation;
+    config.save();
+    Ok(())
+}
+
+/// Translate danmu of live into a separate track, returns the number of lines translated
+#[tauri::command]
+async fn translate_danmu(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<usize, String> {
+    Ok(state
+        .recorder_manager
+        .translate_danmu(room_id, live_id)
+        .await?)
+}
+
+/// YouTube style chat replay (json-lines) of live danmu
+#[tauri::command]
+async fn export_chat_replay(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<String, String> {
+    Ok(state
+        .recorder_manager
+        .export_chat_replay(room_id, live_id)
+        .await?)
+}
+
+/// WebVTT chapters of live, chapter offsets are relative to playback start
+#[tauri::command]
+async fn export_chapters(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+    chapters: Vec<Chapter>,
+) -> Result<String, String> {
+    let record = state.db.get_record(room_id, live_id).await?;
+    Ok(recorder::danmu::export::chapters_vtt(
+        &chapters,
+        record.length as f64,
+    ))
+}
+
+#[tauri::command]
+async fn get_keyword_rules(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+) -> Result<Vec<KeywordRuleRow>, String> {
+    Ok(state.db.get_keyword_rules(room_id).await?)
+}
+
+#[tauri::command]
+async fn add_keyword_rule(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    pattern: String,
+    is_regex: bool,
+) -> Result<KeywordRuleRow, String> {
+    if pattern.is_empty() {
+        return Err("Keyword is empty".into());
+    }
+    if is_regex {
+        regex::Regex::new(&pattern).map_err(|e| e.to_string())?;
+    }
+    let rule = state
+        .db
+        .add_keyword_rule(room_id, &pattern, is_regex)
+        .await?;
+    // recorder might not be running, rules are loaded when it starts
+    let _ = state.recorder_manager.reload_keyword_rules(room_id).await;
+    Ok(rule)
+}
+
+#[tauri::command]
+async fn remove_keyword_rule(state: tauri::State<'_, State>, id: i64) -> Result<(), String> {
+    let rule = state.db.get_keyword_rule(id).await?;
+    state.db.remove_keyword_rule(id).await?;
+    let _ = state
+        .recorder_manager
+        .reload_keyword_rules(rule.room_id)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_markers(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<MarkerRow>, String> {
+    Ok(state.db.get_markers(room_id, live_id).await?)
+}
+
+/// Ad and technical difficulty ranges detected in a live, offsets in seconds
+#[tauri::command]
+async fn get_interruptions(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<InterruptionRow>, String> {
+    Ok(state.db.get_interruptions(room_id, live_id).await?)
+}
+
+/// Danmu count per minute of a live
+#[tauri::command]
+async fn get_danmu_statistics(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<DanmuStatisticRow>, String> {
+    Ok(state.db.get_danmu_statistics(room_id, live_id).await?)
+}
+
+/// Online count samples of a live
+#[tauri::command]
+async fn get_room_metrics(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: u64,
+) -> Result<Vec<RoomMetricRow>, String> {
+    Ok(state.db.get_room_metrics(room_id, live_id).await?)
+}
+
+#[tauri::command]
+async fn get_interactions(
+    state: tauri::State<'_, State>,
+    live_id: u64,
+) -> Result<Vec<InteractionRow>, String> {
+    Ok(state.db.get_interactions(live_id).await?)
+}
+
+#[derive(serde::Serialize)]
+struct RevenueStats {
+    lives: Vec<RevenueStatRow>,
+    months: Vec<RevenueStatRow>,
+}
+
+#[tauri::command]
+async fn get_revenue_stats(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+) -> Result<RevenueStats, String> {
+    Ok(RevenueStats {
+        lives: state.db.get_revenue_by_live(room_id).await?,
+        months: state.db.get_revenue_by_month(room_id).await?,
+    })
+}
+
+/// Gifts received by name, of one live if live_id is given or of the whole room
+#[tauri::command]
+async fn get_revenue_summary(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    live_id: Option<u64>,
+) -> Result<Vec<GiftSummaryRow>, String> {
+    Ok(state.db.get_gift_summary(room_id, live_id).await?)
+}
+
+/// results older than this are refreshed in background
+const CDN_SPEEDTEST_EXPIRE_DAYS: i64 = 7;
+
+/// accounts are kept alive once in this many seconds, plus a random jitter up to a half hour
+const KEEPALIVE_INTERVAL: u64 = 6 * 3600;
+const KEEPALIVE_JITTER: u64 = 1800;
+
+async fn keep_accounts_alive(db: &Database, client: &BiliClient) {
+    let accounts = match db.get_accounts().await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            log::error!("Get accounts for keep-alive failed: {}", e);
+            return;
+        }
+    };
+    for account in accounts.iter().filter(|a| a.keepalive) {
+        match client.keep_alive(account).await {
+            Ok(_) => {
+                if let Err(e) = db.update_last_keepalive(account.uid).await {
+                    log::error!("Update keep-alive of {} failed: {}", account.uid, e);
+                }
+            }
+            Err(e) => log::warn!("Keep-alive of account {} failed: {}", account.uid, e),
+        }
+    }
+}
+
+#[tauri::command]
+async fn set_account_keepalive(
+    state: tauri::State<'_, State>,
+    uid: u64,
+    enabled: bool,
+) -> Result<(), String> {
+    Ok(state.db.set_account_keepalive(uid, enabled).await?)
+}
+
+async fn run_cdn_speedtest(
+    db: &Database,
+    recorder_manager: &RecorderManager,
+    platform: &str,
+) -> Result<Vec<CdnSpeedRow>, String> {
+    let results = recorder_manager.cdn_speedtest(platform).await?;
+    Ok(db.set_cdn_speeds(platform, &results).await?)
+}
+
+#[tauri::command]
+async fn cdn_speedtest(
+    state: tauri::State<'_, State>,
+    platform: String,
+) -> Result<Vec<CdnSpeedRow>, String> {
+    log::info!("Start cdn speedtest for {}", platform);
+    run_cdn_speedtest(&state.db, &state.recorder_manager, &platform).await
+}
+
+#[tauri::command]
+async fn get_cdn_speeds(
+    state: tauri::State<'_, State>,
+    platform: String,
+) -> Result<Vec<CdnSpeedRow>, String> {
+    Ok(state.db.get_cdn_speeds(&platform).await?)
+}
+
 #[derive(serde::Serialize)]
 struct AccountInfo {
     pub primary_uid: u64,
@@ -653,9 +2333,21 @@ async fn get_accounts(state: tauri::State<'_, State>) -> Result<AccountInfo, Str
     Ok(account_info)
 }
 
+/// `isolated` overrides config.live_window_isolated for this window.
+/// Isolated windows open the platform live page with a data directory of their own, so
+/// cookies and sessions of main window are not shared, while their local storage is kept.
+/// WKWebView has no data directory option, so macOS falls back to an incognito webview.
+/// Otherwise the player seeks to `start` (seconds) once loaded.
 #[tauri::command]
-async fn open_live(state: tauri::State<'_, State>, room_id: u64, ts: u64) -> Result<(), String> {
+async fn open_live(
+    state: tauri::State<'_, State>,
+    room_id: u64,
+    ts: u64,
+    isolated: Option<bool>,
+    start: Option<f64>,
+) -> Result<(), String> {
     log::info!("Open player window: {} {}", room_id, ts);
+    let isolated = isolated.unwrap_or(state.config.read().await.live_window_isolated);
     let addr = state.recorder_manager.get_hls_server_addr().await.unwrap();
     let recorder_info = state
         .recorder_manager
@@ -663,26 +2355,41 @@ async fn open_live(state: tauri::State<'_, State>, room_id: u64, ts: u64) -> Res
         .await
         .unwrap();
     let handle = state.app_handle.clone();
-    let builder = tauri::WebviewWindowBuilder::new(
-        &handle,
-        format!("Live:{}:{}", room_id, ts),
+    let url = if isolated {
+        let page = format!("https://live.bilibili.com/{}", room_id);
+        tauri::WebviewUrl::External(page.parse().map_err(|e| format!("{}", e))?)
+    } else {
         tauri::WebviewUrl::App(
             format!(
-                "live_index.html?port={}&room_id={}&ts={}",
+                "live_index.html?port={}&room_id={}&ts={}&t={}",
                 addr.port(),
                 room_id,
-                ts
+                ts,
+                start.unwrap_or(0.0)
             )
             .into(),
-        ),
-    )
-    .title(format!(
-        "Live[{}] {}",
-        room_id, recorder_info.room_info.room_title
-    ))
-    .theme(Some(Theme::Light))
-    .inner_size(1200.0, 800.0)
-    .effects(WindowEffectsConfig {
+        )
+    };
+    let mut builder =
+        tauri::WebviewWindowBuilder::new(&handle, format!("Live:{}:{}", room_id, ts), url)
+            .title(format!(
+                "Live[{}] {}",
+                room_id, recorder_info.room_info.room_title
+            ))
+            .theme(Some(Theme::Light))
+            .inner_size(1200.0, 800.0);
+    if isolated {
+        #[cfg(not(target_os = "macos"))]
+        {
+            let app_dirs = AppDirs::new(Some("cn.vjoi.bili-shadowreplay"), false).unwrap();
+            builder = builder.data_directory(app_dirs.data_dir.join("isolated_webview"));
+        }
+        #[cfg(target_os = "macos")]
+        {
+            builder = builder.incognito(true);
+        }
+    }
+    let builder = builder.effects(WindowEffectsConfig {
         effects: vec![
             tauri_utils::WindowEffect::Tabbed,
             tauri_utils::WindowEffect::Mica,
@@ -712,9 +2419,22 @@ async fn open_live(state: tauri::State<'_, State>, room_id: u64, ts: u64) -> Res
     Ok(())
 }
 
+/// Open the window a deep link (like `bsr://live/{room_id}/{live_id}?t=01:23:45`) points to
+#[tauri::command]
+async fn open_deep_link(state: tauri::State<'_, State>, link: String) -> Result<(), String> {
+    match DeepLink::parse(&link)? {
+        DeepLink::Live {
+            room_id,
+            live_id,
+            offset,
+        } => open_live(state, room_id, live_id, None, offset).await,
+    }
+}
+
 #[tauri::command]
 async fn get_messages(state: tauri::State<'_, State>) -> Result<Vec<MessageRow>, String> {
-    Ok(state.db.get_messages().await?)
+    let locale = state.config.read().await.locale;
+    Ok(state.db.get_messages(locale).await?)
 }
 
 #[tauri::command]
@@ -732,6 +2452,17 @@ async fn get_video(state: tauri::State<'_, State>, id: i64) -> Result<VideoRow,
     Ok(state.db.get_video(id).await?)
 }
 
+/// Container, codec and resolution of a video, served from media info cache
+#[tauri::command]
+async fn get_video_media_info(
+    state: tauri::State<'_, State>,
+    id: i64,
+) -> Result<ffmpeg::MediaInfo, String> {
+    let video = state.db.get_video(id).await?;
+    let output = state.config.read().await.output.clone();
+    library::probe(&state.db, &format!("{}/{}", output, video.file)).await
+}
+
 #[tauri::command]
 async fn get_videos(state: tauri::State<'_, State>, room_id: u64) -> Result<Vec<VideoRow>, String> {
     Ok(state.db.get_videos(room_id).await?)
@@ -747,6 +2478,9 @@ async fn delete_video(state: tauri::State<'_, State>, id: i64) -> Result<(), Str
     if let Err(e) = std::fs::remove_file(file) {
         log::error!("Delete video file error: {}", e);
     }
+    if let Err(e) = state.db.remove_media_info(&filepath).await {
+        log::warn!("Remove media info of {} failed: {}", filepath, e);
+    }
     Ok(state.db.delete_video(id).await?)
 }
 
@@ -803,14 +2537,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     ])
     .unwrap();
 
-    // Setup ffmpeg
-    // ffmpeg_sidecar::download::auto_download().unwrap();
-
     //Setup database
-    let migrations = vec![Migration {
-        version: 1,
-        description: "create_initial_tables",
-        sql: r#"
+    let migrations = vec![
+        Migration {
+            version: 1,
+            description: "create_initial_tables",
+            sql: r#"
             CREATE TABLE accounts (uid INTEGER PRIMARY KEY, name TEXT, avatar TEXT, csrf TEXT, cookies TEXT, created_at TEXT);
             CREATE TABLE recorders (room_id INTEGER PRIMARY KEY, created_at TEXT);
             CREATE TABLE records (live_id INTEGER PRIMARY KEY, room_id INTEGER, title TEXT, length INTEGER, size INTEGER, created_at TEXT);
@@ -818,8 +2550,121 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             CREATE TABLE messages (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT, content TEXT, read INTEGER, created_at TEXT);
             CREATE TABLE videos (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, cover TEXT, file TEXT, length INTEGER, size INTEGER, status INTEGER, bvid TEXT, title TEXT, desc TEXT, tags TEXT, area INTEGER, created_at TEXT);
             "#,
-        kind: MigrationKind::Up,
-    }];
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 2,
+            description: "create_cdn_speeds_table",
+            sql: r#"
+            CREATE TABLE cdn_speeds (platform TEXT, host TEXT, speed INTEGER, created_at TEXT, PRIMARY KEY (platform, host));
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 3,
+            description: "create_interactions_table",
+            sql: r#"
+            CREATE TABLE interactions (id INTEGER PRIMARY KEY AUTOINCREMENT, live_id INTEGER, room_id INTEGER, kind TEXT, uid INTEGER, user_name TEXT, content TEXT, gift_name TEXT, num INTEGER, value INTEGER, ts INTEGER);
+            CREATE INDEX interactions_live_id ON interactions (live_id);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 4,
+            description: "create_keyword_rules_and_markers_tables",
+            sql: r#"
+            CREATE TABLE keyword_rules (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, pattern TEXT, is_regex INTEGER, created_at TEXT);
+            CREATE TABLE markers (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, offset REAL, realtime INTEGER, content TEXT, created_at TEXT);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 5,
+            description: "recreate_danmu_statistics_table",
+            // live_id was primary key, which allows only one row per live
+            sql: r#"
+            DROP TABLE danmu_statistics;
+            CREATE TABLE danmu_statistics (id INTEGER PRIMARY KEY AUTOINCREMENT, live_id INTEGER, room_id INTEGER, value INTEGER, time_point TEXT);
+            CREATE INDEX danmu_statistics_live_id ON danmu_statistics (live_id);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 6,
+            description: "create_room_metrics_table",
+            sql: r#"
+            CREATE TABLE room_metrics (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, online INTEGER, ts INTEGER);
+            CREATE INDEX room_metrics_live_id ON room_metrics (live_id);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 7,
+            description: "add_key_to_messages",
+            sql: r#"
+            ALTER TABLE messages ADD COLUMN key TEXT;
+            ALTER TABLE messages ADD COLUMN params TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 8,
+            description: "create_media_info_table",
+            sql: r#"
+            CREATE TABLE media_info (path TEXT PRIMARY KEY, mtime INTEGER, container TEXT, codec TEXT, width INTEGER, height INTEGER);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 9,
+            description: "add_keepalive_to_accounts",
+            sql: r#"
+            ALTER TABLE accounts ADD COLUMN keepalive INTEGER DEFAULT 1;
+            ALTER TABLE accounts ADD COLUMN last_keepalive TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 10,
+            description: "create_interruptions_table",
+            sql: r#"
+            CREATE TABLE interruptions (id INTEGER PRIMARY KEY AUTOINCREMENT, room_id INTEGER, live_id INTEGER, start REAL, end REAL, created_at TEXT);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 11,
+            description: "add_quality_to_recorders",
+            sql: r#"
+            ALTER TABLE recorders ADD COLUMN quality INTEGER DEFAULT 10000;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 12,
+            description: "add_schedule_to_recorders",
+            sql: r#"
+            ALTER TABLE recorders ADD COLUMN schedule TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 13,
+            description: "add_priority_to_recorders",
+            sql: r#"
+            ALTER TABLE recorders ADD COLUMN priority INTEGER DEFAULT 0;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        Migration {
+            version: 14,
+            description: "add_cover_to_records",
+            sql: r#"
+            ALTER TABLE records ADD COLUMN cover TEXT;
+            "#,
+            kind: MigrationKind::Up,
+        },
+    ];
 
     // Tauri part
     tauri::Builder::default()
@@ -844,8 +2689,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
             // init
-            let client = Arc::new(BiliClient::new().unwrap());
-            let config = Arc::new(RwLock::new(Config::load()));
+            let config = Config::load();
+            // a direct client would send what user meant to proxy, so don't start without it
+            let client = Arc::new(BiliClient::with_proxy(config.proxy.as_deref()).map_err(
+                |e| {
+                    log::error!("Invalid proxy {:?} in config: {}", config.proxy, e);
+                    e
+                },
+            )?);
+            // Setup ffmpeg
+            ffmpeg::set_paths(config.ffmpeg_path.clone(), config.ffprobe_path.clone());
+            ffmpeg::set_workers(config.ffmpeg_workers);
+            ffmpeg::set_long_workers(config.ffmpeg_long_workers);
+            let config = Arc::new(RwLock::new(config));
             let config_clone = config.clone();
             let recorder_manager =
                 Arc::new(RecorderManager::new(app.handle().clone(), config.clone()));
@@ -862,6 +2718,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     tauri_plugin_sql::DbPool::Sqlite(pool) => Some(pool),
                 };
                 db_clone.set(sqlite_pool.unwrap().clone()).await;
+                if config_clone.read().await.recovered_from_backup {
+                    let _ = db_clone.new_message(MessageKey::ConfigRecovered, &[]).await;
+                }
+                let output = config_clone.read().await.output.clone();
+                tauri::async_runtime::spawn(library::warm_up_media_cache(db_clone.clone(), output));
                 let initial_rooms = db_clone.get_recorders().await.unwrap();
                 let mut primary_uid = config_clone.read().await.primary_uid;
                 let accounts = db_clone.get_accounts().await.unwrap();
@@ -926,12 +2787,53 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     log::warn!("No available account found");
                 }
             });
+            // refresh cdn ranking in background, speedtest needs a streaming room so retry hourly
+            let db_clone = db.clone();
+            let recorder_manager_clone = recorder_manager.clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let latest = match db_clone.get_cdn_speeds("bilibili").await {
+                        Ok(rows) => rows.into_iter().next(),
+                        Err(_) => None,
+                    };
+                    let expired = match latest {
+                        Some(row) => match chrono::DateTime::parse_from_rfc3339(&row.created_at) {
+                            Ok(t) => {
+                                Utc::now().signed_duration_since(t).num_days()
+                                    >= CDN_SPEEDTEST_EXPIRE_DAYS
+                            }
+                            Err(_) => true,
+                        },
+                        None => true,
+                    };
+                    if expired {
+                        if let Err(e) =
+                            run_cdn_speedtest(&db_clone, &recorder_manager_clone, "bilibili").await
+                        {
+                            log::debug!("Cdn speedtest skipped: {}", e);
+                        }
+                    }
+                    tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+                }
+            });
+            // keep account cookies from idle expiry
+            let db_clone = db.clone();
+            let client_clone = client.clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let jitter = rand::random::<u64>() % KEEPALIVE_JITTER;
+                    tokio::time::sleep(std::time::Duration::from_secs(KEEPALIVE_INTERVAL + jitter))
+                        .await;
+                    keep_accounts_alive(&db_clone, &client_clone).await;
+                }
+            });
             let state = State {
                 db,
                 client,
                 config,
                 recorder_manager,
                 app_handle: app.handle().clone(),
+                reencoding: Arc::new(AtomicBool::new(false)),
             };
             let _ = tray::create_tray(app.handle());
             app.manage(state);
@@ -948,17 +2850,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .invoke_handler(tauri::generate_handler![
             get_recorder_list,
             add_recorder,
+            set_recorder_quality,
+            set_recorder_schedule,
+            set_recorder_priority,
             remove_recorder,
+            export_recorders,
+            import_recorders,
             get_config,
             set_cache_path,
             set_output_path,
             clip,
             clip_range,
+            export_audio,
+            get_contact_sheet,
+            detect_archive_scenes,
             upload_procedure,
+            reencode_library,
+            cancel_reencode_library,
             show_in_folder,
             get_qr,
             get_qr_status,
             open_live,
+            open_deep_link,
             get_accounts,
             add_account,
             remove_account,
@@ -967,20 +2880,103 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             get_archive,
             get_archives,
             delete_archive,
+            merge_archives,
+            repair_archive,
+            compress_archive,
+            export_archive,
+            create_archive_torrent,
+            remove_archive_package,
+            set_archive_cover,
+            get_archive_snapshots,
+            set_snapshot_interval,
+            set_stream_codec,
+            set_backoff,
             get_messages,
             read_message,
             delete_message,
             get_video,
+            get_video_media_info,
             get_videos,
             delete_video,
             get_disk_info,
             send_danmaku,
+            get_platform_capabilities,
             update_notify,
+            set_task_notify,
+            set_quiet_hours,
+            set_room_quiet_hours,
+            set_live_window_isolated,
+            set_monitor_stream,
+            set_clip_loudnorm,
+            set_clip_chapters,
+            set_clip_container,
+            set_archive_profile,
+            set_clip_profile,
+            set_clip_codec,
+            get_hwaccel_encoders,
+            set_monitor_profile,
+            set_torrent_web_seed,
+            set_upload_encode,
+            set_archive_nfo,
+            set_detect_interruptions,
+            set_clip_skip_interruptions,
+            set_offload,
+            set_ffmpeg_path,
+            set_ffmpeg_workers,
+            set_ffmpeg_long_workers,
+            get_ffmpeg_version,
+            set_account_keepalive,
+            download_ffmpeg,
+            set_locale,
+            set_record_update_interval,
+            set_filler_pause,
+            set_recording_mode,
+            start_record,
+            pause_recording,
+            resume_recording,
+            set_watermark,
+            set_room_watermark,
+            set_split_limit,
+            set_room_split_limit,
+            set_proxy,
+            set_room_proxy,
+            set_max_recordings,
+            set_download_limit,
+            set_room_download_limit,
+            set_segment_writes,
+            set_danmu_collapse_window,
             get_danmu_record,
+            get_interactions,
+            get_danmu_statistics,
+            get_room_metrics,
+            get_revenue_stats,
+            get_revenue_summary,
+            get_keyword_rules,
+            add_keyword_rule,
+            remove_keyword_rule,
+            get_markers,
+            get_interruptions,
+            get_danmu_heatmap,
+            get_danmu_highlights,
+            export_danmu,
+            set_translation,
+            translate_danmu,
+            export_chat_replay,
+            export_chapters,
             get_video_typelist,
-            export_to_file
+            export_to_file,
+            cdn_speedtest,
+            get_cdn_speeds
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                // make sure buffered danmu is written before quit
+                if let Some(state) = app_handle.try_state::<State>() {
+                    tauri::async_runtime::block_on(state.recorder_manager.stop_all());
+                }
+            }
+        });
     Ok(())
 }
@@ -0,0 +1,65 @@
+/// Metadata of an exported archive, written as a movie nfo that Jellyfin, Emby and Kodi read
+/// from a sidecar file named like the video
+pub struct Nfo {
+    pub title: String,
+    /// streamer of the room
+    pub actor: String,
+    /// "YYYY-MM-DD"
+    pub date: String,
+    pub plot: String,
+    /// file name of the poster next to the video
+    pub thumb: Option<String>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+impl Nfo {
+    pub fn render(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<movie>\n",
+        );
+        xml += &format!("  <title>{}</title>\n", escape(&self.title));
+        xml += &format!("  <plot>{}</plot>\n", escape(&self.plot));
+        xml += &format!("  <premiered>{}</premiered>\n", escape(&self.date));
+        if let Some(year) = self.date.get(..4) {
+            xml += &format!("  <year>{}</year>\n", escape(year));
+        }
+        xml += &format!(
+            "  <actor>\n    <name>{}</name>\n    <role>主播</role>\n  </actor>\n",
+            escape(&self.actor)
+        );
+        xml += &format!("  <studio>{}</studio>\n", escape(&self.actor));
+        if let Some(thumb) = &self.thumb {
+            xml += &format!("  <thumb aspect=\"poster\">{}</thumb>\n", escape(thumb));
+        }
+        xml += "</movie>\n";
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_escapes_and_adds_year() {
+        let nfo = Nfo {
+            title: "Tom & Jerry <live>".into(),
+            actor: "streamer".into(),
+            date: "2024-01-05".into(),
+            plot: "it's \"fun\"".into(),
+            thumb: None,
+        };
+        let xml = nfo.render();
+        assert!(xml.contains("<title>Tom &amp; Jerry &lt;live&gt;</title>"));
+        assert!(xml.contains("<plot>it&apos;s &quot;fun&quot;</plot>"));
+        assert!(xml.contains("<year>2024</year>"));
+        assert!(!xml.contains("<thumb"));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::Config;
+use chrono::{Local, NaiveTime};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+/// Notifications are suppressed between start and end, both in local "HH:MM".
+/// Range may cross midnight, like 23:00 - 08:00.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").ok()
+}
+
+impl QuietHours {
+    pub fn is_valid(&self) -> bool {
+        parse_time(&self.start).is_some() && parse_time(&self.end).is_some()
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+/// Room settings take precedence over global quiet hours
+fn is_quiet(config: &Config, room_id: Option<u64>) -> bool {
+    let quiet_hours = room_id
+        .and_then(|id| config.room_quiet_hours.get(&id.to_string()))
+        .or(config.quiet_hours.as_ref());
+    match quiet_hours {
+        Some(quiet_hours) => quiet_hours.contains(Local::now().time()),
+        None => false,
+    }
+}
+
+/// Show a system notification unless it's in quiet hours of the room
+pub fn notify(
+    app_handle: &AppHandle,
+    config: &Config,
+    room_id: Option<u64>,
+    title: &str,
+    body: &str,
+) {
+    if is_quiet(config, room_id) {
+        log::info!("Notification suppressed in quiet hours: {} {}", title, body);
+        return;
+    }
+    app_handle
+        .notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .show()
+        .unwrap();
+}
+
+/// Long running tasks that report when they finish
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskKind {
+    Clip,
+    Upload,
+    Reencode,
+    Export,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TaskNotify {
+    pub done: bool,
+    pub failed: bool,
+}
+
+impl Default for TaskNotify {
+    fn default() -> Self {
+        TaskNotify {
+            done: true,
+            failed: true,
+        }
+    }
+}
+
+/// Emitted as `task:finished`, target is the file or link produced, for opening it from ui
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct TaskFinished {
+    pub kind: TaskKind,
+    pub room_id: Option<u64>,
+    pub ok: bool,
+    pub message: String,
+    pub target: Option<String>,
+}
+
+fn task_notify_enabled(config: &Config, kind: TaskKind, ok: bool) -> bool {
+    let settings = config.task_notify.get(&kind).cloned().unwrap_or_default();
+    if !ok {
+        return settings.failed;
+    }
+    // older switches still apply to clip and upload
+    settings.done
+        && match kind {
+            TaskKind::Clip => config.clip_notify,
+            TaskKind::Upload => config.post_notify,
+            _ => true,
+        }
+}
+
+/// Report a finished task to ui, and as a system notification if it's enabled for the kind
+pub fn notify_task(app_handle: &AppHandle, config: &Config, task: TaskFinished, title: &str) {
+    if task_notify_enabled(config, task.kind, task.ok) {
+        notify(app_handle, config, task.room_id, title, &task.message);
+    }
+    let _ = app_handle.emit("task:finished", task);
+}
@@ -0,0 +1,240 @@
+use chrono::{DateTime, Utc};
+use custom_error::custom_error;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Url};
+use sha2::{Digest, Sha256};
+
+custom_error! {pub OffloadError
+    InvalidUrl {url: String} = "Invalid storage url: {url}",
+    ClientError {err: reqwest::Error} = "Storage request failed: {err}",
+    StatusError {status: u16, body: String} = "Storage responded {status}: {body}",
+    StorageNotFound {location: String} = "Storage is not configured any more: {location}",
+}
+
+impl From<reqwest::Error> for OffloadError {
+    fn from(e: reqwest::Error) -> Self {
+        OffloadError::ClientError { err: e }
+    }
+}
+
+/// S3-compatible bucket finished segments are moved into, objects are addressed in path style
+/// like `{endpoint}/{bucket}/{room_id}/{live_id}/{file}`
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// base url players fetch objects from, like a cdn in front of bucket;
+    /// bucket url is used if it's not set, which must be public readable then
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+impl S3Config {
+    /// Endpoint and bucket, recorded with every offloaded segment so it's still found after
+    /// offload config changes
+    pub fn location(&self) -> String {
+        format!("{}/{}", self.endpoint.trim_end_matches('/'), self.bucket)
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("{}/{}", self.location(), key)
+    }
+
+    /// Url of an object for playlists
+    pub fn public_url(&self, key: &str) -> String {
+        match &self.public_url {
+            Some(base) => format!("{}/{}", base.trim_end_matches('/'), key),
+            None => self.object_url(key),
+        }
+    }
+}
+
+/// Storage of `location` in `storages`
+pub fn find<'a>(storages: &'a [S3Config], location: &str) -> Option<&'a S3Config> {
+    storages.iter().find(|s| s.location() == location)
+}
+
+/// Url of an object in storage of `location`, plain object url if the storage isn't known
+pub fn public_url(storages: &[S3Config], location: &str, key: &str) -> String {
+    match find(storages, location) {
+        Some(s3) => s3.public_url(key),
+        None => format!("{}/{}", location, key),
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Client going through `proxy` if it's set, like http://127.0.0.1:7890 or socks5://host:port
+fn client(proxy: Option<&str>) -> Result<reqwest::Client, reqwest::Error> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    builder.build()
+}
+
+/// Authorization header of AWS Signature Version 4 for a request at `now`, signing host,
+/// payload hash and date headers
+fn sign(
+    config: &S3Config,
+    method: &Method,
+    path: &str,
+    host: &str,
+    payload_hash: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method.as_str(),
+        path,
+        host,
+        payload_hash,
+        amz_date,
+        signed_headers,
+        payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut signing_key = hmac_sha256(format!("AWS4{}", config.secret_key).as_bytes(), &date);
+    for part in [config.region.as_str(), "s3", "aws4_request"] {
+        signing_key = hmac_sha256(&signing_key, part);
+    }
+    let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key, scope, signed_headers, signature
+    )
+}
+
+/// Send a request signed with AWS Signature Version 4, payload is signed as well
+async fn request(
+    config: &S3Config,
+    proxy: Option<&str>,
+    method: Method,
+    key: &str,
+    body: Vec<u8>,
+) -> Result<Vec<u8>, OffloadError> {
+    let url_str = config.object_url(key);
+    let url = Url::parse(&url_str).map_err(|_| OffloadError::InvalidUrl { url: url_str })?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        _ => {
+            return Err(OffloadError::InvalidUrl {
+                url: url.to_string(),
+            })
+        }
+    };
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    let authorization = sign(config, &method, url.path(), &host, &payload_hash, now);
+    let resp = client(proxy)?
+        .request(method, url)
+        .header("x-amz-date", amz_date)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("Authorization", authorization)
+        .body(body)
+        .send()
+        .await?;
+    let status = resp.status();
+    let content = resp.bytes().await?.to_vec();
+    if !status.is_success() {
+        return Err(OffloadError::StatusError {
+            status: status.as_u16(),
+            body: String::from_utf8_lossy(&content).to_string(),
+        });
+    }
+    Ok(content)
+}
+
+pub async fn put(
+    config: &S3Config,
+    proxy: Option<&str>,
+    key: &str,
+    body: Vec<u8>,
+) -> Result<(), OffloadError> {
+    request(config, proxy, Method::PUT, key, body)
+        .await
+        .map(|_| ())
+}
+
+pub async fn get(
+    config: &S3Config,
+    proxy: Option<&str>,
+    key: &str,
+) -> Result<Vec<u8>, OffloadError> {
+    request(config, proxy, Method::GET, key, Vec::new()).await
+}
+
+pub async fn delete(config: &S3Config, proxy: Option<&str>, key: &str) -> Result<(), OffloadError> {
+    request(config, proxy, Method::DELETE, key, Vec::new())
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn storage() -> S3Config {
+        S3Config {
+            endpoint: "https://s3.example.com".into(),
+            region: "us-east-1".into(),
+            bucket: "replay".into(),
+            access_key: "AKIDEXAMPLE".into(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            public_url: None,
+        }
+    }
+
+    #[test]
+    fn sign_matches_sigv4() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap();
+        let payload_hash = hex::encode(Sha256::digest(b""));
+        let authorization = sign(
+            &storage(),
+            &Method::GET,
+            "/replay/1/2/a.m4s",
+            "s3.example.com",
+            &payload_hash,
+            now,
+        );
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240105/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=e41ade65de469dc43a41661b133c77e8031979fbe2753f2bd82b81777fc18513"
+        );
+    }
+
+    #[test]
+    fn public_url_prefers_base() {
+        let mut s3 = storage();
+        assert_eq!(
+            s3.public_url("1/2/a.m4s"),
+            "https://s3.example.com/replay/1/2/a.m4s"
+        );
+        s3.public_url = Some("https://cdn.example.com/".into());
+        assert_eq!(
+            s3.public_url("1/2/a.m4s"),
+            "https://cdn.example.com/1/2/a.m4s"
+        );
+    }
+}
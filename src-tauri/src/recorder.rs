@@ -1,27 +1,46 @@
+pub mod backoff;
 pub mod bilibili;
 pub mod danmu;
+pub mod ratelimit;
+pub mod writer;
 use async_std::{fs, stream::StreamExt};
+use backoff::{Backoff, BackoffPolicy};
 use bilibili::{errors::BiliClientError, RoomInfo};
-use bilibili::{BiliClient, BiliStream, StreamType, UserInfo};
-use chrono::{TimeZone, Utc};
+use bilibili::{BiliClient, BiliStream, StreamCodec, StreamType, UserInfo, QN_ORIGINAL};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
 use custom_error::custom_error;
-use danmu::{DanmuEntry, DanmuStorage};
+use danmu::heatmap::{HeatmapBucket, Highlight};
+use danmu::keyword::KeywordMatcher;
+use danmu::translate::{self, TranslateError};
+use danmu::{collapse, export, heatmap, resync, DanmuEntry, DanmuStorage};
 use dashmap::DashMap;
 use felgens::{ws_socket_object, FelgensError, WsStreamMessageType};
 use m3u8_rs::Playlist;
-use rand::Rng;
+use ratelimit::RateLimiter;
 use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tauri::{AppHandle, Emitter, Url};
-use tauri_plugin_notification::NotificationExt;
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tokio::sync::{Mutex, RwLock};
+use writer::WritePolicy;
 
-use crate::database::{account::AccountRow, record::RecordRow, Database, DatabaseError};
+use crate::database::{
+    account::AccountRow, interaction::InteractionRow, record::RecordRow,
+    statistics::DanmuStatisticRow, Database, DatabaseError,
+};
+use crate::deeplink::DeepLink;
+use crate::ffmpeg::{self, ArchiveProfile, AudioFormat, Container, Watermark};
+use crate::library;
+use crate::locale::MessageKey;
+use crate::nfo::Nfo;
+use crate::notifier;
+use crate::offload::{self, OffloadError, S3Config};
 use crate::Config;
 
 #[derive(Clone)]
@@ -31,6 +50,317 @@ pub struct TsEntry {
     pub sequence: u64,
     pub length: f64,
     pub size: u64,
+    /// location of offload storage it's moved into, see `S3Config::location`; no local file then
+    pub remote: Option<String>,
+}
+
+/// Events raised by recorder while recording
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum RecorderEvent {
+    /// offset is relative to playback start, ts is the danmu time in ms,
+    /// link opens the record at the hit
+    KeywordHit {
+        room_id: u64,
+        live_id: u64,
+        pattern: String,
+        content: String,
+        offset: f64,
+        ts: u64,
+        link: String,
+    },
+}
+
+/// Online count is saved at most once in this many seconds
+const METRICS_INTERVAL: i64 = 60;
+
+/// Seconds of new segments collected before they are transcoded into a monitor chunk
+const MONITOR_CHUNK_SECS: u64 = 60;
+
+/// Archives merged into one must be apart for at most this many seconds
+const MERGE_MAX_GAP_SECS: i64 = 30 * 60;
+
+/// Segments at the tail of an archive decoded by repair, crashes break the last ones written
+const REPAIR_TAIL_SEGMENTS: usize = 10;
+
+/// Compressed archive may be shorter than original by this many seconds, or it's not trusted
+const COMPRESS_LENGTH_TOLERANCE: f64 = 5.0;
+
+/// Segments of compressed archives are this long, a keyframe for every archive segment would
+/// cost most of the saving; clips of them are cut at these boundaries
+const COMPRESS_SEGMENT_SECS: u64 = 10;
+
+/// Segments are offloaded once in this many seconds
+const OFFLOAD_CHECK_SECS: u64 = 30;
+
+/// Segments within this many ms from the newest one stay local,
+/// monitor and filler checks still read them
+const OFFLOAD_KEEP_MS: u64 = 3 * 60 * 1000;
+
+/// Lines of `{file}:{size}` in work dir, one for each offloaded segment
+const OFFLOAD_INDEX: &str = "offloaded.txt";
+
+/// Contact sheets of a live in progress are reused for this long, so looking through them
+/// doesn't decode new thumbnails on every request
+const LIVE_SHEET_TTL: Duration = Duration::from_secs(60);
+
+/// Filler is checked once in this many seconds
+const FILLER_CHECK_SECS: u64 = 60;
+
+/// Snapshot interval is checked once in this many seconds
+const SNAPSHOT_CHECK_SECS: u64 = 60;
+
+/// Room is considered idle when no danmu arrives for this many seconds
+const FILLER_QUIET_SECS: i64 = 300;
+
+/// Segments of this many seconds at the tail are checked for a static frame
+const FILLER_SAMPLE_SECS: f64 = 10.0;
+
+/// Room status is polled once in this many seconds while not recording
+const STATUS_CHECK_SECS: u64 = 10;
+
+/// Queue depths are sampled once in this many seconds
+const PIPELINE_CHECK_SECS: u64 = 10;
+
+/// Depths above these are falling behind, segments of one playlist fetch, danmu lines
+/// waiting for disk, and danmu messages received but not handled
+const PENDING_DOWNLOADS_THRESHOLD: usize = 10;
+const PENDING_WRITES_THRESHOLD: usize = 10_000;
+const DANMU_BACKLOG_THRESHOLD: usize = 1_000;
+
+/// Queue depths of recording pipeline, a depth staying high means recorder can not keep up
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct PipelineMetrics {
+    /// new segments found in the latest playlist fetch
+    pub pending_downloads: usize,
+    /// danmu lines buffered but not written into file
+    pub pending_writes: usize,
+    /// danmu messages received from websocket but not handled yet
+    pub danmu_backlog: usize,
+}
+
+/// Written into work dir of a split live, with id of the part following it
+const PART_INDEX: &str = "next_part.txt";
+
+/// Seconds before expiry a stream url is renewed while recording
+const STREAM_RENEW_AHEAD: i64 = 60;
+/// Play url is requested at most once in this many seconds while recording
+const STREAM_REFRESH_INTERVAL: i64 = 30;
+
+/// Slate is checked once in this many seconds, a slate found covers the time since last check
+const INTERRUPTION_CHECK_SECS: f64 = 20.0;
+
+/// Seconds of the newest segments decoded by each check, slates last far longer than this
+const INTERRUPTION_SAMPLE_SECS: f64 = 5.0;
+
+/// Slate range still going on in current live, see `check_interruption`
+#[derive(Clone, Copy)]
+struct OpenInterruption {
+    live_id: u64,
+    start: f64,
+    end: f64,
+}
+
+/// Frame difference of ffmpeg scene score above which a new scene starts
+const SCENE_THRESHOLD: f64 = 0.4;
+
+/// Content of markers added by scene detection, they are replaced on every run
+const SCENE_MARKER: &str = "场景切换";
+
+/// Scenes closer than this many seconds to the previous one are dropped
+const SCENE_MIN_GAP: f64 = 30.0;
+
+/// Filler (垫片/轮播) detection of the current live, see `check_filler`
+#[derive(Default)]
+struct FillerState {
+    /// segment downloads are paused
+    paused: bool,
+    /// when recording is paused by user, which also stops segment downloads
+    manual: Option<i64>,
+    /// segments were skipped since last downloaded entry
+    gap: bool,
+    last_danmu: i64,
+}
+
+impl FillerState {
+    fn is_paused(&self) -> bool {
+        self.paused || self.manual.is_some()
+    }
+}
+
+/// Progress of monitor stream of the current live, see `update_monitor`
+#[derive(Default)]
+struct MonitorState {
+    live_id: u64,
+    /// sequence of last segment transcoded, entries may be pruned from the front meanwhile
+    last_sequence: Option<u64>,
+    chunks: Vec<f64>,
+}
+
+/// Danmu counter of the current minute, saved into danmu_statistics when minute changes
+#[derive(Clone, Copy)]
+struct DanmuMinute {
+    live_id: u64,
+    minute: u64,
+    count: i64,
+}
+
+/// Frame of a live taken while recording, offset is in seconds relative to playback start
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Snapshot {
+    pub offset: u64,
+    /// url path served by hls server
+    pub url: String,
+}
+
+/// Where archive cover comes from
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum CoverSource {
+    /// frame of the recording at offset in seconds, relative to first segment
+    Frame { offset: f64 },
+    /// data url of a pasted image, like `data:image/png;base64,...`
+    Image { data: String },
+}
+
+/// Window a room is recorded in. Days are 1 (Monday) to 7 (Sunday), empty for every day;
+/// times are local "HH:MM" and window may cross midnight, which then belongs to the start day.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct RecordWindow {
+    #[serde(default)]
+    pub days: Vec<u32>,
+    pub start: String,
+    pub end: String,
+}
+
+impl RecordWindow {
+    pub fn is_valid(&self) -> bool {
+        NaiveTime::parse_from_str(&self.start, "%H:%M").is_ok()
+            && NaiveTime::parse_from_str(&self.end, "%H:%M").is_ok()
+            && self.days.iter().all(|d| (1..=7).contains(d))
+    }
+
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let (Ok(start), Ok(end)) = (
+            NaiveTime::parse_from_str(&self.start, "%H:%M"),
+            NaiveTime::parse_from_str(&self.end, "%H:%M"),
+        ) else {
+            return false;
+        };
+        let on = |day: u32| self.days.is_empty() || self.days.contains(&day);
+        let today = now.weekday().number_from_monday();
+        let time = now.time();
+        if start <= end {
+            on(today) && start <= time && time < end
+        } else if time >= start {
+            on(today)
+        } else if time < end {
+            on(if today == 1 { 7 } else { today - 1 })
+        } else {
+            false
+        }
+    }
+}
+
+/// Live is split into parts once it passes either limit, 0 or None means no limit
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct SplitLimit {
+    #[serde(default)]
+    pub max_minutes: Option<u64>,
+    #[serde(default)]
+    pub max_mb: Option<u64>,
+}
+
+impl SplitLimit {
+    fn exceeded(&self, length: f64, size: u64) -> bool {
+        self.max_minutes
+            .is_some_and(|m| m > 0 && length >= (m * 60) as f64)
+            || self
+                .max_mb
+                .is_some_and(|mb| mb > 0 && size >= mb * 1024 * 1024)
+    }
+}
+
+/// Rooms recording now and rooms live but waiting for a free slot, shared by all recorders
+/// to keep simultaneous recordings under `max_recordings`
+#[derive(Default)]
+pub struct RecordingSlots {
+    state: std::sync::Mutex<SlotsState>,
+}
+
+#[derive(Default)]
+struct SlotsState {
+    active: HashSet<u64>,
+    /// room id to priority
+    waiting: HashMap<u64, i64>,
+}
+
+impl RecordingSlots {
+    /// Take a slot for room, 0 `max` for unlimited. Room is queued if slots are full or a
+    /// waiting room has higher priority, and the highest one gets the next free slot.
+    pub fn acquire(&self, room_id: u64, priority: i64, max: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.active.contains(&room_id) {
+            return true;
+        }
+        let ahead = state
+            .waiting
+            .iter()
+            .any(|(id, p)| *id != room_id && *p > priority);
+        if max == 0 || (state.active.len() < max && !ahead) {
+            state.waiting.remove(&room_id);
+            state.active.insert(room_id);
+            return true;
+        }
+        state.waiting.insert(room_id, priority);
+        false
+    }
+
+    /// Room is not recording nor waiting anymore
+    pub fn release(&self, room_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.active.remove(&room_id);
+        state.waiting.remove(&room_id);
+    }
+
+    pub fn is_waiting(&self, room_id: u64) -> bool {
+        self.state.lock().unwrap().waiting.contains_key(&room_id)
+    }
+}
+
+/// What `repair_archive` fixed
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct RepairReport {
+    /// moved into `broken.tmp/` of the archive, nothing is deleted
+    pub removed_segments: Vec<String>,
+    pub removed_danmu_lines: usize,
+    pub length: i64,
+    pub size: i64,
+}
+
+/// Stream url currently recorded, for showing when it's going to be refreshed
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct StreamStatus {
+    pub host: String,
+    /// unix timestamp the url expires at
+    pub expire: i64,
+    /// seconds until url may be refreshed, renewal happens `STREAM_RENEW_AHEAD` seconds before
+    /// expiry
+    pub renew_in: i64,
+    /// error that caused latest reconnection
+    pub last_error: Option<String>,
+}
+
+/// How a live is kept once recorder sees it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingMode {
+    /// the whole live is archived from its start
+    #[default]
+    Full,
+    /// only the last `rolling_buffer_minutes` are kept, until record is pressed
+    /// and the buffer is promoted into a real archive
+    Rolling,
 }
 
 /// A recorder for BiliBili live streams
@@ -60,6 +390,38 @@ pub struct BiliRecorder {
     cache_size: Arc<RwLock<u64>>,
     danmu_storage: Arc<RwLock<Option<DanmuStorage>>>,
     m3u8_cache: DashMap<u64, String>,
+    keyword_matchers: Arc<RwLock<Vec<KeywordMatcher>>>,
+    danmu_minute: Arc<RwLock<Option<DanmuMinute>>>,
+    last_metric: Arc<RwLock<i64>>,
+    monitor: Arc<RwLock<MonitorState>>,
+    /// set while old segments of current live are dropped in rolling mode
+    pub buffering: Arc<RwLock<bool>>,
+    last_error: Arc<RwLock<Option<String>>>,
+    filler: Arc<RwLock<FillerState>>,
+    last_record_update: Arc<RwLock<i64>>,
+    pipeline: Arc<RwLock<PipelineMetrics>>,
+    interruption: Arc<RwLock<Option<OpenInterruption>>>,
+    /// cdn hosts switched to since the last segment came in, see `failover`
+    failovers: Arc<RwLock<usize>>,
+    /// room info requests failed in a row, status checks slow down while it fails
+    status_backoff: Arc<RwLock<Backoff>>,
+    /// when play url was last requested, in seconds
+    last_refresh: Arc<RwLock<i64>>,
+    /// qn of room setting, see `bilibili::QN_ORIGINAL`
+    pub quality: Arc<RwLock<u64>>,
+    /// qn current stream is requested with, kept for the whole live so segments match
+    stream_quality: Arc<RwLock<u64>>,
+    /// codec current stream is requested with, kept like `stream_quality`
+    stream_codec: Arc<RwLock<StreamCodec>>,
+    /// room is only recorded in these windows, always if empty
+    pub schedule: Arc<RwLock<Vec<RecordWindow>>>,
+    download_limiter: Arc<RateLimiter>,
+    /// ms taken off stream offsets once current live is split into parts
+    offset_shift: Arc<RwLock<u64>>,
+    last_snapshot: Arc<RwLock<i64>>,
+    /// rooms with higher priority take free recording slots first
+    pub priority: Arc<RwLock<i64>>,
+    slots: Arc<RecordingSlots>,
 }
 
 custom_error! {pub RecorderError
@@ -76,7 +438,13 @@ custom_error! {pub RecorderError
     InvalidDBOP {err: DatabaseError } = "Database error: {err}",
     ClientError {err: BiliClientError} = "BiliClient error: {err}",
     ClipError {err: String} = "FFMPEG error: {err}",
+    MergeError {err: String} = "Merge archives failed: {err}",
+    CompressError {err: String} = "Compress archive failed: {err}",
     IoError {err: std::io::Error} = "IO error: {err}",
+    TranslateError {err: TranslateError} = "Translate error: {err}",
+    TranslationNotFound {lang: String} = "Translated danmu not found: {lang}",
+    OffloadError {err: OffloadError} = "Offload error: {err}",
+    InvalidCover = "Cover image must be a data:image url",
 }
 
 impl From<DatabaseError> for RecorderError {
@@ -91,6 +459,12 @@ impl From<BiliClientError> for RecorderError {
     }
 }
 
+impl From<TranslateError> for RecorderError {
+    fn from(value: TranslateError) -> Self {
+        RecorderError::TranslateError { err: value }
+    }
+}
+
 impl BiliRecorder {
     pub async fn new(
         app_handle: AppHandle,
@@ -99,17 +473,30 @@ impl BiliRecorder {
         room_id: u64,
         account: &AccountRow,
         config: Arc<RwLock<Config>>,
+        slots: Arc<RecordingSlots>,
     ) -> Result<Self, RecorderError> {
-        let client = BiliClient::new()?;
+        let proxy = config.read().await.proxy_for(room_id);
+        let client = BiliClient::with_proxy(proxy.as_deref())?;
         let room_info = client.get_room_info(account, room_id).await?;
         let user_info = client
             .get_user_info(webid, account, room_info.user_id)
             .await?;
+        let quality = db
+            .get_recorder_quality(room_id)
+            .await
+            .unwrap_or(QN_ORIGINAL);
+        let codec = config.read().await.stream_codec;
+        let schedule = db.get_recorder_schedule(room_id).await.unwrap_or_default();
+        let priority = db.get_recorder_priority(room_id).await.unwrap_or(0);
         let mut live_status = false;
         let mut live_stream = None;
         if room_info.live_status == 1 {
             live_status = true;
-            if let Ok(stream) = client.get_play_url(account, room_info.room_id).await {
+            if let Ok(mut stream) = client
+                .get_play_url(account, room_info.room_id, quality, codec)
+                .await
+            {
+                stream.prefer(&db.get_cdn_ranking("bilibili").await.unwrap_or_default());
                 live_stream = Some(stream);
             } else {
                 log::error!("[{}]Room is online but fetching stream failed", room_id);
@@ -137,19 +524,162 @@ impl BiliRecorder {
             cache_size: Arc::new(RwLock::new(0)),
             danmu_storage: Arc::new(RwLock::new(None)),
             m3u8_cache: DashMap::new(),
+            keyword_matchers: Arc::new(RwLock::new(Vec::new())),
+            danmu_minute: Arc::new(RwLock::new(None)),
+            last_metric: Arc::new(RwLock::new(0)),
+            monitor: Arc::new(RwLock::new(MonitorState::default())),
+            buffering: Arc::new(RwLock::new(false)),
+            last_error: Arc::new(RwLock::new(None)),
+            last_record_update: Arc::new(RwLock::new(0)),
+            pipeline: Arc::new(RwLock::new(PipelineMetrics::default())),
+            interruption: Arc::new(RwLock::new(None)),
+            failovers: Arc::new(RwLock::new(0)),
+            status_backoff: Arc::new(RwLock::new(Backoff::default())),
+            last_refresh: Arc::new(RwLock::new(0)),
+            quality: Arc::new(RwLock::new(quality)),
+            stream_quality: Arc::new(RwLock::new(quality)),
+            stream_codec: Arc::new(RwLock::new(codec)),
+            schedule: Arc::new(RwLock::new(schedule)),
+            download_limiter: Arc::new(RateLimiter::new()),
+            offset_shift: Arc::new(RwLock::new(0)),
+            last_snapshot: Arc::new(RwLock::new(0)),
+            priority: Arc::new(RwLock::new(priority)),
+            slots,
+            filler: Arc::new(RwLock::new(FillerState {
+                last_danmu: Utc::now().timestamp(),
+                ..Default::default()
+            })),
         };
+        recorder.reset_buffering().await;
+        recorder.reload_keyword_rules().await;
         log::info!("Recorder for room {} created.", room_id);
         Ok(recorder)
     }
 
+    /// Load keyword rules of this room from db, invalid rules are skipped
+    pub async fn reload_keyword_rules(&self) {
+        match self.db.get_keyword_rules(self.room_id).await {
+            Ok(rules) => {
+                *self.keyword_matchers.write().await =
+                    rules.into_iter().filter_map(KeywordMatcher::new).collect();
+            }
+            Err(e) => {
+                log::error!("[{}]Load keyword rules failed: {}", self.room_id, e);
+            }
+        }
+    }
+
     pub async fn reset(&self) {
+        if let Err(e) = self.save_record(true).await {
+            log::error!("[{}]Save record failed: {}", self.room_id, e);
+        }
         *self.ts_length.write().await = 0.0;
         *self.last_sequence.write().await = 0;
         self.ts_entries.write().await.clear();
         *self.header.write().await = None;
         *self.timestamp.write().await = 0;
+        *self.offset_shift.write().await = 0;
+        *self.last_snapshot.write().await = 0;
         *self.last_update.write().await = Utc::now().timestamp();
+        self.flush_danmu().await;
+        self.save_danmu_minute().await;
         *self.danmu_storage.write().await = None;
+        self.reset_buffering().await;
+        *self.filler.write().await = FillerState {
+            last_danmu: Utc::now().timestamp(),
+            ..Default::default()
+        };
+    }
+
+    async fn reset_buffering(&self) {
+        *self.buffering.write().await =
+            self.config.read().await.recording_mode == RecordingMode::Rolling;
+    }
+
+    /// Stop dropping segments of current live, what is buffered becomes start of the archive
+    pub async fn promote_buffer(&self) {
+        let mut buffering = self.buffering.write().await;
+        if *buffering {
+            log::info!("[{}]Rolling buffer promoted into archive", self.room_id);
+            *buffering = false;
+        }
+    }
+
+    /// Requests of this room go through `proxy` from now on
+    pub async fn set_proxy(&self, proxy: Option<&str>) -> Result<(), RecorderError> {
+        *self.client.write().await = BiliClient::with_proxy(proxy)?;
+        log::info!("[{}]Proxy set to {:?}", self.room_id, proxy);
+        Ok(())
+    }
+
+    /// Stop segment downloads of current live, live and its work dir are kept for resuming
+    pub async fn pause(&self) {
+        let mut filler = self.filler.write().await;
+        if filler.manual.is_none() {
+            log::info!("[{}]Recording paused", self.room_id);
+            filler.manual = Some(Utc::now().timestamp());
+        }
+    }
+
+    /// Download segments again into the same live, a marker notes the skipped time
+    pub async fn resume(&self) {
+        let Some(since) = self.filler.write().await.manual.take() else {
+            return;
+        };
+        log::info!("[{}]Recording resumed", self.room_id);
+        let live_id = *self.timestamp.read().await;
+        if live_id == 0 {
+            return;
+        }
+        let now = Utc::now().timestamp();
+        let offset = *self.ts_length.read().await;
+        if let Err(e) = self
+            .db
+            .add_marker(
+                self.room_id,
+                live_id,
+                offset,
+                now,
+                &format!("录制暂停 {} 秒", now - since),
+            )
+            .await
+        {
+            log::error!("[{}]Add pause marker failed: {}", self.room_id, e);
+        }
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.filler.read().await.manual.is_some()
+    }
+
+    /// Drop segments older than rolling buffer window, when buffering
+    async fn prune_buffer(&self, work_dir: &str) {
+        if !*self.buffering.read().await {
+            return;
+        }
+        let window = self.config.read().await.rolling_buffer_minutes * 60 * 1000;
+        let mut entries = self.ts_entries.write().await;
+        let Some(last) = entries.last() else {
+            return;
+        };
+        let keep_from = last.offset.saturating_sub(window);
+        let expired = entries.partition_point(|e| e.offset < keep_from);
+        if expired == 0 {
+            return;
+        }
+        let mut freed_size = 0;
+        let mut freed_length = 0.0;
+        for e in entries.drain(..expired) {
+            if let Err(err) = fs::remove_file(format!("{}/{}", work_dir, e.url)).await {
+                log::warn!("[{}]Remove buffered segment failed: {}", self.room_id, err);
+            }
+            freed_size += e.size;
+            freed_length += e.length;
+        }
+        let mut cache_size = self.cache_size.write().await;
+        *cache_size = cache_size.saturating_sub(freed_size);
+        let mut ts_length = self.ts_length.write().await;
+        *ts_length = (*ts_length - freed_length).max(0.0);
     }
 
     async fn check_status(&self) -> bool {
@@ -161,53 +691,52 @@ impl BiliRecorder {
             .await
         {
             Ok(room_info) => {
+                self.status_backoff.write().await.reset();
                 *self.room_info.write().await = room_info.clone();
                 let live_status = room_info.live_status == 1;
+                if live_status {
+                    self.save_room_metric(room_info.online).await;
+                }
 
                 // handle live notification
                 if *self.live_status.read().await != live_status {
+                    let config = self.config.read().await;
                     if live_status {
-                        if self.config.read().await.live_start_notify {
-                            self.app_handle
-                                .notification()
-                                .builder()
-                                .title("BiliShadowReplay - 直播开始")
-                                .body(format!(
+                        if config.live_start_notify {
+                            notifier::notify(
+                                &self.app_handle,
+                                &config,
+                                Some(self.room_id),
+                                "BiliShadowReplay - 直播开始",
+                                &format!(
                                     "{} 开启了直播：{}",
                                     self.user_info.read().await.user_name,
                                     room_info.room_title
-                                ))
-                                .show()
-                                .unwrap();
+                                ),
+                            );
                         }
-                    } else if self.config.read().await.live_end_notify {
-                        self.app_handle
-                            .notification()
-                            .builder()
-                            .title("BiliShadowReplay - 直播结束")
-                            .body(format!(
-                                "{} 的直播结束了",
-                                self.user_info.read().await.user_name
-                            ))
-                            .show()
-                            .unwrap();
+                    } else if config.live_end_notify {
+                        notifier::notify(
+                            &self.app_handle,
+                            &config,
+                            Some(self.room_id),
+                            "BiliShadowReplay - 直播结束",
+                            &format!("{} 的直播结束了", self.user_info.read().await.user_name),
+                        );
                     }
                 }
 
                 // if stream is confirmed to be closed, live stream cache is cleaned.
                 // all request will go through fs
                 if live_status {
-                    let mut rng = rand::thread_rng();
-                    // WHY: when program started, all stream is fetched nearly at the same time, so they will expire toggether,
-                    // this might meet server rate limit. So we add a random offset to make request spread over time.
-                    let offset = rng.gen_range(5..=120);
+                    // renewals of rooms started together are spread by the jittered status polls
                     // no need to update stream as it's not expired yet
                     if self
                         .live_stream
                         .read()
                         .await
                         .as_ref()
-                        .is_some_and(|s| s.expire - offset > Utc::now().timestamp())
+                        .is_some_and(|s| s.expire - STREAM_RENEW_AHEAD > Utc::now().timestamp())
                     {
                         return live_status;
                     }
@@ -215,21 +744,7 @@ impl BiliRecorder {
                         "[{}]Stream is empty or nearly expired, updating",
                         self.room_id
                     );
-                    match self
-                        .client
-                        .read()
-                        .await
-                        .get_play_url(&self.account, self.room_id)
-                        .await
-                    {
-                        Ok(stream) => {
-                            log::info!("[{}]Update stream: {:?}", self.room_id, stream);
-                            *self.live_stream.write().await = Some(stream);
-                        }
-                        Err(e) => {
-                            log::error!("[{}]Update stream failed: {}", self.room_id, e);
-                        }
-                    }
+                    self.refresh_stream().await;
                 } else {
                     self.reset().await;
                 }
@@ -237,13 +752,36 @@ impl BiliRecorder {
                 live_status
             }
             Err(e) => {
-                log::error!("[{}]Update room status failed: {}", self.room_id, e);
+                let mut backoff = self.status_backoff.write().await;
+                backoff.fail();
+                log::error!(
+                    "[{}]Update room status failed {} times: {}",
+                    self.room_id,
+                    backoff.failures(),
+                    e
+                );
                 // may encouter internet issues, not sure whether the stream is closed or started, just remain
                 *self.live_status.read().await
             }
         }
     }
 
+    pub async fn stream_status(&self) -> Option<StreamStatus> {
+        let stream = self.live_stream.read().await.clone()?;
+        Some(StreamStatus {
+            host: stream.host,
+            expire: stream.expire,
+            renew_in: (stream.expire - STREAM_RENEW_AHEAD - Utc::now().timestamp()).max(0),
+            last_error: self.last_error.read().await.clone(),
+        })
+    }
+
+    /// Speedtest on all cdn hosts of current stream, None if room is not streaming
+    pub async fn cdn_speedtest(&self) -> Option<Vec<(String, u64)>> {
+        let stream = self.live_stream.read().await.clone()?;
+        Some(self.client.read().await.cdn_speedtest(&stream).await)
+    }
+
     pub async fn get_archives(&self) -> Result<Vec<RecordRow>, RecorderError> {
         Ok(self.db.get_records(self.room_id).await?)
     }
@@ -261,70 +799,1560 @@ impl BiliRecorder {
             return Err(e.into());
         }
         let target_dir = format!("{}/{}/{}", self.config.read().await.cache, self.room_id, ts);
+        let (storages, proxy) = {
+            let config = self.config.read().await;
+            (config.offload_storages(), config.proxy_for(self.room_id))
+        };
+        for e in self.get_fs_entries(&target_dir).await {
+            let Some(location) = &e.remote else {
+                continue;
+            };
+            let key = format!("{}/{}/{}", self.room_id, ts, e.url);
+            let Some(s3) = offload::find(&storages, location) else {
+                log::warn!(
+                    "[{}]Offloaded {} is left in {}, storage is not configured",
+                    self.room_id,
+                    key,
+                    location
+                );
+                continue;
+            };
+            if let Err(err) = offload::delete(s3, proxy.as_deref(), &key).await {
+                log::warn!("[{}]Delete offloaded {} failed: {}", self.room_id, key, err);
+            }
+        }
         if let Err(e) = fs::remove_dir_all(target_dir).await {
             log::error!("remove archive failed [{}]{}: {}", self.room_id, ts, e);
             return Err(RecorderError::IoError { err: e });
         }
-        Ok(())
+        Ok(())
+    }
+
+    /// Save cover of an archive as cover.jpg in its work dir, which is served by hls server.
+    /// Returns url path of the cover, which is also kept in the record.
+    pub async fn set_archive_cover(
+        &self,
+        live_id: u64,
+        source: CoverSource,
+    ) -> Result<String, RecorderError> {
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let output = format!("{}/cover.jpg", work_dir);
+        let (input, temp) = match source {
+            CoverSource::Frame { offset } => {
+                let entries = if *self.timestamp.read().await == live_id {
+                    self.ts_entries.read().await.clone()
+                } else {
+                    self.get_fs_entries(&work_dir).await
+                };
+                let first = entries.first().ok_or(RecorderError::EmptyCache)?.offset;
+                let target = first + (offset.max(0.0) * 1000.0) as u64;
+                let index = entries
+                    .partition_point(|e| e.offset <= target)
+                    .saturating_sub(1);
+                let file_list = vec![
+                    format!("{}/h{}.m4s", work_dir, live_id),
+                    format!("{}/{}", work_dir, entries[index].url),
+                ];
+                let fetched = self.fetch_offloaded(&file_list).await?;
+                let input = Self::generate_clip(&file_list, &work_dir, "cover.tmp").await;
+                for f in fetched {
+                    let _ = fs::remove_file(f).await;
+                }
+                let input = input?;
+                (input.clone(), Some(input))
+            }
+            // anything else would have ffmpeg read local files or urls
+            CoverSource::Image { data } if data.starts_with("data:image/") => (data, None),
+            CoverSource::Image { .. } => return Err(RecorderError::InvalidCover),
+        };
+        let output_clone = output.clone();
+        let result = ffmpeg::execute(move || ffmpeg::snapshot(&input, &output_clone)).await;
+        if let Some(temp) = temp {
+            let _ = tokio::fs::remove_file(temp).await;
+        }
+        result.map_err(|err| RecorderError::ClipError { err })?;
+        let cover = format!("/{}/{}/cover.jpg", self.room_id, live_id);
+        self.db.set_record_cover(live_id, &cover).await?;
+        Ok(cover)
+    }
+
+    /// Fix an archive interrupted by a crash or full disk: empty and undecodable segments at
+    /// the tail are moved into `broken.tmp/`, broken danmu lines dropped, leftover temp files
+    /// cleaned, and length and size recalculated. Playlist is regenerated on next request.
+    /// Segments are fragments named by their offsets, so kept ones are not remuxed and there
+    /// are no timestamps to rewrite; a segment is dropped only if ffmpeg ran and failed to
+    /// decode it, repair stops if ffmpeg can't run or the header doesn't decode.
+    pub async fn repair_archive(&self, live_id: u64) -> Result<RepairReport, RecorderError> {
+        if live_id == *self.timestamp.read().await {
+            return Err(RecorderError::ArchiveInUse { ts: live_id });
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let mut report = RepairReport::default();
+        for temp in [
+            "monitor.tmp",
+            "filler.tmp",
+            "cover.tmp",
+            "repair.tmp",
+            "compress.tmp",
+            "slate.tmp",
+            "snapshot.tmp",
+            "scene.tmp",
+            "export.tmp",
+            "export.tmp.chapters.mp4",
+        ] {
+            let _ = fs::remove_file(format!("{}/{}", work_dir, temp)).await;
+        }
+        self.remove_package(live_id).await;
+        for temp_dir in ["contact.tmp", "compressed.tmp"] {
+            let _ = fs::remove_dir_all(format!("{}/{}", work_dir, temp_dir)).await;
+        }
+
+        let broken_dir = format!("{}/broken.tmp", work_dir);
+        let mut entries = self.get_fs_entries(&work_dir).await;
+        let mut empty = Vec::new();
+        entries.retain(|e| {
+            if e.size > 0 || e.remote.is_some() {
+                return true;
+            }
+            empty.push(e.url.clone());
+            false
+        });
+        for url in empty {
+            self.move_broken(&work_dir, &broken_dir, &url).await?;
+            report.removed_segments.push(url);
+        }
+        // everything fails with a broken header, that's not for repair to guess at
+        if let Some(first) = entries.iter().find(|e| e.remote.is_none()) {
+            if !self.segment_decodes(&work_dir, live_id, first).await? {
+                return Err(RecorderError::ClipError {
+                    err: format!("header or first segment {} does not decode", first.url),
+                });
+            }
+        }
+        // from the end, stop at the first segment that decodes
+        let tail_start = entries.len().saturating_sub(REPAIR_TAIL_SEGMENTS).max(1);
+        while entries.len() > tail_start {
+            let e = entries.last().unwrap().clone();
+            // offloaded ones were finished before upload
+            if e.remote.is_some() {
+                break;
+            }
+            if self.segment_decodes(&work_dir, live_id, &e).await? {
+                break;
+            }
+            log::warn!("[{}]Move broken segment {} aside", self.room_id, e.url);
+            self.move_broken(&work_dir, &broken_dir, &e.url).await?;
+            report.removed_segments.push(e.url);
+            entries.pop();
+        }
+
+        let danmu_file = format!("{}/danmu.txt", work_dir);
+        if let Ok(content) = tokio::fs::read(&danmu_file).await {
+            let content = String::from_utf8_lossy(&content);
+            let mut fixed = String::new();
+            for line in content.lines() {
+                let valid = line
+                    .split_once(':')
+                    .is_some_and(|(ts, _)| ts.parse::<u64>().is_ok());
+                if valid {
+                    fixed += line;
+                    fixed.push('\n');
+                } else {
+                    report.removed_danmu_lines += 1;
+                }
+            }
+            if report.removed_danmu_lines > 0 {
+                if let Err(e) = tokio::fs::write(&danmu_file, fixed).await {
+                    return Err(RecorderError::IoError { err: e });
+                }
+            }
+        }
+
+        report.length = entries.iter().map(|e| e.length).sum::<f64>() as i64;
+        report.size = entries.iter().map(|e| e.size).sum::<u64>() as i64;
+        self.db
+            .update_record(live_id, report.length, report.size as u64)
+            .await?;
+        self.m3u8_cache.remove(&live_id);
+        log::info!(
+            "[{}]Archive {} repaired: {:?}",
+            self.room_id,
+            live_id,
+            report
+        );
+        Ok(report)
+    }
+
+    /// Re-encode an archive with a smaller profile to save storage. Segments are rebuilt
+    /// in the archive naming after all of them decode, originals are moved aside and deleted
+    /// only once the swap is done, any failure before that puts them back.
+    pub async fn compress_archive(
+        &self,
+        live_id: u64,
+        profile: &ArchiveProfile,
+    ) -> Result<RecordRow, RecorderError> {
+        if live_id == *self.timestamp.read().await {
+            return Err(RecorderError::ArchiveInUse { ts: live_id });
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let entries = self.get_fs_entries(&work_dir).await;
+        if entries.is_empty() {
+            return Err(RecorderError::EmptyCache);
+        }
+        if entries.iter().any(|e| e.remote.is_some()) {
+            return Err(RecorderError::CompressError {
+                err: "archive is offloaded".into(),
+            });
+        }
+        // one continuous encode can not keep gaps of timeline, markers would drift
+        if entries
+            .windows(2)
+            .any(|w| w[1].sequence - w[0].sequence > 1)
+        {
+            return Err(RecorderError::CompressError {
+                err: "archive has discontinuities".into(),
+            });
+        }
+        let header = format!("{}/h{}.m4s", work_dir, live_id);
+        let mut file_list = vec![header.clone()];
+        file_list.extend(entries.iter().map(|e| format!("{}/{}", work_dir, e.url)));
+        self.remove_package(live_id).await;
+        let input = Self::generate_clip(&file_list, &work_dir, "compress.tmp").await?;
+        let out_dir = format!("{}/compressed.tmp", work_dir);
+        let _ = fs::remove_dir_all(&out_dir).await;
+        if let Err(e) = fs::create_dir_all(&out_dir).await {
+            let _ = fs::remove_file(&input).await;
+            return Err(RecorderError::IoError { err: e });
+        }
+        let (input_clone, out_dir_clone, profile) =
+            (input.clone(), out_dir.clone(), profile.clone());
+        let result = ffmpeg::execute_long(move || {
+            ffmpeg::encode_hls(
+                &input_clone,
+                &out_dir_clone,
+                &profile,
+                COMPRESS_SEGMENT_SECS,
+            )
+        })
+        .await;
+        let _ = fs::remove_file(&input).await;
+        let result = match result {
+            Ok(_) => {
+                self.replace_with_compressed(&work_dir, live_id, &entries)
+                    .await
+            }
+            Err(err) => Err(RecorderError::CompressError { err }),
+        };
+        let _ = fs::remove_dir_all(&out_dir).await;
+        let size = result?;
+        let record = self.db.get_record(self.room_id, live_id).await?;
+        self.db.update_record(live_id, record.length, size).await?;
+        self.m3u8_cache.remove(&live_id);
+        log::info!(
+            "[{}]Archive {} compressed: {} -> {} bytes",
+            self.room_id,
+            live_id,
+            record.size,
+            size
+        );
+        Ok(self.db.get_record(self.room_id, live_id).await?)
+    }
+
+    /// Swap segments of archive with encoded ones in `compressed.tmp`, returns the new size
+    async fn replace_with_compressed(
+        &self,
+        work_dir: &str,
+        live_id: u64,
+        entries: &[TsEntry],
+    ) -> Result<u64, RecorderError> {
+        let out_dir = format!("{}/compressed.tmp", work_dir);
+        let index = fs::read(format!("{}/index.m3u8", out_dir))
+            .await
+            .map_err(|e| RecorderError::IoError { err: e })?;
+        let playlist = match m3u8_rs::parse_media_playlist_res(&index) {
+            Ok(playlist) => playlist,
+            Err(_) => {
+                return Err(RecorderError::M3u8ParseFailed {
+                    content: String::from_utf8_lossy(&index).to_string(),
+                })
+            }
+        };
+        let original_length = entries.iter().map(|e| e.length).sum::<f64>();
+        let length = playlist
+            .segments
+            .iter()
+            .map(|s| s.duration as f64)
+            .sum::<f64>();
+        if playlist.segments.is_empty() || length + COMPRESS_LENGTH_TOLERANCE < original_length {
+            return Err(RecorderError::CompressError {
+                err: format!("encoded {:.0}s of {:.0}s", length, original_length),
+            });
+        }
+        let mut encoded = vec![format!("{}/init.m4s", out_dir)];
+        encoded.extend(
+            playlist
+                .segments
+                .iter()
+                .map(|s| format!("{}/{}", out_dir, s.uri)),
+        );
+        self.verify_compressed(work_dir, &encoded).await?;
+
+        let header = format!("{}/h{}.m4s", work_dir, live_id);
+        let backup_dir = format!("{}/original.tmp", work_dir);
+        let mut moved = Vec::new();
+        let mut result = fs::create_dir_all(&backup_dir).await;
+        let mut originals = vec![format!("h{}.m4s", live_id)];
+        originals.extend(entries.iter().map(|e| e.url.clone()));
+        for name in originals.iter() {
+            if result.is_err() {
+                break;
+            }
+            result = fs::rename(
+                format!("{}/{}", work_dir, name),
+                format!("{}/{}", backup_dir, name),
+            )
+            .await;
+            if result.is_ok() {
+                moved.push(name.clone());
+            }
+        }
+        // offsets continue from the first original one, as markers and danmu refer to them
+        let mut offset = entries.first().unwrap().offset;
+        let mut size = 0;
+        let mut created = Vec::new();
+        if result.is_ok() {
+            result = fs::rename(format!("{}/init.m4s", out_dir), &header).await;
+        }
+        for (i, segment) in playlist.segments.iter().enumerate() {
+            if result.is_err() {
+                break;
+            }
+            let file_name = format!("{:x}-{}.m4s", offset, i + 1);
+            let target = format!("{}/{}", work_dir, file_name);
+            result = fs::rename(format!("{}/{}", out_dir, segment.uri), &target).await;
+            if let Ok(meta) = fs::metadata(&target).await {
+                size += meta.len();
+                created.push(target);
+            }
+            offset += (segment.duration as f64 * 1000.0) as u64;
+        }
+        if let Err(e) = result {
+            let e = RecorderError::IoError { err: e };
+            log::error!(
+                "[{}]Compress archive {} failed, restore originals: {}",
+                self.room_id,
+                live_id,
+                e
+            );
+            for file in created.iter() {
+                let _ = fs::remove_file(file).await;
+            }
+            for name in moved.iter() {
+                let _ = fs::rename(
+                    format!("{}/{}", backup_dir, name),
+                    format!("{}/{}", work_dir, name),
+                )
+                .await;
+            }
+            let _ = fs::remove_dir_all(&backup_dir).await;
+            return Err(e);
+        }
+        if let Err(e) = fs::remove_dir_all(&backup_dir).await {
+            log::warn!("[{}]Remove original segments failed: {}", self.room_id, e);
+        }
+        Ok(size)
+    }
+
+    /// Whether segment decodes with its header, errors if ffmpeg can't tell
+    async fn segment_decodes(
+        &self,
+        work_dir: &str,
+        live_id: u64,
+        entry: &TsEntry,
+    ) -> Result<bool, RecorderError> {
+        let header = Self::header_of(work_dir, live_id, entry.sequence).await;
+        if let Err(e) = fs::metadata(&header).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        let file_list = vec![header, format!("{}/{}", work_dir, entry.url)];
+        let input = Self::generate_clip(&file_list, work_dir, "repair.tmp").await?;
+        let input_clone = input.clone();
+        let result = ffmpeg::execute(move || ffmpeg::verify(&input_clone)).await;
+        let _ = tokio::fs::remove_file(&input).await;
+        result.map_err(|err| RecorderError::ClipError { err })
+    }
+
+    /// Segments dropped by repair are kept in `broken_dir` rather than deleted
+    async fn move_broken(
+        &self,
+        work_dir: &str,
+        broken_dir: &str,
+        name: &str,
+    ) -> Result<(), RecorderError> {
+        fs::create_dir_all(broken_dir)
+            .await
+            .map_err(|e| RecorderError::IoError { err: e })?;
+        fs::rename(
+            format!("{}/{}", work_dir, name),
+            format!("{}/{}", broken_dir, name),
+        )
+        .await
+        .map_err(|e| RecorderError::IoError { err: e })
+    }
+
+    /// Every encoded segment must decode with the new header before originals are touched
+    async fn verify_compressed(
+        &self,
+        work_dir: &str,
+        file_list: &[String],
+    ) -> Result<(), RecorderError> {
+        let check = Self::generate_clip(&file_list.to_vec(), work_dir, "compress.tmp").await?;
+        let check_clone = check.clone();
+        let verified = ffmpeg::execute_long(move || ffmpeg::verify(&check_clone)).await;
+        let _ = fs::remove_file(&check).await;
+        match verified {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(RecorderError::CompressError {
+                err: "encoded segments do not decode".into(),
+            }),
+            Err(err) => Err(RecorderError::CompressError { err }),
+        }
+    }
+
+    /// Merge consecutive archives of a stream that dropped and restarted into the earliest one.
+    /// Segments are renamed into its work dir with shifted offsets and a sequence gap, so
+    /// playlist gets a discontinuity at every joint. Headers of later parts are kept as
+    /// `h{live_id}-{sequence}.m4s`, starting from the segment of that sequence.
+    pub async fn merge_archives(&self, mut live_ids: Vec<u64>) -> Result<RecordRow, RecorderError> {
+        live_ids.sort();
+        live_ids.dedup();
+        if live_ids.len() < 2 {
+            return Err(RecorderError::MergeError {
+                err: "at least two archives are needed".into(),
+            });
+        }
+        let current = *self.timestamp.read().await;
+        if live_ids.contains(&current) {
+            return Err(RecorderError::ArchiveInUse { ts: current });
+        }
+        let mut records = Vec::new();
+        for live_id in live_ids.iter() {
+            records.push(self.db.get_record(self.room_id, *live_id).await?);
+        }
+        for pair in records.windows(2) {
+            let gap = pair[1].live_id as i64 - (pair[0].live_id as i64 + pair[0].length);
+            if gap > MERGE_MAX_GAP_SECS {
+                return Err(RecorderError::MergeError {
+                    err: format!(
+                        "{} and {} are {}s apart",
+                        pair[0].live_id, pair[1].live_id, gap
+                    ),
+                });
+            }
+        }
+        self.remove_package(live_ids[0]).await;
+        let cache = self.config.read().await.cache.clone();
+        let dir_of = |live_id: u64| format!("{}/{}/{}", cache, self.room_id, live_id);
+        // parts are clipped together, so their streams must match
+        let mut infos = Vec::new();
+        for live_id in live_ids.iter() {
+            let header = format!("{}/h{}.m4s", dir_of(*live_id), live_id);
+            let info = library::probe(&self.db, &header)
+                .await
+                .map_err(|err| RecorderError::ClipError { err })?;
+            infos.push(info);
+        }
+        let base = &infos[0];
+        for (info, live_id) in infos.iter().zip(live_ids.iter()).skip(1) {
+            if info.codec != base.codec || info.width != base.width || info.height != base.height {
+                return Err(RecorderError::MergeError {
+                    err: format!(
+                        "stream of {} is {} {}x{}, not {} {}x{}",
+                        live_id,
+                        info.codec,
+                        info.width,
+                        info.height,
+                        base.codec,
+                        base.width,
+                        base.height
+                    ),
+                });
+            }
+        }
+
+        // offloaded segments can not be renamed into another archive
+        for live_id in live_ids.iter().skip(1) {
+            if self
+                .get_fs_entries(&dir_of(*live_id))
+                .await
+                .iter()
+                .any(|e| e.remote.is_some())
+            {
+                return Err(RecorderError::MergeError {
+                    err: format!("{} is offloaded", live_id),
+                });
+            }
+        }
+        let target = live_ids[0];
+        let target_dir = dir_of(target);
+        // renames done before a failing step are undone, so parts stay as they were
+        let danmu_file = format!("{}/danmu.txt", target_dir);
+        let danmu_len = fs::metadata(&danmu_file).await.map(|m| m.len()).ok();
+        let mut moved = Vec::new();
+        let result = match self
+            .move_parts(&records, &cache, &target_dir, &mut moved)
+            .await
+        {
+            Ok(()) => self.move_part_data(&records, target).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            for (from, to) in moved.iter().rev() {
+                if let Err(e) = fs::rename(to, from).await {
+                    log::error!("[{}]Move {} back failed: {}", self.room_id, to, e);
+                }
+            }
+            // danmu of parts appended to target are cut off again
+            match danmu_len {
+                Some(len) => {
+                    if let Ok(file) = OpenOptions::new().write(true).open(&danmu_file).await {
+                        let _ = file.set_len(len).await;
+                    }
+                }
+                None => {
+                    let _ = fs::remove_file(&danmu_file).await;
+                }
+            }
+            return Err(e);
+        }
+        let room_id = self.room_id;
+        for record in records.iter().skip(1) {
+            let from = record.live_id;
+            self.db.remove_record(from).await?;
+            if let Err(e) = fs::remove_dir_all(dir_of(from)).await {
+                log::warn!("[{}]Remove merged archive {} failed: {}", room_id, from, e);
+            }
+            self.m3u8_cache.remove(&from);
+        }
+        let last = records.last().unwrap();
+        let length = (last.live_id - target) as i64 + last.length;
+        let size = records.iter().map(|r| r.size).sum::<i64>();
+        self.db.update_record(target, length, size as u64).await?;
+        self.m3u8_cache.remove(&target);
+        log::info!(
+            "[{}]Archives {:?} merged into {}",
+            self.room_id,
+            live_ids,
+            target
+        );
+        Ok(self.db.get_record(self.room_id, target).await?)
+    }
+
+    /// Move segments, headers and danmu of parts after the first into `target_dir`, every
+    /// rename done is pushed into `moved` as (from, to)
+    async fn move_parts(
+        &self,
+        records: &[RecordRow],
+        cache: &str,
+        target_dir: &str,
+        moved: &mut Vec<(String, String)>,
+    ) -> Result<(), RecorderError> {
+        let target = records[0].live_id;
+        let entries = self.get_fs_entries(target_dir).await;
+        let mut next_sequence = entries.last().map_or(0, |e| e.sequence) + 2;
+        for record in records.iter().skip(1) {
+            let dir = format!("{}/{}/{}", cache, self.room_id, record.live_id);
+            let shift = (record.live_id - target) * 1000;
+            let source_entries = self.get_fs_entries(&dir).await;
+            let first_sequence = source_entries.first().map_or(0, |e| e.sequence);
+            let mut headers = vec![(first_sequence, format!("h{}.m4s", record.live_id))];
+            headers.extend(Self::get_part_headers(&dir, record.live_id).await);
+            for (sequence, header) in headers.iter() {
+                let sequence = next_sequence + sequence - first_sequence;
+                let from = format!("{}/{}", dir, header);
+                let to = format!("{}/h{}-{}.m4s", target_dir, target, sequence);
+                fs::rename(&from, &to)
+                    .await
+                    .map_err(|err| RecorderError::IoError { err })?;
+                moved.push((from, to));
+            }
+            for e in source_entries.iter() {
+                let sequence = next_sequence + e.sequence - first_sequence;
+                let file_name = format!("{:x}-{}.m4s", e.offset + shift, sequence);
+                let from = format!("{}/{}", dir, e.url);
+                let to = format!("{}/{}", target_dir, file_name);
+                fs::rename(&from, &to)
+                    .await
+                    .map_err(|err| RecorderError::IoError { err })?;
+                moved.push((from, to));
+            }
+            next_sequence += source_entries
+                .last()
+                .map_or(0, |e| e.sequence - first_sequence)
+                + 2;
+            // danmu lines carry wall clock timestamps, they are appended as is
+            if let Ok(content) = tokio::fs::read(format!("{}/danmu.txt", dir)).await {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(format!("{}/danmu.txt", target_dir))
+                    .await;
+                if let Err(e) = match file {
+                    Ok(mut file) => file.write_all(&content).await,
+                    Err(e) => Err(e),
+                } {
+                    return Err(RecorderError::IoError { err: e });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move db rows of parts after the first into target, all of them or none
+    async fn move_part_data(
+        &self,
+        records: &[RecordRow],
+        target: u64,
+    ) -> Result<(), RecorderError> {
+        let room_id = self.room_id;
+        let mut tx = self.db.begin().await?;
+        for record in records.iter().skip(1) {
+            let from = record.live_id;
+            let shift = (from - target) as f64;
+            self.db
+                .move_markers(&mut tx, room_id, from, target, shift)
+                .await?;
+            self.db
+                .move_interruptions(&mut tx, room_id, from, target, shift)
+                .await?;
+            self.db
+                .move_danmu_statistics(&mut tx, room_id, from, target)
+                .await?;
+            self.db
+                .move_room_metrics(&mut tx, room_id, from, target)
+                .await?;
+            self.db
+                .move_interactions(&mut tx, room_id, from, target)
+                .await?;
+        }
+        tx.commit().await.map_err(DatabaseError::from)?;
+        Ok(())
+    }
+
+    pub async fn run(&self) {
+        let self_clone = self.clone();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                // playlist errors in a row, kept over status checks until entries update again
+                let mut playlist_backoff = Backoff::default();
+                while !*self_clone.quit.lock().await {
+                    // status is still checked out of schedule, only recording is skipped
+                    let should_record =
+                        self_clone.check_status().await && self_clone.in_schedule().await;
+                    if !should_record {
+                        self_clone.slots.release(self_clone.room_id);
+                    }
+                    if should_record && self_clone.acquire_slot().await {
+                        // Live status is ok, start recording.
+                        while !*self_clone.quit.lock().await {
+                            if !self_clone.in_schedule().await {
+                                log::info!(
+                                    "[{}]Out of recording schedule, stop recording",
+                                    self_clone.room_id
+                                );
+                                if *self_clone.timestamp.read().await != 0 {
+                                    self_clone.reset().await;
+                                }
+                                break;
+                            }
+                            match self_clone.update_entries().await {
+                                Ok(ms) => {
+                                    playlist_backoff.reset();
+                                    if ms < 1000 {
+                                        thread::sleep(std::time::Duration::from_millis(
+                                            (1000 - ms) as u64,
+                                        ));
+                                    } else {
+                                        log::warn!(
+                                            "[{}]Update entries cost too long: {}ms",
+                                            self_clone.room_id,
+                                            ms
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    log::error!(
+                                        "[{}]Update entries error: {}",
+                                        self_clone.room_id,
+                                        e
+                                    );
+                                    *self_clone.last_error.write().await = Some(e.to_string());
+                                    // a 403 or timeout is often only on one cdn host
+                                    if matches!(e, RecorderError::ClientError { .. })
+                                        && self_clone.failover().await
+                                    {
+                                        continue;
+                                    }
+                                    break;
+                                }
+                            }
+                        }
+                        // go check status again, later if playlist keeps failing
+                        let delay = playlist_backoff.next(&self_clone.backoff_policy().await);
+                        thread::sleep(delay);
+                        continue;
+                    }
+                    // Every 10s check live status, later if room info keeps failing
+                    let delay = self_clone.status_backoff.read().await.poll(
+                        Duration::from_secs(STATUS_CHECK_SECS),
+                        &self_clone.backoff_policy().await,
+                    );
+                    thread::sleep(delay);
+                }
+                self_clone.slots.release(self_clone.room_id);
+                log::info!("recording thread {} quit.", self_clone.room_id);
+            });
+        });
+        // Thread for danmaku
+        let self_clone = self.clone();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let flusher = self_clone.clone();
+                tokio::spawn(async move {
+                    flusher.danmu_flush_loop().await;
+                });
+                let metrics = self_clone.clone();
+                tokio::spawn(async move {
+                    metrics.metrics_loop().await;
+                });
+                let monitor = self_clone.clone();
+                tokio::spawn(async move {
+                    monitor.monitor_loop().await;
+                });
+                let filler = self_clone.clone();
+                tokio::spawn(async move {
+                    filler.filler_loop().await;
+                });
+                let pipeline = self_clone.clone();
+                tokio::spawn(async move {
+                    pipeline.pipeline_loop().await;
+                });
+                let offloader = self_clone.clone();
+                tokio::spawn(async move {
+                    offloader.offload_loop().await;
+                });
+                let slate = self_clone.clone();
+                tokio::spawn(async move {
+                    slate.interruption_loop().await;
+                });
+                let snapshot = self_clone.clone();
+                tokio::spawn(async move {
+                    snapshot.snapshot_loop().await;
+                });
+                self_clone.danmu().await;
+            });
+        });
+    }
+
+    pub async fn stop(&self) {
+        *self.quit.lock().await = true;
+        self.flush_danmu().await;
+        self.save_danmu_minute().await;
+        if let Err(e) = self.save_record(true).await {
+            log::error!("[{}]Save record failed: {}", self.room_id, e);
+        }
+    }
+
+    /// Update length and size of current record, at most once in `record_update_interval`
+    /// seconds unless forced, so many recordings don't keep db busy with tiny writes
+    async fn save_record(&self, force: bool) -> Result<(), RecorderError> {
+        let timestamp = *self.timestamp.read().await;
+        if timestamp == 0 {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp();
+        {
+            let interval = self.config.read().await.record_update_interval as i64;
+            let mut last = self.last_record_update.write().await;
+            if !force && now - *last < interval {
+                return Ok(());
+            }
+            *last = now;
+        }
+        self.db
+            .update_record(
+                timestamp,
+                self.ts_entries
+                    .read()
+                    .await
+                    .iter()
+                    .fold(0.0, |t, e| t + e.length) as i64,
+                *self.cache_size.read().await,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn pipeline_metrics(&self) -> PipelineMetrics {
+        let mut metrics = self.pipeline.read().await.clone();
+        if let Some(storage) = self.danmu_storage.read().await.as_ref() {
+            metrics.pending_writes = storage.pending_lines().await;
+        }
+        metrics
+    }
+
+    /// Warn by a message once any queue stays above its threshold for
+    /// `backpressure_alert_minutes`, before it shows up as gaps in recording
+    async fn pipeline_loop(&self) {
+        let mut over_since: [Option<i64>; 3] = [None; 3];
+        let mut alerted = [false; 3];
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(PIPELINE_CHECK_SECS)).await;
+            if !*self.live_status.read().await {
+                over_since = [None; 3];
+                alerted = [false; 3];
+                continue;
+            }
+            let metrics = self.pipeline_metrics().await;
+            let depths = [
+                (
+                    "pending_downloads",
+                    metrics.pending_downloads,
+                    PENDING_DOWNLOADS_THRESHOLD,
+                ),
+                (
+                    "pending_writes",
+                    metrics.pending_writes,
+                    PENDING_WRITES_THRESHOLD,
+                ),
+                (
+                    "danmu_backlog",
+                    metrics.danmu_backlog,
+                    DANMU_BACKLOG_THRESHOLD,
+                ),
+            ];
+            let limit = self.config.read().await.backpressure_alert_minutes as i64 * 60;
+            let now = Utc::now().timestamp();
+            for (i, (name, depth, threshold)) in depths.iter().enumerate() {
+                if depth <= threshold {
+                    over_since[i] = None;
+                    alerted[i] = false;
+                    continue;
+                }
+                let since = *over_since[i].get_or_insert(now);
+                if alerted[i] || now - since < limit {
+                    continue;
+                }
+                alerted[i] = true;
+                log::warn!(
+                    "[{}]Recording is falling behind, {} is {} for {}s",
+                    self.room_id,
+                    name,
+                    depth,
+                    now - since
+                );
+                if let Err(e) = self
+                    .db
+                    .new_message(
+                        MessageKey::PipelineBehind,
+                        &[
+                            self.room_id.to_string(),
+                            name.to_string(),
+                            depth.to_string(),
+                        ],
+                    )
+                    .await
+                {
+                    log::error!("[{}]Save message failed: {}", self.room_id, e);
+                }
+            }
+        }
+    }
+
+    /// Move finished segments of current live into offload storage when it's configured.
+    /// Segments of rolling buffer stay local, they may be dropped soon.
+    async fn offload_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(OFFLOAD_CHECK_SECS)).await;
+            let Some(s3) = self.config.read().await.offload.clone() else {
+                continue;
+            };
+            if !*self.live_status.read().await || *self.buffering.read().await {
+                continue;
+            }
+            self.offload_segments(&s3).await;
+        }
+    }
+
+    async fn offload_segments(&self, s3: &S3Config) {
+        let live_id = *self.timestamp.read().await;
+        if live_id == 0 {
+            return;
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let pending: Vec<TsEntry> = {
+            let entries = self.ts_entries.read().await;
+            let Some(last) = entries.last() else {
+                return;
+            };
+            entries
+                .iter()
+                .filter(|e| e.remote.is_none() && e.offset + OFFLOAD_KEEP_MS < last.offset)
+                .cloned()
+                .collect()
+        };
+        let location = s3.location();
+        let proxy = self.config.read().await.proxy_for(self.room_id);
+        for e in pending {
+            if *self.quit.lock().await || *self.timestamp.read().await != live_id {
+                break;
+            }
+            let file = format!("{}/{}", work_dir, e.url);
+            let content = match fs::read(&file).await {
+                Ok(content) => content,
+                Err(err) => {
+                    log::warn!("[{}]Read segment {} failed: {}", self.room_id, e.url, err);
+                    continue;
+                }
+            };
+            let key = format!("{}/{}/{}", self.room_id, live_id, e.url);
+            // stop at the first failure, segments are retried in order next time
+            if let Err(err) = offload::put(s3, proxy.as_deref(), &key, content).await {
+                log::warn!(
+                    "[{}]Offload segment {} failed: {}",
+                    self.room_id,
+                    e.url,
+                    err
+                );
+                break;
+            }
+            let index = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(format!("{}/{}", work_dir, OFFLOAD_INDEX))
+                .await;
+            let written = match index {
+                Ok(mut index) => {
+                    index
+                        .write_all(format!("{}:{}:{}\n", e.url, e.size, location).as_bytes())
+                        .await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = written {
+                // local file is kept, so the segment is still listed
+                log::error!("[{}]Write offload index failed: {}", self.room_id, err);
+                break;
+            }
+            let _ = fs::remove_file(&file).await;
+            if let Some(entry) = self
+                .ts_entries
+                .write()
+                .await
+                .iter_mut()
+                .find(|entry| entry.url == e.url)
+            {
+                entry.remote = Some(location.clone());
+            }
+        }
+    }
+
+    /// Download offloaded files in list back to their local path, for clipping them. Each is
+    /// fetched from the storage recorded in offload index of its dir.
+    /// Returns files downloaded, which should be removed after use.
+    async fn fetch_offloaded(&self, file_list: &[String]) -> Result<Vec<String>, RecorderError> {
+        let (cache, storages, proxy) = {
+            let config = self.config.read().await;
+            (
+                config.cache.clone(),
+                config.offload_storages(),
+                config.proxy_for(self.room_id),
+            )
+        };
+        let mut indexes: HashMap<String, Vec<TsEntry>> = HashMap::new();
+        let mut fetched = Vec::new();
+        for file in file_list {
+            if fs::metadata(file).await.is_ok() {
+                continue;
+            }
+            let Some((dir, name)) = file.rsplit_once('/') else {
+                continue;
+            };
+            if !indexes.contains_key(dir) {
+                let index = self.get_offloaded_entries(dir).await;
+                indexes.insert(dir.to_string(), index);
+            }
+            let Some(location) = indexes[dir]
+                .iter()
+                .find(|e| e.url == name)
+                .and_then(|e| e.remote.clone())
+            else {
+                continue;
+            };
+            let Some(key) = file.strip_prefix(&format!("{}/", cache)) else {
+                continue;
+            };
+            let key = key.replace("//", "/");
+            let result = match offload::find(&storages, &location) {
+                None => Err(RecorderError::OffloadError {
+                    err: OffloadError::StorageNotFound { location },
+                }),
+                Some(s3) => match offload::get(s3, proxy.as_deref(), &key).await {
+                    Ok(content) => tokio::fs::write(file, content)
+                        .await
+                        .map_err(|e| RecorderError::IoError { err: e }),
+                    Err(e) => Err(RecorderError::OffloadError { err: e }),
+                },
+            };
+            if let Err(e) = result {
+                for f in fetched.iter() {
+                    let _ = fs::remove_file(f).await;
+                }
+                return Err(e);
+            }
+            fetched.push(file.clone());
+        }
+        Ok(fetched)
+    }
+
+    /// Url of a segment in playlist, offloaded ones are fetched from their storage directly
+    fn segment_url(&self, storages: &[S3Config], live_id: u64, entry: &TsEntry) -> String {
+        let file_name = entry.url.split('/').last().unwrap();
+        match &entry.remote {
+            Some(location) => offload::public_url(
+                storages,
+                location,
+                &format!("{}/{}/{}", self.room_id, live_id, file_name),
+            ),
+            None => format!("/{}/{}/{}", self.room_id, live_id, file_name),
+        }
+    }
+
+    /// check_status is not called while recording, so online count is polled here
+    async fn metrics_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(METRICS_INTERVAL as u64)).await;
+            if !*self.live_status.read().await {
+                continue;
+            }
+            match self
+                .client
+                .read()
+                .await
+                .get_room_info(&self.account, self.room_id)
+                .await
+            {
+                Ok(room_info) => {
+                    let online = room_info.online;
+                    *self.room_info.write().await = room_info;
+                    self.save_room_metric(online).await;
+                }
+                Err(e) => {
+                    log::warn!("[{}]Get room info for metrics failed: {}", self.room_id, e);
+                }
+            }
+        }
+    }
+
+    async fn save_room_metric(&self, online: u64) {
+        let live_id = *self.timestamp.read().await;
+        let now = Utc::now().timestamp();
+        {
+            let mut last_metric = self.last_metric.write().await;
+            if live_id == 0 || now - *last_metric < METRICS_INTERVAL {
+                return;
+            }
+            *last_metric = now;
+        }
+        if let Err(e) = self
+            .db
+            .add_room_metric(self.room_id, live_id, online as i64, now)
+            .await
+        {
+            log::error!("[{}]Save room metric failed: {}", self.room_id, e);
+        }
+    }
+
+    async fn monitor_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(MONITOR_CHUNK_SECS)).await;
+            if !self.config.read().await.monitor_stream {
+                continue;
+            }
+            if let Err(e) = self.update_monitor().await {
+                log::warn!("[{}]Update monitor stream failed: {}", self.room_id, e);
+            }
+        }
+    }
+
+    /// Transcode segments downloaded since last run into a low bitrate chunk,
+    /// chunks are listed in monitor.m3u8 in the work dir of live
+    async fn update_monitor(&self) -> Result<(), RecorderError> {
+        let live_id = *self.timestamp.read().await;
+        if live_id == 0 {
+            return Ok(());
+        }
+        let mut state = self.monitor.write().await;
+        if state.live_id != live_id {
+            *state = MonitorState {
+                live_id,
+                ..Default::default()
+            };
+        }
+        let entries: Vec<TsEntry> = self
+            .ts_entries
+            .read()
+            .await
+            .iter()
+            .filter(|e| state.last_sequence.map_or(true, |last| e.sequence > last))
+            .cloned()
+            .collect();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let mut file_list = Vec::new();
+        if let Some(header) = self.header.read().await.as_ref() {
+            file_list.push(format!("{}/{}", work_dir, header.url));
+        }
+        for e in entries.iter() {
+            file_list.push(format!("{}/{}", work_dir, e.url.split('/').last().unwrap()));
+        }
+        let input = Self::generate_clip(&file_list, &work_dir, "monitor.tmp").await?;
+        let output = format!("{}/monitor-{}.ts", work_dir, state.chunks.len());
+        let input_clone = input.clone();
+        let profile = self.config.read().await.monitor_profile.clone();
+        let result =
+            ffmpeg::execute(move || ffmpeg::transcode_monitor(&input_clone, &output, &profile))
+                .await;
+        let _ = tokio::fs::remove_file(&input).await;
+        result.map_err(|err| RecorderError::ClipError { err })?;
+        state.last_sequence = entries.last().map(|e| e.sequence);
+        state.chunks.push(entries.iter().map(|e| e.length).sum());
+
+        let target_duration = state.chunks.iter().cloned().fold(0.0, f64::max).ceil();
+        let mut m3u8_content = "#EXTM3U\n".to_string();
+        m3u8_content += "#EXT-X-VERSION:3\n";
+        m3u8_content += &format!("#EXT-X-TARGETDURATION:{}\n", target_duration);
+        m3u8_content += "#EXT-X-MEDIA-SEQUENCE:0\n";
+        for (i, length) in state.chunks.iter().enumerate() {
+            // chunks are transcoded separately, timestamps restart in each of them
+            if i > 0 {
+                m3u8_content += "#EXT-X-DISCONTINUITY\n";
+            }
+            m3u8_content += &format!("#EXTINF:{:.3},\nmonitor-{}.ts\n", length, i);
+        }
+        if let Err(e) = tokio::fs::write(format!("{}/monitor.m3u8", work_dir), m3u8_content).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        Ok(())
+    }
+
+    async fn snapshot_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(SNAPSHOT_CHECK_SECS)).await;
+            if let Err(e) = self.take_snapshot().await {
+                log::warn!("[{}]Take snapshot failed: {}", self.room_id, e);
+            }
+        }
+    }
+
+    /// Save newest frame of current live as `snapshot-{offset}.jpg` in work dir, once in
+    /// `snapshot_interval_minutes`
+    async fn take_snapshot(&self) -> Result<(), RecorderError> {
+        let interval = self.config.read().await.snapshot_interval_minutes as i64 * 60;
+        let live_id = *self.timestamp.read().await;
+        if interval == 0
+            || live_id == 0
+            || !*self.live_status.read().await
+            || self.filler.read().await.is_paused()
+        {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp();
+        {
+            let mut last = self.last_snapshot.write().await;
+            if now - *last < interval {
+                return Ok(());
+            }
+            *last = now;
+        }
+        let (Some(header), Some(entry), Some((start, _))) = (
+            self.header.read().await.clone(),
+            self.ts_entries.read().await.last().cloned(),
+            self.tail_position().await,
+        ) else {
+            return Ok(());
+        };
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let offset = start as u64;
+        let file_list = vec![
+            format!("{}/{}", work_dir, header.url),
+            format!("{}/{}", work_dir, entry.url),
+        ];
+        let input = Self::generate_clip(&file_list, &work_dir, "snapshot.tmp").await?;
+        let (input_clone, output) = (
+            input.clone(),
+            format!("{}/snapshot-{}.jpg", work_dir, offset),
+        );
+        let result = ffmpeg::execute(move || ffmpeg::snapshot(&input_clone, &output)).await;
+        let _ = tokio::fs::remove_file(&input).await;
+        result.map_err(|err| RecorderError::ClipError { err })
+    }
+
+    /// Snapshots of a live in order, see `take_snapshot`
+    pub async fn get_snapshots(&self, live_id: u64) -> Vec<Snapshot> {
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let mut snapshots = Vec::new();
+        let Ok(mut dir) = tokio::fs::read_dir(&work_dir).await else {
+            return snapshots;
+        };
+        while let Ok(Some(entry)) = dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(offset) = name
+                .strip_prefix("snapshot-")
+                .and_then(|n| n.strip_suffix(".jpg"))
+                .and_then(|n| n.parse().ok())
+            else {
+                continue;
+            };
+            snapshots.push(Snapshot {
+                offset,
+                url: format!("/{}/{}/{}", self.room_id, live_id, name),
+            });
+        }
+        snapshots.sort_by_key(|s| s.offset);
+        snapshots
+    }
+
+    async fn filler_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs(FILLER_CHECK_SECS)).await;
+            if let Err(e) = self.check_filler().await {
+                log::warn!("[{}]Check filler failed: {}", self.room_id, e);
+            }
+        }
+    }
+
+    /// Pause segment downloads while room is looping filler content, which is a static frame
+    /// with almost no danmu. Downloads resume as soon as danmu comes back.
+    async fn check_filler(&self) -> Result<(), RecorderError> {
+        let quiet = {
+            let mut filler = self.filler.write().await;
+            let quiet = Utc::now().timestamp() - filler.last_danmu >= FILLER_QUIET_SECS;
+            let enabled = self.config.read().await.filler_pause;
+            if filler.paused && (!quiet || !enabled || !*self.live_status.read().await) {
+                log::info!("[{}]Room is active again, resume recording", self.room_id);
+                filler.paused = false;
+            }
+            quiet && enabled && !filler.paused
+        };
+        let live_id = *self.timestamp.read().await;
+        if !quiet || live_id == 0 {
+            return Ok(());
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let Some(input) = self
+            .sample_tail(&work_dir, FILLER_SAMPLE_SECS, "filler.tmp")
+            .await?
+        else {
+            return Ok(());
+        };
+        let input_clone = input.clone();
+        let result =
+            ffmpeg::execute(move || ffmpeg::is_static(&input_clone, FILLER_SAMPLE_SECS * 0.8))
+                .await;
+        let _ = tokio::fs::remove_file(&input).await;
+        if result.map_err(|err| RecorderError::ClipError { err })? {
+            log::info!("[{}]Filler content detected, pause recording", self.room_id);
+            self.filler.write().await.paused = true;
+        }
+        Ok(())
+    }
+
+    /// Join header and the newest `secs` of current live into `name` in work dir, for checking
+    /// what room is streaming now. None if less than that is recorded.
+    async fn sample_tail(
+        &self,
+        work_dir: &str,
+        secs: f64,
+        name: &str,
+    ) -> Result<Option<String>, RecorderError> {
+        let mut file_list = Vec::new();
+        if let Some(header) = self.header.read().await.as_ref() {
+            file_list.push(format!("{}/{}", work_dir, header.url));
+        }
+        {
+            let entries = self.ts_entries.read().await;
+            let mut length = 0.0;
+            let mut tail = Vec::new();
+            for e in entries.iter().rev() {
+                if length >= secs {
+                    break;
+                }
+                length += e.length;
+                tail.push(format!("{}/{}", work_dir, e.url));
+            }
+            if length < secs {
+                return Ok(None);
+            }
+            file_list.extend(tail.into_iter().rev());
+        }
+        Ok(Some(Self::generate_clip(&file_list, work_dir, name).await?))
+    }
+
+    /// Start and end in seconds of the last entry of current live, relative to the first entry
+    /// like clip ranges. Entry offsets are used as lengths don't cover pauses and filler gaps.
+    async fn tail_position(&self) -> Option<(f64, f64)> {
+        let entries = self.ts_entries.read().await;
+        let (first, last) = (entries.first()?, entries.last()?);
+        let start = (last.offset - first.offset) as f64 / 1000.0;
+        Some((start, start + last.length))
+    }
+
+    async fn interruption_loop(&self) {
+        while !*self.quit.lock().await {
+            tokio::time::sleep(Duration::from_secs_f64(INTERRUPTION_CHECK_SECS)).await;
+            if let Err(e) = self.check_interruption().await {
+                log::warn!("[{}]Check interruption failed: {}", self.room_id, e);
+            }
+        }
+    }
+
+    /// Mark ranges of current live showing an ad or technical difficulty slate, which is
+    /// a static frame over silence. A range is saved once the slate is gone or live ends.
+    async fn check_interruption(&self) -> Result<(), RecorderError> {
+        let live_id = *self.timestamp.read().await;
+        let enabled = self.config.read().await.detect_interruptions;
+        if !enabled
+            || live_id == 0
+            || !*self.live_status.read().await
+            || self.filler.read().await.is_paused()
+        {
+            let open = self.interruption.write().await.take();
+            if let Some(open) = open {
+                self.save_interruption(open).await;
+            }
+            return Ok(());
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let Some(input) = self
+            .sample_tail(&work_dir, INTERRUPTION_SAMPLE_SECS, "slate.tmp")
+            .await?
+        else {
+            return Ok(());
+        };
+        let Some((_, end)) = self.tail_position().await else {
+            let _ = tokio::fs::remove_file(&input).await;
+            return Ok(());
+        };
+        let input_clone = input.clone();
+        let result =
+            ffmpeg::execute(move || ffmpeg::is_slate(&input_clone, INTERRUPTION_SAMPLE_SECS * 0.8))
+                .await;
+        let _ = tokio::fs::remove_file(&input).await;
+        let slate = result.map_err(|err| RecorderError::ClipError { err })?;
+        let finished = {
+            let mut open = self.interruption.write().await;
+            match (*open, slate) {
+                (Some(current), true) if current.live_id == live_id => {
+                    *open = Some(OpenInterruption { end, ..current });
+                    None
+                }
+                (current, true) => {
+                    log::info!("[{}]Slate detected at {:.0}s", self.room_id, end);
+                    *open = Some(OpenInterruption {
+                        live_id,
+                        start: (end - INTERRUPTION_CHECK_SECS).max(0.0),
+                        end,
+                    });
+                    current
+                }
+                (current, false) => open.take().or(current),
+            }
+        };
+        if let Some(finished) = finished {
+            self.save_interruption(finished).await;
+        }
+        Ok(())
+    }
+
+    async fn save_interruption(&self, range: OpenInterruption) {
+        log::info!(
+            "[{}]Interruption of live {} from {:.0}s to {:.0}s",
+            self.room_id,
+            range.live_id,
+            range.start,
+            range.end
+        );
+        if let Err(e) = self
+            .db
+            .add_interruption(self.room_id, range.live_id, range.start, range.end)
+            .await
+        {
+            log::error!("[{}]Save interruption failed: {}", self.room_id, e);
+        }
+    }
+
+    /// Interruption ranges to leave out of clips and packages, empty unless it's enabled
+    async fn skipped_ranges(&self, live_id: u64) -> Vec<(f64, f64)> {
+        if !self.config.read().await.clip_skip_interruptions {
+            return Vec::new();
+        }
+        match self.db.get_interruptions(self.room_id, live_id).await {
+            Ok(rows) => rows.into_iter().map(|r| (r.start, r.end)).collect(),
+            Err(e) => {
+                log::warn!("[{}]Get interruptions failed: {}", self.room_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// `offset` is in ms relative to the first entry
+    fn in_ranges(ranges: &[(f64, f64)], offset: u64) -> bool {
+        let secs = offset as f64 / 1000.0;
+        ranges
+            .iter()
+            .any(|(start, end)| secs >= *start && secs < *end)
+    }
+
+    /// Where `offset` in seconds lands in a clip starting at `x` with ranges left out, none if
+    /// it's inside one of them
+    fn clip_offset(ranges: &[(f64, f64)], x: f64, offset: f64) -> Option<f64> {
+        if ranges
+            .iter()
+            .any(|(start, end)| offset >= *start && offset < *end)
+        {
+            return None;
+        }
+        Some(offset - x - Self::skipped_length(ranges, x, offset))
+    }
+
+    /// Seconds of ranges inside [x, y]
+    fn skipped_length(ranges: &[(f64, f64)], x: f64, y: f64) -> f64 {
+        ranges
+            .iter()
+            .map(|(start, end)| (end.min(y) - start.max(x)).max(0.0))
+            .sum()
+    }
+
+    async fn danmu_flush_loop(&self) {
+        while !*self.quit.lock().await {
+            let interval = self.config.read().await.danmu_flush_interval.max(1);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            self.flush_danmu().await;
+        }
+    }
+
+    /// Write buffered danmu lines of current live into file
+    async fn flush_danmu(&self) {
+        if let Some(storage) = self.danmu_storage.read().await.as_ref() {
+            if let Err(e) = storage.flush().await {
+                log::warn!(
+                    "[{}]Flush danmu failed, lines are kept in buffer: {}",
+                    self.room_id,
+                    e
+                );
+            }
+        }
     }
 
-    pub async fn run(&self) {
-        let self_clone = self.clone();
-        thread::spawn(move || {
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(async move {
-                while !*self_clone.quit.lock().await {
-                    if self_clone.check_status().await {
-                        // Live status is ok, start recording.
-                        while !*self_clone.quit.lock().await {
-                            match self_clone.update_entries().await {
-                                Ok(ms) => {
-                                    if ms < 1000 {
-                                        thread::sleep(std::time::Duration::from_millis(
-                                            (1000 - ms) as u64,
-                                        ));
-                                    } else {
-                                        log::warn!(
-                                            "[{}]Update entries cost too long: {}ms",
-                                            self_clone.room_id,
-                                            ms
-                                        );
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!(
-                                        "[{}]Update entries error: {}",
-                                        self_clone.room_id,
-                                        e
-                                    );
-                                    break;
-                                }
-                            }
-                        }
-                        // go check status again after random 2-5 secs
-                        let mut rng = rand::thread_rng();
-                        let secs = rng.gen_range(2..=5);
-                        thread::sleep(std::time::Duration::from_secs(secs));
-                        continue;
-                    }
-                    // Every 10s check live status.
-                    thread::sleep(std::time::Duration::from_secs(10));
+    /// Count danmu into the minute of `ts`, the previous minute is saved once a new one begins
+    async fn count_danmu(&self, ts: u64) {
+        let live_id = *self.timestamp.read().await;
+        let minute = ts / 60_000;
+        let finished = {
+            let mut current = self.danmu_minute.write().await;
+            match current.as_mut() {
+                Some(c) if c.live_id == live_id && c.minute == minute => {
+                    c.count += 1;
+                    None
                 }
-                log::info!("recording thread {} quit.", self_clone.room_id);
-            });
-        });
-        // Thread for danmaku
-        let self_clone = self.clone();
-        thread::spawn(move || {
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            runtime.block_on(async move {
-                self_clone.danmu().await;
-            });
-        });
+                _ => current.replace(DanmuMinute {
+                    live_id,
+                    minute,
+                    count: 1,
+                }),
+            }
+        };
+        if let Some(finished) = finished {
+            self.save_statistic(finished).await;
+        }
     }
 
-    pub async fn stop(&self) {
-        *self.quit.lock().await = true;
+    async fn save_danmu_minute(&self) {
+        let current = self.danmu_minute.write().await.take();
+        if let Some(current) = current {
+            self.save_statistic(current).await;
+        }
+    }
+
+    async fn save_statistic(&self, stat: DanmuMinute) {
+        let time_point = Utc
+            .timestamp_opt((stat.minute * 60) as i64, 0)
+            .unwrap()
+            .to_rfc3339();
+        if let Err(e) = self
+            .db
+            .add_danmu_statistic(stat.live_id, self.room_id, stat.count, &time_point)
+            .await
+        {
+            log::error!("[{}]Save danmu statistic failed: {}", self.room_id, e);
+        }
+    }
+
+    pub async fn get_danmu_statistics(
+        &self,
+        live_id: u64,
+    ) -> Result<Vec<DanmuStatisticRow>, RecorderError> {
+        Ok(self.db.get_danmu_statistics(self.room_id, live_id).await?)
     }
 
     async fn danmu(&self) {
@@ -353,25 +2381,190 @@ impl BiliRecorder {
             if *self.quit.lock().await {
                 break;
             }
-            if let WsStreamMessageType::DanmuMsg(msg) = msg {
-                let _ = self.app_handle.emit(
-                    &format!("danmu:{}", room),
-                    DanmuEntry {
-                        ts: msg.timestamp,
-                        content: msg.msg.clone(),
-                    },
-                );
-                if *self.live_status.read().await {
-                    // save danmu
-                    if let Some(storage) = self.danmu_storage.write().await.as_ref() {
-                        storage.add_line(msg.timestamp, &msg.msg).await;
+            self.pipeline.write().await.danmu_backlog = rx.len();
+            match msg {
+                WsStreamMessageType::DanmuMsg(msg) => {
+                    let _ = self.app_handle.emit(
+                        &format!("danmu:{}", room),
+                        DanmuEntry {
+                            ts: msg.timestamp,
+                            content: msg.msg.clone(),
+                        },
+                    );
+                    if *self.live_status.read().await {
+                        // save danmu
+                        if let Some(storage) = self.danmu_storage.write().await.as_ref() {
+                            storage.add_line(msg.timestamp, &msg.msg).await;
+                        }
+                        self.count_danmu(msg.timestamp).await;
+                        self.filler.write().await.last_danmu = Utc::now().timestamp();
+                        self.check_keywords(msg.timestamp, &msg.msg).await;
                     }
                 }
+                WsStreamMessageType::SuperChatMessage(msg) => {
+                    // superchat price is in CNY
+                    self.save_interaction(
+                        "superchat",
+                        msg.uid,
+                        &msg.uname,
+                        &msg.msg,
+                        "",
+                        1,
+                        msg.price as i64 * 1000,
+                    )
+                    .await;
+                }
+                // silver coin gifts are free, they are no revenue
+                WsStreamMessageType::SendGift(msg) if msg.coin_type == "gold" => {
+                    self.save_interaction(
+                        "gift",
+                        msg.uid,
+                        &msg.uname,
+                        "",
+                        &msg.gift_name,
+                        msg.num as i64,
+                        msg.price as i64 * msg.num as i64,
+                    )
+                    .await;
+                }
+                // guard price is in gold coins per month, num is months bought
+                WsStreamMessageType::GuardBuy(msg) => {
+                    self.save_interaction(
+                        "guard",
+                        msg.uid,
+                        &msg.username,
+                        "",
+                        &msg.gift_name,
+                        msg.num as i64,
+                        msg.price as i64 * msg.num as i64,
+                    )
+                    .await;
+                }
+                _ => {}
             }
         }
         Ok(())
     }
 
+    async fn check_keywords(&self, ts: u64, content: &str) {
+        let now = Utc::now().timestamp();
+        let mut hits = Vec::new();
+        for matcher in self.keyword_matchers.write().await.iter_mut() {
+            if matcher.hit(content, now) {
+                hits.push(matcher.rule.pattern.clone());
+            }
+        }
+        if hits.is_empty() {
+            return;
+        }
+        let live_id = *self.timestamp.read().await;
+        let start = self.get_playback_start(live_id).await;
+        let offset = ts.saturating_sub(start) as f64 / 1000.0;
+        let link = DeepLink::Live {
+            room_id: self.room_id,
+            live_id,
+            offset: Some(offset),
+        }
+        .to_link();
+        for pattern in hits {
+            self.handle_event(RecorderEvent::KeywordHit {
+                room_id: self.room_id,
+                live_id,
+                pattern,
+                content: content.into(),
+                offset,
+                ts,
+                link: link.clone(),
+            })
+            .await;
+        }
+    }
+
+    async fn handle_event(&self, event: RecorderEvent) {
+        let _ = self.app_handle.emit("recorder_event", event.clone());
+        match event {
+            RecorderEvent::KeywordHit {
+                live_id,
+                pattern,
+                content,
+                offset,
+                ts,
+                ..
+            } => {
+                log::info!("[{}]Keyword {} hit: {}", self.room_id, pattern, content);
+                if let Err(e) = self
+                    .db
+                    .add_marker(
+                        self.room_id,
+                        live_id,
+                        offset,
+                        (ts / 1000) as i64,
+                        &format!("[{}] {}", pattern, content),
+                    )
+                    .await
+                {
+                    log::error!("[{}]Add keyword marker failed: {}", self.room_id, e);
+                }
+                let config = self.config.read().await;
+                if config.keyword_notify {
+                    notifier::notify(
+                        &self.app_handle,
+                        &config,
+                        Some(self.room_id),
+                        "BiliShadowReplay - 关键词提醒",
+                        &format!(
+                            "{} 的直播间出现了关键词 {}：{}",
+                            self.user_info.read().await.user_name,
+                            pattern,
+                            content
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Interactions are saved only when recording, value is in gold coins
+    #[allow(clippy::too_many_arguments)]
+    async fn save_interaction(
+        &self,
+        kind: &str,
+        uid: u64,
+        user_name: &str,
+        content: &str,
+        gift_name: &str,
+        num: i64,
+        value: i64,
+    ) {
+        let live_id = *self.timestamp.read().await;
+        if !*self.live_status.read().await || live_id == 0 {
+            return;
+        }
+        let interaction = InteractionRow {
+            id: 0,
+            live_id,
+            room_id: self.room_id,
+            kind: kind.into(),
+            uid,
+            user_name: user_name.into(),
+            content: content.into(),
+            gift_name: gift_name.into(),
+            num,
+            value,
+            ts: Utc::now().timestamp_millis(),
+        };
+        match self.db.add_interaction(&interaction).await {
+            Ok(interaction) => {
+                let _ = self
+                    .app_handle
+                    .emit(&format!("interaction:{}", self.room_id), interaction);
+            }
+            Err(e) => {
+                log::error!("[{}]Save {} failed: {}", self.room_id, kind, e);
+            }
+        }
+    }
+
     async fn get_playlist(&self) -> Result<Playlist, RecorderError> {
         let stream = self.live_stream.read().await.clone();
         if stream.is_none() {
@@ -459,13 +2652,242 @@ impl BiliRecorder {
         }
     }
 
+    async fn acquire_slot(&self) -> bool {
+        let max = self.config.read().await.max_recordings;
+        let priority = *self.priority.read().await;
+        let was_waiting = self.slots.is_waiting(self.room_id);
+        let acquired = self.slots.acquire(self.room_id, priority, max);
+        if !acquired && !was_waiting {
+            log::info!(
+                "[{}]Recording slots are full, waiting with priority {}",
+                self.room_id,
+                priority
+            );
+        }
+        acquired
+    }
+
+    /// Live but waiting for a recording slot
+    pub fn is_queued(&self) -> bool {
+        self.slots.is_waiting(self.room_id)
+    }
+
+    async fn in_schedule(&self) -> bool {
+        let schedule = self.schedule.read().await;
+        let now = Local::now().naive_local();
+        schedule.is_empty() || schedule.iter().any(|w| w.contains(now))
+    }
+
+    /// Request a new play url for current stream, which keeps recording into the same live.
+    /// Returns whether stream is updated.
+    async fn refresh_stream(&self) -> bool {
+        *self.last_refresh.write().await = Utc::now().timestamp();
+        // quality and codec settings apply from next live
+        if *self.timestamp.read().await == 0 {
+            *self.stream_quality.write().await = *self.quality.read().await;
+            *self.stream_codec.write().await = self.config.read().await.stream_codec;
+        }
+        let quality = *self.stream_quality.read().await;
+        let codec = *self.stream_codec.read().await;
+        match self
+            .client
+            .read()
+            .await
+            .get_play_url(&self.account, self.room_id, quality, codec)
+            .await
+        {
+            Ok(mut stream) => {
+                stream.prefer(
+                    &self
+                        .db
+                        .get_cdn_ranking("bilibili")
+                        .await
+                        .unwrap_or_default(),
+                );
+                log::info!("[{}]Update stream: {:?}", self.room_id, stream);
+                *self.live_stream.write().await = Some(stream);
+                *self.failovers.write().await = 0;
+                true
+            }
+            Err(e) => {
+                log::error!("[{}]Update stream failed: {}", self.room_id, e);
+                false
+            }
+        }
+    }
+
+    /// Whether current stream has a host not tried since the last segment came in
+    async fn can_failover(&self) -> bool {
+        let failovers = *self.failovers.read().await;
+        self.live_stream
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|s| failovers + 1 < s.hosts.len())
+    }
+
+    /// Move current stream to the next cdn host after a request failed. Hosts are tried in turn
+    /// until segments come in again; once all of them failed the stream is dropped, so a new
+    /// play url is fetched on next status check. Returns whether recording goes on.
+    async fn failover(&self) -> bool {
+        let mut failovers = self.failovers.write().await;
+        let mut stream = self.live_stream.write().await;
+        let next = stream.as_ref().and_then(|s| {
+            if *failovers + 1 < s.hosts.len() {
+                s.next_host()
+            } else {
+                None
+            }
+        });
+        match next {
+            Some(next) => {
+                log::warn!(
+                    "[{}]Switch cdn host {} -> {}",
+                    self.room_id,
+                    stream.as_ref().unwrap().host,
+                    next.host
+                );
+                *failovers += 1;
+                *stream = Some(next);
+                true
+            }
+            None => {
+                *failovers = 0;
+                drop(stream);
+                drop(failovers);
+                // url may be outdated as well, like a 403 before expiry, so get a new one in
+                // place once in a while instead of ending the session
+                if *self.last_refresh.read().await + STREAM_REFRESH_INTERVAL
+                    < Utc::now().timestamp()
+                {
+                    log::warn!("[{}]All cdn hosts failed, refresh stream", self.room_id);
+                    if self.refresh_stream().await {
+                        return true;
+                    }
+                }
+                log::error!("[{}]All cdn hosts failed, fetch a new stream", self.room_id);
+                *self.live_stream.write().await = None;
+                false
+            }
+        }
+    }
+
+    /// Start a new part of current live once it's over the split limit of room, so very long
+    /// lives come in upload friendly pieces. A part is recorded as a live of its own, with
+    /// id at the time it starts, and keeps downloading the same stream.
+    async fn split_if_needed(&self) -> Result<(), RecorderError> {
+        let (limit, cache, policy) = {
+            let config = self.config.read().await;
+            let limit = config
+                .room_split
+                .get(&self.room_id.to_string())
+                .or(config.split.as_ref())
+                .cloned();
+            (limit, config.cache.clone(), config.danmu_sync)
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        if *self.buffering.read().await {
+            return Ok(());
+        }
+        let length = *self.ts_length.read().await;
+        let size = *self.cache_size.read().await;
+        if !limit.exceeded(length, size) {
+            return Ok(());
+        }
+        let Some(header) = self.header.read().await.clone() else {
+            return Ok(());
+        };
+        let Some(last) = self.ts_entries.read().await.last().cloned() else {
+            return Ok(());
+        };
+        let live_id = *self.timestamp.read().await;
+        let part_id = live_id + last.offset / 1000;
+        if part_id == live_id {
+            return Ok(());
+        }
+        let work_dir = format!("{}/{}/{}", cache, self.room_id, live_id);
+        let part_dir = format!("{}/{}/{}", cache, self.room_id, part_id);
+        fs::create_dir_all(&part_dir)
+            .await
+            .map_err(|e| RecorderError::IoError { err: e })?;
+        let part_header = format!("h{}.m4s", part_id);
+        tokio::fs::copy(
+            format!("{}/{}", work_dir, header.url),
+            format!("{}/{}", part_dir, part_header),
+        )
+        .await
+        .map_err(|e| RecorderError::IoError { err: e })?;
+        tokio::fs::write(format!("{}/{}", work_dir, PART_INDEX), part_id.to_string())
+            .await
+            .map_err(|e| RecorderError::IoError { err: e })?;
+        self.save_record(true).await?;
+        self.flush_danmu().await;
+        let title = self.room_info.read().await.room_title.clone();
+        self.db.add_record(part_id, self.room_id, &title).await?;
+
+        *self.offset_shift.write().await += (part_id - live_id) * 1000;
+        *self.timestamp.write().await = part_id;
+        self.ts_entries.write().await.clear();
+        *self.ts_length.write().await = 0.0;
+        *self.cache_size.write().await = header.size;
+        *self.header.write().await = Some(TsEntry {
+            url: part_header,
+            ..header
+        });
+        *self.danmu_storage.write().await =
+            DanmuStorage::new(&format!("{}/danmu.txt", part_dir), policy).await;
+        log::info!(
+            "[{}]Live {} split at {:.0}s, {} bytes, recording part {}",
+            self.room_id,
+            live_id,
+            length,
+            size,
+            part_id
+        );
+        Ok(())
+    }
+
+    /// Retry delays of config, read on every use so changes apply right away
+    async fn backoff_policy(&self) -> BackoffPolicy {
+        self.config.read().await.backoff
+    }
+
+    async fn write_policy(&self) -> WritePolicy {
+        self.config.read().await.segment_writes
+    }
+
+    /// Global and room limiters with their rate in bytes/s, from limits in KB/s of config
+    async fn download_limits(&self) -> [(&RateLimiter, u64); 2] {
+        let config = self.config.read().await;
+        let room = config
+            .room_download_limit
+            .get(&self.room_id.to_string())
+            .copied()
+            .unwrap_or(0);
+        [
+            (&ratelimit::GLOBAL, config.download_limit * 1024),
+            (&self.download_limiter, room * 1024),
+        ]
+    }
+
     async fn update_entries(&self) -> Result<u128, RecorderError> {
         let task_begin_time = std::time::Instant::now();
         let current_stream = self.live_stream.read().await.clone();
         if current_stream.is_none() {
             return Err(RecorderError::NoStreamAvailable);
         }
-        let current_stream = current_stream.unwrap();
+        let mut current_stream = current_stream.unwrap();
+        // url stops working on expiry, renew it ahead without leaving current live
+        if current_stream.expire - STREAM_RENEW_AHEAD < Utc::now().timestamp()
+            && *self.last_refresh.read().await + STREAM_REFRESH_INTERVAL < Utc::now().timestamp()
+        {
+            log::info!("[{}]Stream is about to expire, refresh", self.room_id);
+            if self.refresh_stream().await {
+                current_stream = self.live_stream.read().await.clone().unwrap();
+            }
+        }
         let parsed = self.get_playlist().await;
         let mut timestamp = *self.timestamp.read().await;
         let mut work_dir = format!(
@@ -486,6 +2908,19 @@ impl BiliRecorder {
                 log::error!("[{}]Parse timestamp failed: {}", self.room_id, header_url);
                 return Err(RecorderError::InvalidTimestamp);
             }
+            // live may be split before an interruption, continue in its last part
+            let part = self.latest_part(timestamp).await;
+            if part != timestamp {
+                log::info!(
+                    "[{}]Live {} continues in part {}",
+                    self.room_id,
+                    timestamp,
+                    part
+                );
+                *self.offset_shift.write().await = (part - timestamp) * 1000;
+                *self.timestamp.write().await = part;
+                timestamp = part;
+            }
             self.db
                 .add_record(
                     timestamp,
@@ -515,22 +2950,36 @@ impl BiliRecorder {
             }
             // danmau file
             let danmu_file_path = format!("{}{}", work_dir, "danmu.txt");
-            *self.danmu_storage.write().await = DanmuStorage::new(&danmu_file_path).await;
+            self.flush_danmu().await;
+            let sync_policy = self.config.read().await.danmu_sync;
+            *self.danmu_storage.write().await =
+                DanmuStorage::new(&danmu_file_path, sync_policy).await;
             let full_header_url = current_stream.ts_url(&header_url);
-            let file_name = header_url.split('/').last().unwrap();
+            let file_name = if *self.offset_shift.read().await > 0 {
+                format!("h{}.m4s", timestamp)
+            } else {
+                header_url.split('/').last().unwrap().to_string()
+            };
+            let file_name = file_name.as_str();
             let mut header = TsEntry {
                 url: file_name.to_string(),
                 offset: 0,
                 sequence: 0,
                 length: 0.0,
                 size: 0,
+                remote: None,
             };
             // Download header
             match self
                 .client
                 .read()
                 .await
-                .download_ts(&full_header_url, &format!("{}/{}", work_dir, file_name))
+                .download_ts(
+                    &full_header_url,
+                    &format!("{}/{}", work_dir, file_name),
+                    &self.download_limits().await,
+                    self.write_policy().await,
+                )
                 .await
             {
                 Ok(size) => {
@@ -549,12 +2998,28 @@ impl BiliRecorder {
             Ok(Playlist::MediaPlaylist(pl)) => {
                 let mut new_segment_fetched = false;
                 let mut sequence = pl.media_sequence;
+                let last_sequence = *self.last_sequence.read().await;
+                self.pipeline.write().await.pending_downloads = pl
+                    .segments
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| sequence + *i as u64 > last_sequence)
+                    .count();
                 for ts in pl.segments {
                     if sequence <= *self.last_sequence.read().await {
                         sequence += 1;
                         continue;
                     }
                     new_segment_fetched = true;
+                    {
+                        let mut filler = self.filler.write().await;
+                        if filler.is_paused() {
+                            filler.gap = true;
+                            *self.last_sequence.write().await = sequence;
+                            sequence += 1;
+                            continue;
+                        }
+                    }
                     let mut offset_hex: String = "".into();
                     let mut seg_offset: u64 = 0;
                     for tag in ts.unknown_tags {
@@ -570,6 +3035,12 @@ impl BiliRecorder {
                             break;
                         }
                     }
+                    // offsets of a split part are relative to the part
+                    let shift = *self.offset_shift.read().await;
+                    if shift > 0 {
+                        seg_offset = seg_offset.saturating_sub(shift);
+                        offset_hex = format!("{:x}", seg_offset);
+                    }
                     let ts_url = current_stream.ts_url(&ts.uri);
                     if Url::parse(&ts_url).is_err() {
                         log::error!("Ts url is invalid. ts_url={} original={}", ts_url, ts.uri);
@@ -588,34 +3059,60 @@ impl BiliRecorder {
                         }
                         ts_length = (seg_offset - last.offset) as f64 / 1000.0;
                     }
+                    // offset to the last entry covers the skipped filler
+                    if std::mem::take(&mut self.filler.write().await.gap) {
+                        ts_length = ts.duration as f64;
+                    }
                     let ts_entry = TsEntry {
                         url: file_name.clone(),
                         offset: seg_offset,
                         sequence,
                         length: ts_length,
                         size: 0,
+                        remote: None,
                     };
                     let client = self.client.clone();
-                    let mut retry = 0;
+                    let limits = self.download_limits().await;
+                    let writes = self.write_policy().await;
+                    let mut backoff = Backoff::default();
+                    let policy = self.backoff_policy().await.for_segments();
                     loop {
-                        if retry > 3 {
-                            log::error!("Download ts failed after retry");
-                            break;
-                        }
                         match client
                             .read()
                             .await
-                            .download_ts(&ts_url, &format!("{}/{}", work_dir, file_name))
+                            .download_ts(
+                                &ts_url,
+                                &format!("{}/{}", work_dir, file_name),
+                                &limits,
+                                writes,
+                            )
                             .await
                         {
                             Ok(size) => {
+                                let ts_entry = TsEntry { size, ..ts_entry };
                                 self.ts_entries.write().await.push(ts_entry);
                                 *self.cache_size.write().await += size;
                                 break;
                             }
                             Err(e) => {
-                                retry += 1;
-                                log::warn!("Download ts failed, retry {}: {}", retry, e);
+                                let delay = backoff.next(&policy);
+                                log::warn!(
+                                    "Download ts failed, retry {}: {}",
+                                    backoff.failures(),
+                                    e
+                                );
+                                if backoff.failures() > 3 {
+                                    if self.can_failover().await {
+                                        log::error!("Download ts failed after retry");
+                                        // segment is fetched again from the next host
+                                        return Err(RecorderError::ClientError { err: e });
+                                    }
+                                    // no other host to try, losing one segment beats ending
+                                    // the session
+                                    log::error!("Download ts failed after retry, skip it");
+                                    break;
+                                }
+                                tokio::time::sleep(delay).await;
                             }
                         }
                     }
@@ -626,18 +3123,11 @@ impl BiliRecorder {
                 }
 
                 if new_segment_fetched {
+                    *self.failovers.write().await = 0;
                     *self.last_update.write().await = Utc::now().timestamp();
-                    self.db
-                        .update_record(
-                            timestamp,
-                            self.ts_entries
-                                .read()
-                                .await
-                                .iter()
-                                .fold(0.0, |t, e| t + e.length) as i64,
-                            *self.cache_size.read().await,
-                        )
-                        .await?;
+                    self.prune_buffer(&work_dir).await;
+                    self.save_record(false).await?;
+                    self.split_if_needed().await?;
                 } else {
                     // if index content is not changed for a long time, we should return a error to fetch a new stream
                     if *self.last_update.read().await < Utc::now().timestamp() - 10 {
@@ -646,60 +3136,590 @@ impl BiliRecorder {
                             stream: current_stream,
                         });
                     }
-                }
-                // check the current stream is too slow or not
-                if let Some(last_entry) = self.ts_entries.read().await.last() {
-                    let last_entry_time = (last_entry.offset + *self.timestamp.read().await) as i64;
-                    if last_entry_time < Utc::now().timestamp() - 10 {
-                        log::error!(
-                            "Stream is too slow, last entry ts is at {}",
-                            last_entry_time
-                        );
-                        return Err(RecorderError::SlowStream {
-                            stream: current_stream,
+                }
+                // check the current stream is too slow or not, entries stop growing while paused
+                if self.filler.read().await.is_paused() {
+                    return Ok(task_begin_time.elapsed().as_millis());
+                }
+                if let Some(last_entry) = self.ts_entries.read().await.last() {
+                    let last_entry_time = (last_entry.offset + *self.timestamp.read().await) as i64;
+                    if last_entry_time < Utc::now().timestamp() - 10 {
+                        log::error!(
+                            "Stream is too slow, last entry ts is at {}",
+                            last_entry_time
+                        );
+                        return Err(RecorderError::SlowStream {
+                            stream: current_stream,
+                        });
+                    }
+                }
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        }
+        Ok(task_begin_time.elapsed().as_millis())
+    }
+
+    /// Follow parts a live is split into, see `split_if_needed`
+    async fn latest_part(&self, live_id: u64) -> u64 {
+        let cache = self.config.read().await.cache.clone();
+        let mut part = live_id;
+        while let Ok(next) = tokio::fs::read_to_string(format!(
+            "{}/{}/{}/{}",
+            cache, self.room_id, part, PART_INDEX
+        ))
+        .await
+        {
+            match next.trim().parse::<u64>() {
+                Ok(next) if next > part => part = next,
+                _ => break,
+            }
+        }
+        part
+    }
+
+    /// Continue a live that was cut by a crash or restart while room is still streaming,
+    /// new segments are appended after those already in work dir
+    async fn restore(&self, work_dir: &str) {
+        // by the way, header will be set after restore, so we don't need to restore it.
+        let entries = self.get_fs_entries(work_dir).await;
+        if entries.is_empty() {
+            return;
+        }
+        self.ts_entries.write().await.extend_from_slice(&entries);
+        *self.ts_length.write().await = entries.iter().map(|e| e.length).sum();
+        *self.cache_size.write().await = entries.iter().map(|e| e.size).sum();
+        *self.last_sequence.write().await = entries.last().unwrap().sequence;
+        log::info!("Restore {} entries from local file", entries.len());
+        let live_id = *self.timestamp.read().await;
+        if let Err(e) = self
+            .db
+            .new_message(
+                MessageKey::RecordingResumed,
+                &[
+                    self.room_id.to_string(),
+                    live_id.to_string(),
+                    entries.len().to_string(),
+                ],
+            )
+            .await
+        {
+            log::error!("[{}]Add message failed: {}", self.room_id, e);
+        }
+    }
+
+    pub async fn clip(&self, ts: u64, d: f64, output_path: &str) -> Result<String, RecorderError> {
+        let total_length = *self.ts_length.read().await;
+        self.clip_range(ts, total_length - d, total_length, output_path, None)
+            .await
+    }
+
+    /// x and y are relative to first sequence. Clip is re-encoded into `codec`, or the one in
+    /// config if it's none, and keeps codec of the stream if neither is set.
+    pub async fn clip_range(
+        &self,
+        ts: u64,
+        x: f64,
+        y: f64,
+        output_path: &str,
+        codec: Option<String>,
+    ) -> Result<String, RecorderError> {
+        let skip = self.skipped_ranges(ts).await;
+        let file = if *self.timestamp.read().await == ts {
+            self.clip_live_range(x, y, &skip, output_path).await?
+        } else {
+            self.clip_archive_range(ts, x, y, &skip, output_path)
+                .await?
+        };
+        if self.config.read().await.clip_loudnorm {
+            let audio_bitrate = self.config.read().await.clip_profile.audio_bitrate.clone();
+            Self::normalize_clip(&file, audio_bitrate).await?;
+        }
+        let watermark = {
+            let config = self.config.read().await;
+            config
+                .room_watermark
+                .get(&self.room_id.to_string())
+                .or(config.watermark.as_ref())
+                .cloned()
+        };
+        let (codec, mut profile) = {
+            let config = self.config.read().await;
+            (
+                codec.or(config.clip_codec.clone()),
+                config.clip_profile.clone(),
+            )
+        };
+        if let Some(codec) = &codec {
+            profile.codec = codec.clone();
+        }
+        if let Some(watermark) = watermark {
+            // watermark re-encodes anyway, so it's done in codec of clip
+            Self::watermark_clip(&file, watermark, profile).await?;
+        } else if codec.is_some() {
+            Self::encode_clip(&file, profile).await?;
+        }
+        if self.config.read().await.clip_chapters {
+            let chapters = self.clip_chapters(ts, x, y, &skip).await;
+            if !chapters.is_empty() {
+                let length = y - x - Self::skipped_length(&skip, x, y);
+                Self::embed_clip_chapters(&file, chapters, length).await?;
+            }
+        }
+        let container = self.config.read().await.clip_container;
+        if container != Container::Mp4 {
+            return Self::remux_clip(&file, container).await;
+        }
+        Ok(file)
+    }
+
+    /// Remux mp4 clip into container, mp4 file is removed and path of new file returned
+    async fn remux_clip(file: &str, container: Container) -> Result<String, RecorderError> {
+        let target = Path::new(file)
+            .with_extension(container.extension())
+            .to_string_lossy()
+            .to_string();
+        let (input, output) = (file.to_string(), target.clone());
+        ffmpeg::execute_long(move || ffmpeg::remux(&input, &output))
+            .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        let _ = tokio::fs::remove_file(file).await;
+        Ok(target)
+    }
+
+    /// Markers and danmu peaks inside [x, y] as chapters relative to clip start, those in
+    /// `skip` left out and the rest moved up by the skipped time before them
+    async fn clip_chapters(
+        &self,
+        live_id: u64,
+        x: f64,
+        y: f64,
+        skip: &[(f64, f64)],
+    ) -> Vec<export::Chapter> {
+        let mut chapters = Vec::new();
+        match self.db.get_markers(self.room_id, live_id).await {
+            Ok(markers) => {
+                for m in markers {
+                    if m.offset < x || m.offset >= y {
+                        continue;
+                    }
+                    if let Some(offset) = Self::clip_offset(skip, x, m.offset) {
+                        chapters.push(export::Chapter {
+                            offset,
+                            title: m.content,
                         });
                     }
                 }
             }
-            Err(e) => {
-                return Err(e);
+            Err(e) => log::warn!("[{}]Get markers for chapters failed: {}", self.room_id, e),
+        }
+        for h in self.get_danmu_highlights(live_id, 30, 10).await {
+            if h.start < x || h.start >= y {
+                continue;
+            }
+            if let Some(offset) = Self::clip_offset(skip, x, h.start) {
+                chapters.push(export::Chapter {
+                    offset,
+                    title: format!("弹幕高峰 ({})", h.count),
+                });
             }
         }
-        Ok(task_begin_time.elapsed().as_millis())
+        if !chapters.is_empty() && chapters.iter().all(|c| c.offset > 0.0) {
+            chapters.push(export::Chapter {
+                offset: 0.0,
+                title: "开始".into(),
+            });
+        }
+        chapters
     }
 
-    async fn restore(&self, work_dir: &str) {
-        // by the way, header will be set after restore, so we don't need to restore it.
-        let entries = self.get_fs_entries(work_dir).await;
+    /// Write chapters into clip file in place
+    async fn embed_clip_chapters(
+        file: &str,
+        chapters: Vec<export::Chapter>,
+        length: f64,
+    ) -> Result<(), RecorderError> {
+        let temp = format!("{}.chapters.mp4", file);
+        let (input, output) = (file.to_string(), temp.clone());
+        ffmpeg::execute_long(move || ffmpeg::embed_chapters(&input, &output, &chapters, length))
+            .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        if let Err(e) = tokio::fs::rename(&temp, file).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        Ok(())
+    }
+
+    /// Tile cols x rows thumbnails evenly spread over a live into a jpg in its work dir,
+    /// each labeled with its offset. Sheets already generated are reused, those of a live in
+    /// progress for `LIVE_SHEET_TTL`.
+    pub async fn generate_contact_sheet(
+        &self,
+        live_id: u64,
+        cols: u32,
+        rows: u32,
+    ) -> Result<String, RecorderError> {
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let live = *self.timestamp.read().await == live_id;
+        let sheet = format!("{}/contact-{}x{}.jpg", work_dir, cols, rows);
+        if let Ok(meta) = fs::metadata(&sheet).await {
+            let fresh = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.elapsed().ok())
+                .is_some_and(|age| age < LIVE_SHEET_TTL);
+            if !live || fresh {
+                return Ok(sheet);
+            }
+        }
+        let entries = if live {
+            self.ts_entries.read().await.clone()
+        } else {
+            self.get_fs_entries(&work_dir).await
+        };
         if entries.is_empty() {
-            return;
+            return Err(RecorderError::EmptyCache);
         }
-        self.ts_entries.write().await.extend_from_slice(&entries);
-        *self.ts_length.write().await = entries.len() as f64;
-        *self.cache_size.write().await = entries.iter().map(|e| e.size).sum();
-        *self.last_sequence.write().await = entries.last().unwrap().sequence;
-        log::info!("Restore {} entries from local file", entries.len());
+        // calls at the same time each work in a dir of their own
+        let thumb_dir = format!("{}/{}", work_dir, Self::unique_tmp("contact"));
+        std::fs::create_dir_all(&thumb_dir).map_err(|e| RecorderError::IoError { err: e })?;
+        let result = self
+            .tile_thumbnails(&work_dir, live_id, &entries, &thumb_dir, cols, rows)
+            .await;
+        let result = match result {
+            // tiled in temp dir and moved in place, so readers never see half a sheet
+            Ok(tiled) => fs::rename(&tiled, &sheet)
+                .await
+                .map_err(|e| RecorderError::IoError { err: e }),
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_dir_all(&thumb_dir).await;
+        result.map(|_| sheet)
     }
 
-    pub async fn clip(&self, ts: u64, d: f64, output_path: &str) -> Result<String, RecorderError> {
-        let total_length = *self.ts_length.read().await;
-        self.clip_range(ts, total_length - d, total_length, output_path)
+    /// Thumbnails of contact sheet are taken and tiled in `thumb_dir`, returns the sheet there
+    async fn tile_thumbnails(
+        &self,
+        work_dir: &str,
+        live_id: u64,
+        entries: &[TsEntry],
+        thumb_dir: &str,
+        cols: u32,
+        rows: u32,
+    ) -> Result<String, RecorderError> {
+        let first = entries.first().unwrap().offset;
+        let count = (cols * rows) as usize;
+        for i in 0..count {
+            let e = &entries[i * entries.len() / count];
+            let file_list = vec![
+                Self::header_of(work_dir, live_id, e.sequence).await,
+                format!("{}/{}", work_dir, e.url),
+            ];
+            let fetched = self.fetch_offloaded(&file_list).await?;
+            let input = Self::generate_clip(&file_list, thumb_dir, "segment.tmp").await;
+            for f in fetched {
+                let _ = fs::remove_file(f).await;
+            }
+            let input = input?;
+            let output = format!("{}/thumb-{}.png", thumb_dir, i);
+            let secs = (e.offset - first) / 1000;
+            let label = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+            ffmpeg::execute(move || ffmpeg::thumbnail(&input, &output, &label))
+                .await
+                .map_err(|err| RecorderError::ClipError { err })?;
+        }
+        let (pattern, output) = (
+            format!("{}/thumb-%d.png", thumb_dir),
+            format!("{}/sheet.jpg", thumb_dir),
+        );
+        let tiled = output.clone();
+        ffmpeg::execute(move || ffmpeg::tile(&pattern, &output, cols, rows))
             .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        Ok(tiled)
     }
 
-    /// x and y are relative to first sequence
-    pub async fn clip_range(
+    /// Name of a temp file or dir no other call uses at the same time, like `contact-{nanos}.tmp`
+    fn unique_tmp(prefix: &str) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        format!("{}-{}.tmp", prefix, nanos)
+    }
+
+    /// Run scene detection over a finished live and store scene changes as markers, so they
+    /// show up as chapters. Returns offsets of the scenes.
+    pub async fn detect_scenes(&self, live_id: u64) -> Result<Vec<f64>, RecorderError> {
+        if *self.timestamp.read().await == live_id {
+            return Err(RecorderError::ArchiveInUse { ts: live_id });
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let entries = self.get_fs_entries(&work_dir).await;
+        let Some(first) = entries.first() else {
+            return Err(RecorderError::EmptyCache);
+        };
+        let mut file_list = vec![format!("{}/h{}.m4s", work_dir, live_id)];
+        file_list.extend(entries.iter().map(|e| format!("{}/{}", work_dir, e.url)));
+        let fetched = self.fetch_offloaded(&file_list).await?;
+        let result =
+            ffmpeg::execute_long(move || ffmpeg::detect_scenes(file_list, SCENE_THRESHOLD)).await;
+        for f in fetched {
+            let _ = fs::remove_file(f).await;
+        }
+        let mut scenes: Vec<f64> = Vec::new();
+        for offset in result.map_err(|err| RecorderError::ClipError { err })? {
+            if scenes
+                .last()
+                .map_or(offset >= SCENE_MIN_GAP, |l| offset - l >= SCENE_MIN_GAP)
+            {
+                scenes.push(offset);
+            }
+        }
+        self.db
+            .remove_markers(self.room_id, live_id, SCENE_MARKER)
+            .await?;
+        let start = first.offset / 1000;
+        for offset in scenes.iter() {
+            let realtime = (live_id + start) as i64 + *offset as i64;
+            self.db
+                .add_marker(self.room_id, live_id, *offset, realtime, SCENE_MARKER)
+                .await?;
+        }
+        log::info!(
+            "[{}]Detected {} scenes in {}",
+            self.room_id,
+            scenes.len(),
+            live_id
+        );
+        Ok(scenes)
+    }
+
+    /// Export audio of a whole live into output_path
+    pub async fn export_audio(
         &self,
-        ts: u64,
-        x: f64,
-        y: f64,
+        live_id: u64,
+        format: AudioFormat,
         output_path: &str,
     ) -> Result<String, RecorderError> {
-        if *self.timestamp.read().await == ts {
-            self.clip_live_range(x, y, output_path).await
+        let live = *self.timestamp.read().await == live_id;
+        let length = if live {
+            *self.ts_length.read().await
         } else {
-            self.clip_archive_range(ts, x, y, output_path).await
+            self.db.get_record(self.room_id, live_id).await?.length as f64
+        };
+        let clip = if live {
+            self.clip_live_range(0.0, length, &[], output_path).await?
+        } else {
+            self.clip_archive_range(live_id, 0.0, length, &[], output_path)
+                .await?
+        };
+        let file = Path::new(&clip)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .to_string();
+        let (input, output) = (clip.clone(), file.clone());
+        let audio_bitrate = self.config.read().await.clip_profile.audio_bitrate.clone();
+        let result = ffmpeg::execute_long(move || {
+            ffmpeg::extract_audio(&input, &output, format, &audio_bitrate)
+        })
+        .await;
+        let _ = tokio::fs::remove_file(&clip).await;
+        result.map_err(|err| RecorderError::ClipError { err })?;
+        Ok(file)
+    }
+
+    /// Join header and segments of an archive into `file_name` in its work dir, a single
+    /// fragmented mp4 that is served by hls server like other cache files
+    pub async fn package_archive(
+        &self,
+        live_id: u64,
+        file_name: &str,
+    ) -> Result<String, RecorderError> {
+        if live_id == *self.timestamp.read().await {
+            return Err(RecorderError::ArchiveInUse { ts: live_id });
+        }
+        let work_dir = format!(
+            "{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let entries = self.get_fs_entries(&work_dir).await;
+        if entries.is_empty() {
+            return Err(RecorderError::EmptyCache);
+        }
+        let skip = self.skipped_ranges(live_id).await;
+        let first = entries.first().unwrap().offset;
+        let mut file_list = vec![format!("{}/h{}.m4s", work_dir, live_id)];
+        file_list.extend(
+            entries
+                .iter()
+                .filter(|e| !Self::in_ranges(&skip, e.offset - first))
+                .map(|e| format!("{}/{}", work_dir, e.url)),
+        );
+        let fetched = self.fetch_offloaded(&file_list).await?;
+        let result = Self::generate_clip(&file_list, &work_dir, file_name).await;
+        for f in fetched {
+            let _ = fs::remove_file(f).await;
+        }
+        result
+    }
+
+    /// Package of an archive kept in its work dir for torrent web seeds, see `package_archive`
+    pub fn seed_package(live_id: u64) -> String {
+        format!("{}.mp4", live_id)
+    }
+
+    /// Remove the package kept for web seeds, it's outdated once segments of archive change
+    pub async fn remove_package(&self, live_id: u64) {
+        let package = format!(
+            "{}/{}/{}/{}",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id,
+            Self::seed_package(live_id)
+        );
+        if fs::remove_file(&package).await.is_ok() {
+            log::info!("[{}]Removed package of {}", self.room_id, live_id);
+        }
+    }
+
+    /// Package an archive into output_path as `{room_id}_{live_id}` in the clip container, with a
+    /// nfo sidecar and poster from archive cover for media servers if `nfo` is set. Returns path
+    /// of the video.
+    pub async fn export_archive(
+        &self,
+        live_id: u64,
+        output_path: &str,
+        nfo: bool,
+    ) -> Result<String, RecorderError> {
+        // packaged apart from the web seed package, which has to stay in place
+        let package = self.package_archive(live_id, "export.tmp").await?;
+        if self.config.read().await.clip_chapters {
+            let length = self.db.get_record(self.room_id, live_id).await?.length as f64;
+            // package leaves the same ranges out
+            let skip = self.skipped_ranges(live_id).await;
+            let chapters = self.clip_chapters(live_id, 0.0, length, &skip).await;
+            if !chapters.is_empty() {
+                let length = length - Self::skipped_length(&skip, 0.0, length);
+                if let Err(e) = Self::embed_clip_chapters(&package, chapters, length).await {
+                    let _ = tokio::fs::remove_file(&package).await;
+                    return Err(e);
+                }
+            }
+        }
+        let name = format!("{}_{}", self.room_id, live_id);
+        let container = self.config.read().await.clip_container;
+        let file = format!("{}/{}.{}", output_path, name, container.extension());
+        // remuxed straight into output, package is fragmented and may be on another disk
+        let (input, output) = (package.clone(), file.clone());
+        let result = ffmpeg::execute_long(move || ffmpeg::remux(&input, &output)).await;
+        let _ = tokio::fs::remove_file(&package).await;
+        result.map_err(|err| RecorderError::ClipError { err })?;
+        if !nfo {
+            return Ok(file);
+        }
+        let record = self.db.get_record(self.room_id, live_id).await?;
+        let cover = format!(
+            "{}/{}/{}/cover.jpg",
+            self.config.read().await.cache,
+            self.room_id,
+            live_id
+        );
+        let poster = format!("{}-poster.jpg", name);
+        let thumb = match tokio::fs::copy(&cover, format!("{}/{}", output_path, poster)).await {
+            Ok(_) => Some(poster),
+            Err(_) => None,
+        };
+        let plot = self
+            .db
+            .get_markers(self.room_id, live_id)
+            .await?
+            .iter()
+            .map(|m| {
+                let secs = m.offset as u64;
+                format!(
+                    "{:02}:{:02}:{:02} {}",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60,
+                    m.content
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let date = chrono::DateTime::parse_from_rfc3339(&record.created_at)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let nfo = Nfo {
+            title: record.title,
+            actor: self.user_info.read().await.user_name.clone(),
+            date,
+            plot,
+            thumb,
+        };
+        tokio::fs::write(format!("{}/{}.nfo", output_path, name), nfo.render())
+            .await
+            .map_err(|e| RecorderError::IoError { err: e })?;
+        Ok(file)
+    }
+
+    /// Burn watermark into clip file in place
+    async fn watermark_clip(
+        file: &str,
+        watermark: Watermark,
+        profile: ArchiveProfile,
+    ) -> Result<(), RecorderError> {
+        let temp = format!("{}.watermark.mp4", file);
+        let (input, output) = (file.to_string(), temp.clone());
+        ffmpeg::execute_long(move || ffmpeg::watermark(&input, &output, &watermark, &profile))
+            .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        if let Err(e) = tokio::fs::rename(&temp, file).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        Ok(())
+    }
+
+    /// Re-encode clip file in place
+    async fn encode_clip(file: &str, profile: ArchiveProfile) -> Result<(), RecorderError> {
+        let temp = format!("{}.encode.mp4", file);
+        let (input, output) = (file.to_string(), temp.clone());
+        ffmpeg::execute_long(move || ffmpeg::encode(&input, &output, &profile))
+            .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        if let Err(e) = tokio::fs::rename(&temp, file).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        Ok(())
+    }
+
+    /// Normalize loudness of clip file in place
+    async fn normalize_clip(file: &str, audio_bitrate: String) -> Result<(), RecorderError> {
+        let temp = format!("{}.loudnorm.mp4", file);
+        let (input, output) = (file.to_string(), temp.clone());
+        ffmpeg::execute_long(move || ffmpeg::loudnorm(&input, &output, &audio_bitrate))
+            .await
+            .map_err(|err| RecorderError::ClipError { err })?;
+        if let Err(e) = tokio::fs::rename(&temp, file).await {
+            return Err(RecorderError::IoError { err: e });
         }
+        Ok(())
     }
 
     pub async fn clip_archive_range(
@@ -707,6 +3727,7 @@ impl BiliRecorder {
         ts: u64,
         x: f64,
         y: f64,
+        skip: &[(f64, f64)],
         output_path: &str,
     ) -> Result<String, RecorderError> {
         log::info!("Create archive clip for range [{}, {}]", x, y);
@@ -728,7 +3749,9 @@ impl BiliRecorder {
                 if e.offset - offset < begin {
                     continue;
                 }
-                file_list.push(format!("{}/{}", work_dir, e.url));
+                if !Self::in_ranges(skip, e.offset - offset) {
+                    file_list.push(format!("{}/{}", work_dir, e.url));
+                }
                 if e.offset - offset > end {
                     break;
                 }
@@ -742,13 +3765,19 @@ impl BiliRecorder {
             Utc::now().format("%m%d%H%M%S"),
             y - x
         );
-        Self::generate_clip(&file_list, output_path, &file_name).await
+        let fetched = self.fetch_offloaded(&file_list).await?;
+        let result = Self::generate_clip(&file_list, output_path, &file_name).await;
+        for f in fetched {
+            let _ = fs::remove_file(f).await;
+        }
+        result
     }
 
     pub async fn clip_live_range(
         &self,
         x: f64,
         y: f64,
+        skip: &[(f64, f64)],
         output_path: &str,
     ) -> Result<String, RecorderError> {
         log::info!("Create live clip for range [{}, {}]", x, y);
@@ -766,7 +3795,9 @@ impl BiliRecorder {
             if e.offset - offset < begin {
                 continue;
             }
-            to_combine.push(e);
+            if !Self::in_ranges(skip, e.offset - offset) {
+                to_combine.push(e);
+            }
             if e.offset - offset > end {
                 break;
             }
@@ -802,7 +3833,12 @@ impl BiliRecorder {
             Utc::now().format("%m%d%H%M%S"),
             y - x
         );
-        Self::generate_clip(&file_list, output_path, &file_name).await
+        let fetched = self.fetch_offloaded(&file_list).await?;
+        let result = Self::generate_clip(&file_list, output_path, &file_name).await;
+        for f in fetched {
+            let _ = fs::remove_file(f).await;
+        }
+        result
     }
 
     async fn generate_clip(
@@ -873,6 +3909,8 @@ impl BiliRecorder {
         if entries.is_empty() {
             return m3u8_content;
         }
+        let storages = self.config.read().await.offload_storages();
+        let part_headers = Self::get_part_headers(&work_dir, timestamp).await;
         let mut last_sequence = entries.first().unwrap().sequence;
         m3u8_content += &format!("#EXT-X-OFFSET:{}\n", entries.first().unwrap().offset);
         for e in entries {
@@ -880,12 +3918,19 @@ impl BiliRecorder {
             if current_seq - last_sequence > 1 {
                 m3u8_content += "#EXT-X-DISCONTINUITY\n"
             }
+            // merged parts are played with their own header
+            if let Some((_, header)) = part_headers.iter().find(|(s, _)| *s == current_seq) {
+                m3u8_content += &format!(
+                    "#EXT-X-MAP:URI=\"/{}/{}/{}\"\n",
+                    self.room_id, timestamp, header
+                );
+            }
             // add #EXT-X-PROGRAM-DATE-TIME with ISO 8601 date
             let ts = timestamp + e.offset / 1000;
             let date_str = Utc.timestamp_opt(ts as i64, 0).unwrap().to_rfc3339();
             m3u8_content += &format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", date_str);
             m3u8_content += &format!("#EXTINF:{:.2},\n", e.length);
-            m3u8_content += &format!("/{}/{}/{}\n", self.room_id, timestamp, e.url);
+            m3u8_content += &format!("{}\n", self.segment_url(&storages, timestamp, &e));
 
             last_sequence = current_seq;
         }
@@ -895,6 +3940,40 @@ impl BiliRecorder {
         m3u8_content
     }
 
+    /// Headers of parts merged into archive by the sequence they start from, see `merge_archives`
+    async fn get_part_headers(path: &str, live_id: u64) -> Vec<(u64, String)> {
+        let mut ret = Vec::new();
+        let Ok(mut direntry) = fs::read_dir(path).await else {
+            return ret;
+        };
+        let prefix = format!("h{}-", live_id);
+        while let Some(Ok(e)) = direntry.next().await {
+            let file_name = e.file_name().to_string_lossy().to_string();
+            let sequence = file_name
+                .strip_prefix(&prefix)
+                .and_then(|s| s.strip_suffix(".m4s"))
+                .and_then(|s| s.parse::<u64>().ok());
+            if let Some(sequence) = sequence {
+                ret.push((sequence, file_name));
+            }
+        }
+        ret.sort();
+        ret
+    }
+
+    /// Header that segment of `sequence` in archive plays with, merged parts have their own
+    async fn header_of(work_dir: &str, live_id: u64, sequence: u64) -> String {
+        let part = Self::get_part_headers(work_dir, live_id)
+            .await
+            .into_iter()
+            .filter(|(s, _)| *s <= sequence)
+            .last();
+        match part {
+            Some((_, header)) => format!("{}/{}", work_dir, header),
+            None => format!("{}/h{}.m4s", work_dir, live_id),
+        }
+    }
+
     /// Fetch HLS segments from local cached file, header is excluded
     async fn get_fs_entries(&self, path: &str) -> Vec<TsEntry> {
         let mut ret = Vec::new();
@@ -950,8 +4029,15 @@ impl BiliRecorder {
                 sequence,
                 length: 1.0,
                 size: e.metadata().await.unwrap().len(),
+                remote: None,
             });
         }
+        // offloaded segments, skipping ones fetched back for clipping
+        for e in self.get_offloaded_entries(path).await {
+            if !ret.iter().any(|local| local.url == e.url) {
+                ret.push(e);
+            }
+        }
         ret.sort_by(|a, b| a.sequence.cmp(&b.sequence));
         if ret.is_empty() {
             return ret;
@@ -967,6 +4053,57 @@ impl BiliRecorder {
         ret
     }
 
+    /// Entries listed in offload index of work dir at `path`, lines are
+    /// `{file}:{size}:{location}`, ones written before location was recorded are in current
+    /// storage
+    async fn get_offloaded_entries(&self, path: &str) -> Vec<TsEntry> {
+        let mut ret = Vec::new();
+        let Ok(index) = tokio::fs::read_to_string(format!("{}/{}", path, OFFLOAD_INDEX)).await
+        else {
+            return ret;
+        };
+        let current = self
+            .config
+            .read()
+            .await
+            .offload
+            .as_ref()
+            .map(|s| s.location());
+        for line in index.lines() {
+            let mut fields = line.splitn(3, ':');
+            let (Some(file_name), Some(size)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let location = fields
+                .next()
+                .map(|l| l.to_string())
+                .or(current.clone())
+                .unwrap_or_default();
+            let Some((offset, sequence)) = file_name
+                .split('.')
+                .next()
+                .and_then(|meta| meta.split_once('-'))
+                .and_then(|(offset, sequence)| {
+                    Some((
+                        u64::from_str_radix(offset, 16).ok()?,
+                        sequence.parse::<u64>().ok()?,
+                    ))
+                })
+            else {
+                continue;
+            };
+            ret.push(TsEntry {
+                url: file_name.to_string(),
+                offset,
+                sequence,
+                length: 1.0,
+                size: size.parse().unwrap_or(0),
+                remote: Some(location),
+            });
+        }
+        ret
+    }
+
     /// if fetching live/last stream m3u8, all entries are cached in memory, so it will be much faster than read_dir
     async fn generate_live_m3u8(&self) -> String {
         let live_status = *self.live_status.read().await;
@@ -993,6 +4130,7 @@ impl BiliRecorder {
             return m3u8_content;
         }
         let timestamp = *self.timestamp.read().await;
+        let storages = self.config.read().await.offload_storages();
         let mut last_sequence = entries.first().unwrap().sequence;
         m3u8_content += &format!("#EXT-X-OFFSET:{}\n", entries.first().unwrap().offset);
         for entry in entries.iter() {
@@ -1006,9 +4144,7 @@ impl BiliRecorder {
             m3u8_content += &format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", date_str);
             m3u8_content += &format!("#EXTINF:{:.2},\n", entry.length,);
             last_sequence = entry.sequence;
-            let file_name = entry.url.split('/').last().unwrap();
-            let local_url = format!("/{}/{}/{}", self.room_id, timestamp, file_name);
-            m3u8_content += &format!("{}\n", local_url);
+            m3u8_content += &format!("{}\n", self.segment_url(&storages, timestamp, entry));
         }
         // let player know stream is closed
         if !live_status {
@@ -1017,6 +4153,148 @@ impl BiliRecorder {
         m3u8_content
     }
 
+    /// Wall clock time in ms of the first cached segment, where playback of live starts
+    pub async fn get_playback_start(&self, live_id: u64) -> u64 {
+        let first_offset = if live_id == *self.timestamp.read().await {
+            self.ts_entries.read().await.first().map(|e| e.offset)
+        } else {
+            let work_dir = format!(
+                "{}/{}/{}",
+                self.config.read().await.cache,
+                self.room_id,
+                live_id
+            );
+            self.get_fs_entries(&work_dir)
+                .await
+                .first()
+                .map(|e| e.offset)
+        };
+        live_id * 1000 + first_offset.unwrap_or(0)
+    }
+
+    /// Wall clock start time in ms of every segment in live
+    async fn get_segment_offsets(&self, live_id: u64) -> Vec<u64> {
+        let entries = if live_id == *self.timestamp.read().await {
+            self.ts_entries.read().await.clone()
+        } else {
+            let work_dir = format!(
+                "{}/{}/{}",
+                self.config.read().await.cache,
+                self.room_id,
+                live_id
+            );
+            self.get_fs_entries(&work_dir).await
+        };
+        entries.iter().map(|e| live_id * 1000 + e.offset).collect()
+    }
+
+    /// Danmu remapped onto recorded media, so they stay in sync with exported video
+    /// when the stream dropped during live
+    async fn get_resynced_danmu(
+        &self,
+        live_id: u64,
+        entries: &[DanmuEntry],
+    ) -> (Vec<DanmuEntry>, u64) {
+        let offsets = self.get_segment_offsets(live_id).await;
+        match offsets.first() {
+            Some(start) => (resync::resync(entries, &offsets), *start),
+            None => (entries.to_vec(), self.get_playback_start(live_id).await),
+        }
+    }
+
+    pub async fn export_chat_replay(&self, live_id: u64) -> String {
+        let entries = self.get_danmu_record(live_id).await;
+        let (entries, start) = self.get_resynced_danmu(live_id, &entries).await;
+        export::chat_replay(&entries, start)
+    }
+
+    pub async fn export_danmu(
+        &self,
+        live_id: u64,
+        options: &export::ExportDanmuOptions,
+    ) -> Result<String, RecorderError> {
+        let record = self.db.get_record(self.room_id, live_id).await?;
+        let entries = match &options.lang {
+            Some(lang) => self.get_translated_danmu(live_id, lang).await?,
+            None => self.get_danmu_record(live_id).await,
+        };
+        let (entries, start) = self.get_resynced_danmu(live_id, &entries).await;
+        let collapse_window = match options.collapse_window {
+            Some(window) => window,
+            None => self.config.read().await.danmu_collapse_window,
+        };
+        let entries = collapse::collapse(&entries, collapse_window);
+        let user_name = self.user_info.read().await.user_name.clone();
+        let meta = export::LiveMeta {
+            room_id: self.room_id,
+            user_name: &user_name,
+            title: &record.title,
+        };
+        Ok(export::export(&entries, start, &meta, options))
+    }
+
+    pub async fn get_danmu_heatmap(&self, live_id: u64, bucket_secs: u64) -> Vec<HeatmapBucket> {
+        let entries = self.get_danmu_record(live_id).await;
+        let start = self.get_playback_start(live_id).await;
+        heatmap::heatmap(&entries, start, bucket_secs)
+    }
+
+    pub async fn get_danmu_highlights(
+        &self,
+        live_id: u64,
+        bucket_secs: u64,
+        count: usize,
+    ) -> Vec<Highlight> {
+        let buckets = self.get_danmu_heatmap(live_id, bucket_secs).await;
+        heatmap::highlights(&buckets, bucket_secs, count)
+    }
+
+    fn translated_danmu_path(&self, cache: &str, live_id: u64, lang: &str) -> String {
+        format!("{}/{}/{}/danmu.{}.txt", cache, self.room_id, live_id, lang)
+    }
+
+    /// Translate danmu of live into a separate track stored next to danmu.txt,
+    /// returns the number of translated lines
+    pub async fn translate_danmu(&self, live_id: u64) -> Result<usize, RecorderError> {
+        let (cache, translation, proxy) = {
+            let config = self.config.read().await;
+            (
+                config.cache.clone(),
+                config.translation.clone(),
+                config.proxy_for(self.room_id),
+            )
+        };
+        let translation = translation.ok_or(TranslateError::NotConfigured)?;
+        let entries = self.get_danmu_record(live_id).await;
+        let translated = translate::translate(&translation, &entries, proxy.as_deref()).await?;
+        let content: String = translated
+            .iter()
+            .map(|e| format!("{}:{}\n", e.ts, e.content))
+            .collect();
+        let path = self.translated_danmu_path(&cache, live_id, &translation.target_lang);
+        if let Err(e) = tokio::fs::write(&path, content).await {
+            return Err(RecorderError::IoError { err: e });
+        }
+        Ok(translated.len())
+    }
+
+    pub async fn get_translated_danmu(
+        &self,
+        live_id: u64,
+        lang: &str,
+    ) -> Result<Vec<DanmuEntry>, RecorderError> {
+        let cache = self.config.read().await.cache.clone();
+        let path = self.translated_danmu_path(&cache, live_id, lang);
+        if !Path::new(&path).exists() {
+            return Err(RecorderError::TranslationNotFound { lang: lang.into() });
+        }
+        let sync_policy = self.config.read().await.danmu_sync;
+        match DanmuStorage::new(&path, sync_policy).await {
+            Some(storage) => Ok(storage.get_entries().await),
+            None => Err(RecorderError::TranslationNotFound { lang: lang.into() }),
+        }
+    }
+
     pub async fn get_danmu_record(&self, ts: u64) -> Vec<DanmuEntry> {
         if ts == *self.timestamp.read().await {
             // just return current cache content
@@ -1034,7 +4312,8 @@ impl BiliRecorder {
                 "danmu.txt"
             );
             log::info!("loading danmu cache from {}", cache_file_path);
-            let storage = DanmuStorage::new(&cache_file_path).await;
+            let sync_policy = self.config.read().await.danmu_sync;
+            let storage = DanmuStorage::new(&cache_file_path, sync_policy).await;
             if storage.is_none() {
                 return Vec::new();
             }
@@ -1043,3 +4322,105 @@ impl BiliRecorder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// 2024-01-05 is a Friday
+    fn at(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn window(days: Vec<u32>, start: &str, end: &str) -> RecordWindow {
+        RecordWindow {
+            days,
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    #[test]
+    fn window_within_day() {
+        let w = window(vec![5], "08:00", "12:00");
+        assert!(w.contains(at(5, 8, 0)));
+        assert!(w.contains(at(5, 11, 59)));
+        assert!(!w.contains(at(5, 12, 0)));
+        assert!(!w.contains(at(6, 9, 0)));
+    }
+
+    #[test]
+    fn window_across_midnight_belongs_to_start_day() {
+        let w = window(vec![5], "22:00", "02:00");
+        assert!(w.contains(at(5, 23, 0)));
+        assert!(w.contains(at(6, 1, 30)));
+        assert!(!w.contains(at(6, 2, 0)));
+        assert!(!w.contains(at(5, 1, 0)));
+        assert!(!w.contains(at(6, 23, 0)));
+        // sunday night goes on into monday
+        let w = window(vec![7], "23:00", "01:00");
+        assert!(w.contains(at(8, 0, 30)));
+        assert!(!w.contains(at(7, 0, 30)));
+    }
+
+    #[test]
+    fn window_validation() {
+        assert!(window(vec![], "22:00", "02:00").is_valid());
+        assert!(!window(vec![0], "22:00", "02:00").is_valid());
+        assert!(!window(vec![], "25:00", "02:00").is_valid());
+    }
+
+    #[test]
+    fn slots_go_to_higher_priority() {
+        let slots = RecordingSlots::default();
+        assert!(slots.acquire(1, 0, 1));
+        assert!(!slots.acquire(2, 1, 1));
+        assert!(!slots.acquire(3, 5, 1));
+        slots.release(1);
+        // room 3 waits with a higher priority, so room 2 can't take the free slot
+        assert!(!slots.acquire(2, 1, 1));
+        assert!(slots.acquire(3, 5, 1));
+        assert!(!slots.is_waiting(3));
+        assert!(slots.is_waiting(2));
+        // taken slot is kept on later calls
+        assert!(slots.acquire(3, 5, 1));
+    }
+
+    #[test]
+    fn slots_unlimited() {
+        let slots = RecordingSlots::default();
+        for room in 0..10 {
+            assert!(slots.acquire(room, 0, 0));
+        }
+    }
+
+    #[test]
+    fn split_limit() {
+        let limit = SplitLimit {
+            max_minutes: Some(60),
+            max_mb: Some(0),
+        };
+        assert!(!limit.exceeded(3599.0, u64::MAX));
+        assert!(limit.exceeded(3600.0, 0));
+        let limit = SplitLimit {
+            max_minutes: None,
+            max_mb: Some(1),
+        };
+        assert!(!limit.exceeded(1e9, 1024 * 1024 - 1));
+        assert!(limit.exceeded(0.0, 1024 * 1024));
+    }
+
+    #[test]
+    fn clip_offset_skips_ranges() {
+        let skip = [(10.0, 20.0), (30.0, 35.0)];
+        assert_eq!(BiliRecorder::clip_offset(&skip, 5.0, 8.0), Some(3.0));
+        assert_eq!(BiliRecorder::clip_offset(&skip, 5.0, 15.0), None);
+        assert_eq!(BiliRecorder::clip_offset(&skip, 5.0, 25.0), Some(10.0));
+        assert_eq!(BiliRecorder::clip_offset(&skip, 15.0, 40.0), Some(15.0));
+        assert_eq!(BiliRecorder::skipped_length(&skip, 0.0, 32.0), 12.0);
+    }
+}
@@ -0,0 +1,140 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// Delays of repeated requests, doubling from `base_ms` up to `max_ms` while they keep failing.
+/// Every delay is randomly spread within `jitter` of it around itself, so recorders started
+/// together don't hit the server at the same moments while keeping the same average.
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct BackoffPolicy {
+    pub base_ms: u64,
+    pub max_ms: u64,
+    /// 0 to 1
+    pub jitter: f64,
+    /// first delay of segment download retries, they have to finish before segments roll
+    /// out of the playlist
+    pub segment_base_ms: u64,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        BackoffPolicy {
+            base_ms: 2000,
+            max_ms: 60000,
+            jitter: 0.5,
+            segment_base_ms: 200,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let spread = jitter / 2.0;
+        delay.mul_f64(1.0 + rand::thread_rng().gen_range(-spread..=spread))
+    }
+
+    /// Same policy starting from `segment_base_ms`, for segment download retries
+    pub fn for_segments(&self) -> BackoffPolicy {
+        BackoffPolicy {
+            base_ms: self.segment_base_ms,
+            ..*self
+        }
+    }
+}
+
+/// Failures in a row of one kind of request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn failures(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn fail(&mut self) {
+        self.attempt = self.attempt.saturating_add(1);
+    }
+
+    /// Record another failure and get the delay before retrying
+    pub fn next(&mut self, policy: &BackoffPolicy) -> Duration {
+        self.fail();
+        self.delay(policy)
+    }
+
+    /// Delay after the failures so far, `base_ms` for the first one
+    fn delay(&self, policy: &BackoffPolicy) -> Duration {
+        let exp = self.attempt.saturating_sub(1).min(16);
+        let delay = policy
+            .base_ms
+            .saturating_mul(1 << exp)
+            .min(policy.max_ms.max(policy.base_ms));
+        policy.jittered(Duration::from_millis(delay))
+    }
+
+    /// Delay of regular polling, stretched by backoff while requests are failing
+    pub fn poll(&self, interval: Duration, policy: &BackoffPolicy) -> Duration {
+        let interval = policy.jittered(interval);
+        if self.attempt == 0 {
+            return interval;
+        }
+        self.delay(policy).max(interval)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> BackoffPolicy {
+        BackoffPolicy {
+            base_ms: 1000,
+            max_ms: 5000,
+            jitter: 0.0,
+            segment_base_ms: 200,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u128> = (0..4)
+            .map(|_| backoff.next(&policy()).as_millis())
+            .collect();
+        assert_eq!(delays, [1000, 2000, 4000, 5000]);
+        backoff.reset();
+        assert_eq!(backoff.next(&policy()).as_millis(), 1000);
+    }
+
+    #[test]
+    fn poll_is_interval_without_failures() {
+        let mut backoff = Backoff::default();
+        let interval = Duration::from_secs(10);
+        assert_eq!(backoff.poll(interval, &policy()), interval);
+        backoff.fail();
+        backoff.fail();
+        backoff.fail();
+        backoff.fail();
+        assert_eq!(
+            backoff.poll(Duration::from_secs(1), &policy()).as_millis(),
+            5000
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_defaulted() {
+        let policy: BackoffPolicy = toml::from_str("base_ms = 500").unwrap();
+        assert_eq!(policy.base_ms, 500);
+        assert_eq!(policy.max_ms, BackoffPolicy::default().max_ms);
+        assert_eq!(policy.segment_base_ms, 200);
+    }
+}
@@ -2,6 +2,8 @@ pub mod errors;
 pub mod profile;
 pub mod response;
 use crate::database::account::AccountRow;
+use crate::recorder::ratelimit::RateLimiter;
+use crate::recorder::writer::{self, WritePolicy};
 
 use errors::BiliClientError;
 use pct_str::PctString;
@@ -34,6 +36,8 @@ pub struct RoomInfo {
     pub room_keyframe_url: String,
     pub room_title: String,
     pub user_id: u64,
+    /// online count shown in room
+    pub online: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -44,6 +48,32 @@ pub struct UserInfo {
     pub user_avatar_url: String,
 }
 
+/// What a platform supports, so frontend can hide features instead of failing at runtime
+#[derive(Serialize, Clone, Debug)]
+pub struct PlatformCapabilities {
+    pub platform: &'static str,
+    pub danmu: bool,
+    pub gifts: bool,
+    pub quality_selection: bool,
+    pub login_methods: Vec<&'static str>,
+    pub send_danmaku: bool,
+}
+
+pub fn capabilities() -> PlatformCapabilities {
+    PlatformCapabilities {
+        platform: "bilibili",
+        danmu: true,
+        gifts: true,
+        quality_selection: true,
+        login_methods: vec!["qrcode", "cookie"],
+        send_danmaku: true,
+    }
+}
+
+/// Quality number (qn) of play url api: 原画 10000, 蓝光 400, 超清 250, 高清 150.
+/// Server falls back to the best one below if a room doesn't provide it.
+pub const QN_ORIGINAL: u64 = 10000;
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QrInfo {
@@ -64,12 +94,28 @@ pub struct BiliClient {
     headers: reqwest::header::HeaderMap,
 }
 
+/// Video codec preferred in play url, hevc is smaller at the same quality but not offered
+/// by every room, avc is used then
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamCodec {
+    #[default]
+    Avc,
+    Hevc,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum StreamType {
     TS,
     FMP4,
 }
 
+#[derive(Clone, Debug)]
+pub struct StreamHost {
+    pub host: String,
+    pub extra: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct BiliStream {
     pub format: StreamType,
@@ -77,6 +123,8 @@ pub struct BiliStream {
     pub path: String,
     pub extra: String,
     pub expire: i64,
+    /// all candidate cdn hosts provided by play url api
+    pub hosts: Vec<StreamHost>,
 }
 
 impl fmt::Display for BiliStream {
@@ -90,16 +138,55 @@ impl fmt::Display for BiliStream {
 }
 
 impl BiliStream {
-    pub fn new(format: StreamType, base_url: &str, host: &str, extra: &str) -> BiliStream {
+    /// hosts must not be empty, the first one is used by default
+    pub fn new(format: StreamType, base_url: &str, hosts: Vec<StreamHost>) -> BiliStream {
+        let first = hosts.first().unwrap();
         BiliStream {
             format,
-            host: host.into(),
+            host: first.host.clone(),
             path: BiliStream::get_path(base_url),
-            extra: extra.into(),
-            expire: BiliStream::get_expire(extra).unwrap(),
+            extra: first.extra.clone(),
+            expire: BiliStream::get_expire(&first.extra).unwrap(),
+            hosts,
         }
     }
 
+    /// Same stream served by another cdn host
+    pub fn with_host(&self, host: &StreamHost) -> BiliStream {
+        BiliStream {
+            host: host.host.clone(),
+            extra: host.extra.clone(),
+            expire: BiliStream::get_expire(&host.extra).unwrap_or(self.expire),
+            ..self.clone()
+        }
+    }
+
+    /// Switch to the first host in ranking that this stream provides,
+    /// stream is not changed if none of them is provided
+    pub fn prefer(&mut self, ranking: &[String]) {
+        let preferred = ranking
+            .iter()
+            .find_map(|r| self.hosts.iter().find(|h| &h.host == r))
+            .cloned();
+        if let Some(host) = preferred {
+            *self = self.with_host(&host);
+        }
+    }
+
+    /// Same stream on the host after current one in candidates, wrapping around;
+    /// None if there is no other host
+    pub fn next_host(&self) -> Option<BiliStream> {
+        if self.hosts.len() < 2 {
+            return None;
+        }
+        let current = self
+            .hosts
+            .iter()
+            .position(|h| h.host == self.host)
+            .unwrap_or(0);
+        Some(self.with_host(&self.hosts[(current + 1) % self.hosts.len()]))
+    }
+
     pub fn index(&self) -> String {
         format!("{}{}{}?{}", self.host, self.path, "index.m3u8", self.extra)
     }
@@ -128,10 +215,20 @@ impl BiliStream {
 
 impl BiliClient {
     pub fn new() -> Result<BiliClient, BiliClientError> {
+        Self::with_proxy(None)
+    }
+
+    /// All requests go through `proxy`, like http://127.0.0.1:7890 or socks5://host:port
+    pub fn with_proxy(proxy: Option<&str>) -> Result<BiliClient, BiliClientError> {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert("user-agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/111.0.0.0 Safari/537.36".parse().unwrap());
 
-        if let Ok(client) = Client::builder().timeout(Duration::from_secs(10)).build() {
+        let mut builder = Client::builder().timeout(Duration::from_secs(10));
+        if let Some(proxy) = proxy {
+            builder =
+                builder.proxy(reqwest::Proxy::all(proxy).map_err(|_| BiliClientError::InvalidUrl)?);
+        }
+        if let Ok(client) = builder.build() {
             Ok(BiliClient { client, headers })
         } else {
             Err(BiliClientError::InitClientError)
@@ -222,6 +319,26 @@ impl BiliClient {
         Ok(())
     }
 
+    /// Request nav with account cookies, so the login session is seen active
+    pub async fn keep_alive(&self, account: &AccountRow) -> Result<(), BiliClientError> {
+        let mut headers = self.headers.clone();
+        headers.insert("cookie", account.cookies.parse().unwrap());
+        let res: serde_json::Value = self
+            .client
+            .get("https://api.bilibili.com/x/web-interface/nav")
+            .headers(headers)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if res["code"].as_i64().unwrap_or(-1) != 0
+            || !res["data"]["isLogin"].as_bool().unwrap_or(false)
+        {
+            return Err(BiliClientError::InvalidCode);
+        }
+        Ok(())
+    }
+
     pub async fn get_user_info(
         &self,
         webid: &str,
@@ -308,6 +425,7 @@ impl BiliClient {
         let live_status = res["data"]["live_status"]
             .as_u64()
             .ok_or(BiliClientError::InvalidValue)? as u8;
+        let online = res["data"]["online"].as_u64().unwrap_or(0);
         Ok(RoomInfo {
             room_id,
             room_title,
@@ -315,6 +433,7 @@ impl BiliClient {
             room_keyframe_url,
             user_id,
             live_status,
+            online,
         })
     }
 
@@ -322,14 +441,20 @@ impl BiliClient {
         &self,
         account: &AccountRow,
         room_id: u64,
+        qn: u64,
+        codec: StreamCodec,
     ) -> Result<BiliStream, BiliClientError> {
         let mut headers = self.headers.clone();
         headers.insert("cookie", account.cookies.parse().unwrap());
+        let codecs = match codec {
+            StreamCodec::Avc => "0",
+            StreamCodec::Hevc => "0,1",
+        };
         let res: GeneralResponse = self
             .client
             .get(format!(
-                "https://api.live.bilibili.com/xlive/web-room/v2/index/getRoomPlayInfo?room_id={}&protocol=1&format=0,1,2&codec=0&qn=10000&platform=h5",
-                room_id
+                "https://api.live.bilibili.com/xlive/web-room/v2/index/getRoomPlayInfo?room_id={}&protocol=1&format=0,1,2&codec={}&qn={}&platform=h5",
+                room_id, codecs, qn
             ))
             .headers(headers)
             .send().await?
@@ -339,7 +464,7 @@ impl BiliClient {
                 if let Some(stream) = data.playurl_info.playurl.stream.first() {
                     // Get fmp4 format
                     if let Some(f) = stream.format.iter().find(|f| f.format_name == "fmp4") {
-                        self.get_stream(f).await
+                        self.get_stream(f, codec).await
                     } else {
                         log::error!("No fmp4 stream found: {:#?}", data);
                         Err(BiliClientError::InvalidResponse)
@@ -358,18 +483,34 @@ impl BiliClient {
         }
     }
 
-    async fn get_stream(&self, format: &Format) -> Result<BiliStream, BiliClientError> {
-        if let Some(codec) = format.codec.first() {
-            if let Some(url_info) = codec.url_info.first() {
-                Ok(BiliStream::new(
-                    StreamType::FMP4,
-                    &codec.base_url,
-                    &url_info.host,
-                    &url_info.extra,
-                ))
-            } else {
-                Err(BiliClientError::InvalidFormat)
+    async fn get_stream(
+        &self,
+        format: &Format,
+        prefer: StreamCodec,
+    ) -> Result<BiliStream, BiliClientError> {
+        let name = match prefer {
+            StreamCodec::Avc => "avc",
+            StreamCodec::Hevc => "hevc",
+        };
+        let codec = format
+            .codec
+            .iter()
+            .find(|c| c.codec_name == name)
+            .or(format.codec.first());
+        if let Some(codec) = codec {
+            log::info!("Stream codec: {}", codec.codec_name);
+            if codec.url_info.is_empty() {
+                return Err(BiliClientError::InvalidFormat);
             }
+            let hosts = codec
+                .url_info
+                .iter()
+                .map(|u| StreamHost {
+                    host: u.host.clone(),
+                    extra: u.extra.clone(),
+                })
+                .collect();
+            Ok(BiliStream::new(StreamType::FMP4, &codec.base_url, hosts))
         } else {
             Err(BiliClientError::InvalidFormat)
         }
@@ -382,25 +523,79 @@ impl BiliClient {
             .headers(self.headers.clone())
             .send()
             .await?
+            .error_for_status()?
             .text()
             .await?)
     }
 
-    pub async fn download_ts(&self, url: &str, file_path: &str) -> Result<u64, BiliClientError> {
-        let res = self
+    /// Every chunk waits for all `limits`, which are limiters with their rate in bytes/s.
+    /// Segment is written along with those of other recordings, see `writer`.
+    pub async fn download_ts(
+        &self,
+        url: &str,
+        file_path: &str,
+        limits: &[(&RateLimiter, u64)],
+        writes: WritePolicy,
+    ) -> Result<u64, BiliClientError> {
+        let mut res = self
             .client
             .get(url)
             .headers(self.headers.clone())
             .send()
-            .await?;
-        let mut file = std::fs::File::create(file_path)?;
-        let bytes = res.bytes().await?;
+            .await?
+            .error_for_status()?;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            for (limiter, rate) in limits {
+                limiter.acquire(chunk.len() as u64, *rate).await;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
         let size = bytes.len() as u64;
-        let mut content = std::io::Cursor::new(bytes);
-        std::io::copy(&mut content, &mut file)?;
+        writer::write(file_path, bytes, writes).await?;
         Ok(size)
     }
 
+    /// Download a sample segment from every candidate host of stream.
+    /// Returns download speed in bytes/s for each host, 0 means the host failed.
+    pub async fn cdn_speedtest(&self, stream: &BiliStream) -> Vec<(String, u64)> {
+        let mut results = Vec::new();
+        for host in stream.hosts.iter() {
+            let speed = match self.sample_speed(&stream.with_host(host)).await {
+                Ok(speed) => speed,
+                Err(e) => {
+                    log::warn!("Speedtest on {} failed: {}", host.host, e);
+                    0
+                }
+            };
+            log::info!("Speedtest on {}: {} B/s", host.host, speed);
+            results.push((host.host.clone(), speed));
+        }
+        results
+    }
+
+    async fn sample_speed(&self, stream: &BiliStream) -> Result<u64, BiliClientError> {
+        let index_content = self.get_index_content(&stream.index()).await?;
+        let playlist = m3u8_rs::parse_media_playlist_res(index_content.as_bytes())
+            .map_err(|_| BiliClientError::InvalidResponse)?;
+        let segment = playlist
+            .segments
+            .last()
+            .ok_or(BiliClientError::InvalidResponse)?;
+        let begin = Instant::now();
+        let bytes = self
+            .client
+            .get(stream.ts_url(&segment.uri))
+            .headers(self.headers.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let elapsed = begin.elapsed().as_secs_f64().max(0.001);
+        Ok((bytes.len() as f64 / elapsed) as u64)
+    }
+
     // Method from js code
     pub async fn get_sign(&self, mut parameters: Value) -> Result<String, BiliClientError> {
         let table = vec![
@@ -683,6 +878,85 @@ impl BiliClient {
         }
     }
 
+    /// Archive info and parts of a submitted video, as the `data` of archive view api
+    pub async fn get_archive(
+        &self,
+        account: &AccountRow,
+        bvid: &str,
+    ) -> Result<Value, BiliClientError> {
+        let mut headers = self.headers.clone();
+        headers.insert("cookie", account.cookies.parse().unwrap());
+        let res: Value = self
+            .client
+            .get(format!(
+                "https://member.bilibili.com/x/vupre/web/archive/view?bvid={}",
+                bvid
+            ))
+            .headers(headers)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if res["code"].as_i64() != Some(0) {
+            log::error!("Get archive {} failed: {}", bvid, res["message"]);
+            return Err(BiliClientError::InvalidCode);
+        }
+        Ok(res["data"].clone())
+    }
+
+    /// Append a part to an archive got from `get_archive`, existing parts and infos are kept
+    pub async fn append_video(
+        &self,
+        account: &AccountRow,
+        archive: &Value,
+        video: &profile::Video,
+    ) -> Result<VideoSubmitData, BiliClientError> {
+        let mut headers = self.headers.clone();
+        headers.insert("cookie", account.cookies.parse().unwrap());
+        let mut body = archive["archive"].clone();
+        let aid = body["aid"].as_u64().ok_or(BiliClientError::InvalidValue)?;
+        let bvid = body["bvid"]
+            .as_str()
+            .ok_or(BiliClientError::InvalidValue)?
+            .to_string();
+        let mut videos: Vec<Value> = archive["videos"]
+            .as_array()
+            .ok_or(BiliClientError::InvalidValue)?
+            .iter()
+            .map(|v| {
+                json!({
+                    "title": v["title"],
+                    "filename": v["filename"],
+                    "desc": v["desc"],
+                    "cid": v["cid"],
+                })
+            })
+            .collect();
+        videos.push(serde_json::to_value(video).unwrap());
+        body["videos"] = Value::Array(videos);
+        body["csrf"] = Value::String(account.csrf.clone());
+        let url = format!(
+            "https://member.bilibili.com/x/vu/web/edit?t={}&csrf={}",
+            chrono::Local::now().timestamp(),
+            account.csrf
+        );
+        let res: Value = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .header("Content-Type", "application/json; charset=UTF-8")
+            .body(body.to_string())
+            .send()
+            .await?
+            .json()
+            .await?;
+        if res["code"].as_i64() != Some(0) {
+            log::error!("Append video to {} failed: {}", bvid, res["message"]);
+            return Err(BiliClientError::InvalidCode);
+        }
+        Ok(VideoSubmitData { aid, bvid })
+    }
+
     pub async fn upload_cover(
         &self,
         account: &AccountRow,
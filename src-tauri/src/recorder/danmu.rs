@@ -1,4 +1,11 @@
-use serde::Serialize;
+pub mod collapse;
+pub mod export;
+pub mod heatmap;
+pub mod keyword;
+pub mod resync;
+pub mod translate;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use tokio::io::AsyncWriteExt;
 use tokio::{
     fs::{File, OpenOptions},
@@ -6,19 +13,37 @@ use tokio::{
     sync::RwLock,
 };
 
+/// Lines kept in memory while danmu file is not writable, oldest lines are dropped beyond this
+const MAX_PENDING_LINES: usize = 100_000;
+
 #[derive(Clone, Serialize)]
 pub struct DanmuEntry {
     pub ts: u64,
     pub content: String,
 }
 
+/// When buffered danmu lines are synced to disk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DanmuSyncPolicy {
+    /// lines are written on flush, syncing is left to the OS
+    Never,
+    /// fsync after every periodic flush
+    #[default]
+    Flush,
+    /// write and fsync every line as it arrives
+    Always,
+}
+
 pub struct DanmuStorage {
     cache: RwLock<Vec<DanmuEntry>>,
+    pending: RwLock<VecDeque<String>>,
     file: RwLock<File>,
+    sync_policy: DanmuSyncPolicy,
 }
 
 impl DanmuStorage {
-    pub async fn new(file_path: &str) -> Option<DanmuStorage> {
+    pub async fn new(file_path: &str, sync_policy: DanmuSyncPolicy) -> Option<DanmuStorage> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -48,21 +73,53 @@ impl DanmuStorage {
             .expect("create danmu.txt failed");
         Some(DanmuStorage {
             cache: RwLock::new(preload_cache),
+            pending: RwLock::new(VecDeque::new()),
             file: RwLock::new(file),
+            sync_policy,
         })
     }
 
+    /// Lines are buffered in memory and written into file by `flush`
     pub async fn add_line(&self, ts: u64, content: &str) {
         self.cache.write().await.push(DanmuEntry {
             ts,
             content: content.to_string(),
         });
-        let _ = self
-            .file
-            .write()
-            .await
-            .write(format!("{}:{}\n", ts, content).as_bytes())
-            .await;
+        {
+            let mut pending = self.pending.write().await;
+            if pending.len() >= MAX_PENDING_LINES {
+                log::warn!("Danmu buffer is full, dropping oldest line");
+                pending.pop_front();
+            }
+            pending.push_back(format!("{}:{}\n", ts, content));
+        }
+        if self.sync_policy == DanmuSyncPolicy::Always {
+            if let Err(e) = self.flush().await {
+                log::warn!("Write danmu failed, line is kept in buffer: {}", e);
+            }
+        }
+    }
+
+    /// Write all buffered lines into file.
+    /// If writing failed, lines stay in buffer and will be retried on next flush.
+    pub async fn flush(&self) -> std::io::Result<()> {
+        let mut pending = self.pending.write().await;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let content: String = pending.iter().map(|l| l.as_str()).collect();
+        let mut file = self.file.write().await;
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+        pending.clear();
+        if self.sync_policy != DanmuSyncPolicy::Never {
+            file.sync_data().await?;
+        }
+        Ok(())
+    }
+
+    pub async fn pending_lines(&self) -> usize {
+        self.pending.read().await.len()
     }
 
     pub async fn get_entries(&self) -> Vec<DanmuEntry> {
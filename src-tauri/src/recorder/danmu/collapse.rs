@@ -0,0 +1,34 @@
+use super::DanmuEntry;
+use std::collections::HashMap;
+
+/// Collapse repeated danmu, a message identical to one shown less than `window_secs` ago
+/// is dropped and counted into the shown one, which gets a `×N` suffix.
+/// Only meant for exports, the stored danmu is never changed.
+pub fn collapse(entries: &[DanmuEntry], window_secs: u64) -> Vec<DanmuEntry> {
+    if window_secs == 0 {
+        return entries.to_vec();
+    }
+    let window = window_secs * 1000;
+    let mut result: Vec<DanmuEntry> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    // normalized content -> index of the shown message in result
+    let mut shown: HashMap<String, usize> = HashMap::new();
+    for e in entries {
+        let key = e.content.trim().to_lowercase();
+        if let Some(&i) = shown.get(&key) {
+            if e.ts.saturating_sub(result[i].ts) < window {
+                counts[i] += 1;
+                continue;
+            }
+        }
+        shown.insert(key, result.len());
+        result.push(e.clone());
+        counts.push(1);
+    }
+    for (e, count) in result.iter_mut().zip(counts) {
+        if count > 1 {
+            e.content = format!("{} ×{}", e.content, count);
+        }
+    }
+    result
+}
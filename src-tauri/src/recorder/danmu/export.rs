@@ -0,0 +1,253 @@
+use super::DanmuEntry;
+use chrono::{Local, TimeZone, Utc};
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DanmuExportFormat {
+    /// BililiveRecorder (录播姬) xml, also accepted by DanmakuFactory
+    Xml,
+    Csv,
+    Jsonl,
+}
+
+/// Columns of csv and jsonl export
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DanmuField {
+    /// wall clock time in ms
+    Ts,
+    /// seconds relative to playback start
+    Offset,
+    User,
+    Content,
+    Color,
+}
+
+fn default_fields() -> Vec<DanmuField> {
+    vec![DanmuField::Ts, DanmuField::Offset, DanmuField::Content]
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ExportDanmuOptions {
+    pub format: DanmuExportFormat,
+    /// export the translated track of this language instead of the original one
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// columns of csv and jsonl, ignored by xml
+    #[serde(default = "default_fields")]
+    pub fields: Vec<DanmuField>,
+    /// seconds in which repeated danmu are collapsed into one, 0 disables it,
+    /// config.danmu_collapse_window is used if not set
+    #[serde(default)]
+    pub collapse_window: Option<u64>,
+}
+
+/// Infos of live written into export headers
+pub struct LiveMeta<'a> {
+    pub room_id: u64,
+    pub user_name: &'a str,
+    pub title: &'a str,
+}
+
+/// A chapter starts at offset (in seconds, relative to playback start)
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Chapter {
+    pub offset: f64,
+    pub title: String,
+}
+
+/// Format seconds as WebVTT timestamp: HH:MM:SS.mmm
+fn vtt_time(secs: f64) -> String {
+    let ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            // control characters are not allowed in xml 1.0
+            c if c.is_control() && c != '\t' && c != '\n' && c != '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn export(
+    entries: &[DanmuEntry],
+    start: u64,
+    meta: &LiveMeta,
+    options: &ExportDanmuOptions,
+) -> String {
+    match options.format {
+        DanmuExportFormat::Xml => xml(entries, start, meta),
+        DanmuExportFormat::Csv => csv(entries, start, &options.fields),
+        DanmuExportFormat::Jsonl => jsonl(entries, start, &options.fields),
+    }
+}
+
+fn field_name(field: DanmuField) -> &'static str {
+    match field {
+        DanmuField::Ts => "ts",
+        DanmuField::Offset => "offset",
+        DanmuField::User => "user",
+        DanmuField::Content => "content",
+        DanmuField::Color => "color",
+    }
+}
+
+/// User and color are not recorded yet, they are exported as empty user and white
+fn field_value(field: DanmuField, entry: &DanmuEntry, start: u64) -> serde_json::Value {
+    match field {
+        DanmuField::Ts => json!(entry.ts),
+        DanmuField::Offset => json!((entry.ts - start) as f64 / 1000.0),
+        DanmuField::User => json!(""),
+        DanmuField::Content => json!(entry.content),
+        DanmuField::Color => json!(16777215),
+    }
+}
+
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Csv with a header line, danmu before `start` is skipped
+pub fn csv(entries: &[DanmuEntry], start: u64, fields: &[DanmuField]) -> String {
+    let header: Vec<&str> = fields.iter().map(|f| field_name(*f)).collect();
+    let mut content = header.join(",") + "\n";
+    for e in entries {
+        if e.ts < start {
+            continue;
+        }
+        let row: Vec<String> = fields
+            .iter()
+            .map(|f| match field_value(*f, e, start) {
+                serde_json::Value::String(s) => csv_escape(&s),
+                v => v.to_string(),
+            })
+            .collect();
+        content += &row.join(",");
+        content.push('\n');
+    }
+    content
+}
+
+/// One json object per danmu, danmu before `start` is skipped
+pub fn jsonl(entries: &[DanmuEntry], start: u64, fields: &[DanmuField]) -> String {
+    let mut content = String::new();
+    for e in entries {
+        if e.ts < start {
+            continue;
+        }
+        let line: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .map(|f| (field_name(*f).to_string(), field_value(*f, e, start)))
+            .collect();
+        content += &serde_json::Value::Object(line).to_string();
+        content.push('\n');
+    }
+    content
+}
+
+/// Danmu in BililiveRecorder xml format, `start` is the wall clock time in ms of playback start.
+/// All danmu is exported as white scrolling comments as mode and color are not recorded.
+pub fn xml(entries: &[DanmuEntry], start: u64, meta: &LiveMeta) -> String {
+    let start_time = Utc
+        .timestamp_opt((start / 1000) as i64, 0)
+        .unwrap()
+        .with_timezone(&Local)
+        .to_rfc3339();
+    let mut content = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<i>\n".to_string();
+    content += "<chatserver>chat.bilibili.com</chatserver>\n<chatid>0</chatid>\n<mission>0</mission>\n<maxlimit>1000</maxlimit>\n<state>0</state>\n<real_name>0</real_name>\n<source>k-v</source>\n";
+    content += &format!(
+        "<BililiveRecorderRecordInfo roomid=\"{}\" shortid=\"0\" name=\"{}\" title=\"{}\" areanameparent=\"\" areanamechild=\"\" start_time=\"{}\" />\n",
+        meta.room_id,
+        xml_escape(meta.user_name),
+        xml_escape(meta.title),
+        start_time
+    );
+    for e in entries {
+        if e.ts < start {
+            continue;
+        }
+        // time,mode,size,color,timestamp,pool,uid,rowid
+        content += &format!(
+            "<d p=\"{:.3},1,25,16777215,{},0,0,0\" user=\"\">{}</d>\n",
+            (e.ts - start) as f64 / 1000.0,
+            e.ts,
+            xml_escape(&e.content)
+        );
+    }
+    content += "</i>\n";
+    content
+}
+
+/// Chat replay in the json-lines format YouTube live chat replays are saved in (as yt-dlp does),
+/// `start` is the wall clock time in ms of playback start, danmu before it is skipped.
+pub fn chat_replay(entries: &[DanmuEntry], start: u64) -> String {
+    let mut content = String::new();
+    for (i, e) in entries.iter().enumerate() {
+        if e.ts < start {
+            continue;
+        }
+        let line = json!({
+            "replayChatItemAction": {
+                "actions": [{
+                    "addChatItemAction": {
+                        "item": {
+                            "liveChatTextMessageRenderer": {
+                                "message": { "runs": [{ "text": e.content }] },
+                                "authorName": { "simpleText": "" },
+                                "timestampUsec": (e.ts * 1000).to_string(),
+                                "id": format!("{}-{}", e.ts, i),
+                            }
+                        },
+                        "clientId": "",
+                    }
+                }],
+                "videoOffsetTimeMsec": (e.ts - start).to_string(),
+            }
+        });
+        content += &line.to_string();
+        content.push('\n');
+    }
+    content
+}
+
+/// WebVTT chapters, each chapter ends where the next begins and the last one ends at `length`
+pub fn chapters_vtt(chapters: &[Chapter], length: f64) -> String {
+    let mut chapters = chapters.to_vec();
+    chapters.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    let mut content = "WEBVTT\n".to_string();
+    for (i, c) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map(|n| n.offset).unwrap_or(length);
+        if end <= c.offset {
+            continue;
+        }
+        content += &format!(
+            "\n{}\n{} --> {}\n{}\n",
+            i + 1,
+            vtt_time(c.offset),
+            vtt_time(end),
+            c.title
+        );
+    }
+    content
+}
@@ -0,0 +1,90 @@
+use super::DanmuEntry;
+use serde::Serialize;
+
+/// Danmu count in [offset, offset + bucket_secs), offset is relative to playback start
+#[derive(Clone, Debug, Serialize)]
+pub struct HeatmapBucket {
+    pub offset: f64,
+    pub count: u64,
+}
+
+/// A suggested clip range, relative to playback start
+#[derive(Clone, Debug, Serialize)]
+pub struct Highlight {
+    pub start: f64,
+    pub end: f64,
+    pub count: u64,
+}
+
+/// `start` is the wall clock time in ms of playback start, danmu before it is skipped
+pub fn heatmap(entries: &[DanmuEntry], start: u64, bucket_secs: u64) -> Vec<HeatmapBucket> {
+    let bucket_ms = bucket_secs.max(1) * 1000;
+    let mut counts: Vec<u64> = Vec::new();
+    for e in entries {
+        if e.ts < start {
+            continue;
+        }
+        let index = ((e.ts - start) / bucket_ms) as usize;
+        if index >= counts.len() {
+            counts.resize(index + 1, 0);
+        }
+        counts[index] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| HeatmapBucket {
+            offset: (i as u64 * bucket_ms) as f64 / 1000.0,
+            count,
+        })
+        .collect()
+}
+
+/// Top `top_n` danmu peaks, hottest first.
+/// A peak is extended over neighbour buckets above mean + stddev, and starts one bucket
+/// earlier as danmu usually lags behind what happened.
+pub fn highlights(buckets: &[HeatmapBucket], bucket_secs: u64, top_n: usize) -> Vec<Highlight> {
+    if buckets.is_empty() || top_n == 0 {
+        return Vec::new();
+    }
+    let bucket_secs = bucket_secs.max(1) as f64;
+    let n = buckets.len() as f64;
+    let mean = buckets.iter().map(|b| b.count as f64).sum::<f64>() / n;
+    let variance = buckets
+        .iter()
+        .map(|b| (b.count as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    let threshold = mean + variance.sqrt();
+
+    let mut order: Vec<usize> = (0..buckets.len()).collect();
+    order.sort_by(|a, b| buckets[*b].count.cmp(&buckets[*a].count));
+    let mut taken = vec![false; buckets.len()];
+    let mut ret = Vec::new();
+    for peak in order {
+        if ret.len() >= top_n || (buckets[peak].count as f64) <= threshold {
+            break;
+        }
+        if taken[peak] {
+            continue;
+        }
+        let mut left = peak;
+        while left > 0 && !taken[left - 1] && buckets[left - 1].count as f64 > threshold {
+            left -= 1;
+        }
+        let mut right = peak;
+        while right + 1 < buckets.len()
+            && !taken[right + 1]
+            && buckets[right + 1].count as f64 > threshold
+        {
+            right += 1;
+        }
+        taken[left..=right].iter_mut().for_each(|t| *t = true);
+        ret.push(Highlight {
+            start: (buckets[left].offset - bucket_secs).max(0.0),
+            end: buckets[right].offset + bucket_secs,
+            count: buckets[left..=right].iter().map(|b| b.count).sum(),
+        });
+    }
+    ret
+}
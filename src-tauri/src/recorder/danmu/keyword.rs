@@ -0,0 +1,48 @@
+use crate::database::keyword::KeywordRuleRow;
+use regex::Regex;
+
+/// A rule is not triggered again in this many seconds, so danmu floods won't spam notifications
+const KEYWORD_HIT_COOLDOWN: i64 = 60;
+
+pub struct KeywordMatcher {
+    pub rule: KeywordRuleRow,
+    regex: Option<Regex>,
+    last_hit: i64,
+}
+
+impl KeywordMatcher {
+    /// None if rule is a regex that does not compile
+    pub fn new(rule: KeywordRuleRow) -> Option<KeywordMatcher> {
+        let regex = if rule.is_regex {
+            match Regex::new(&rule.pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    log::error!("Invalid keyword rule {}: {}", rule.pattern, e);
+                    return None;
+                }
+            }
+        } else {
+            None
+        };
+        Some(KeywordMatcher {
+            rule,
+            regex,
+            last_hit: 0,
+        })
+    }
+
+    /// `now` is in seconds, returns false while rule is cooling down
+    pub fn hit(&mut self, content: &str, now: i64) -> bool {
+        if now - self.last_hit < KEYWORD_HIT_COOLDOWN {
+            return false;
+        }
+        let matched = match &self.regex {
+            Some(re) => re.is_match(content),
+            None => content.contains(&self.rule.pattern),
+        };
+        if matched {
+            self.last_hit = now;
+        }
+        matched
+    }
+}
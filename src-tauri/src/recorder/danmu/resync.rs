@@ -0,0 +1,41 @@
+use super::DanmuEntry;
+
+/// Offsets of adjacent segments further apart than this mean the stream dropped in between
+const GAP_THRESHOLD_MS: u64 = 5000;
+/// Length assumed for segments before a gap and for the last one
+const NOMINAL_SEGMENT_MS: u64 = 1000;
+
+/// Remap danmu onto the timeline of the recorded media, where gaps of the stream are collapsed.
+/// `offsets` are wall clock start times in ms of all segments, sorted.
+/// Result timestamps are `offsets[0]` plus the position in recorded media, so they can be used
+/// with `offsets[0]` as playback start. Danmu sent during a gap lands at the point the gap is cut.
+pub fn resync(entries: &[DanmuEntry], offsets: &[u64]) -> Vec<DanmuEntry> {
+    let Some(&first) = offsets.first() else {
+        return entries.to_vec();
+    };
+    // position in media where each segment starts, and its media length
+    let mut media_starts = Vec::with_capacity(offsets.len());
+    let mut lengths = Vec::with_capacity(offsets.len());
+    let mut position = 0;
+    for (i, offset) in offsets.iter().enumerate() {
+        let length = match offsets.get(i + 1) {
+            Some(next) if next - offset <= GAP_THRESHOLD_MS => next - offset,
+            _ => NOMINAL_SEGMENT_MS,
+        };
+        media_starts.push(position);
+        lengths.push(length);
+        position += length;
+    }
+    entries
+        .iter()
+        .filter(|e| e.ts >= first)
+        .map(|e| {
+            let i = offsets.partition_point(|o| *o <= e.ts).saturating_sub(1);
+            let within = e.ts.saturating_sub(offsets[i]).min(lengths[i]);
+            DanmuEntry {
+                ts: first + media_starts[i] + within,
+                content: e.content.clone(),
+            }
+        })
+        .collect()
+}
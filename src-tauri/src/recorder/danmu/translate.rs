@@ -0,0 +1,150 @@
+use super::DanmuEntry;
+use custom_error::custom_error;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Danmu lines are sent to translation api in batches of this size
+const BATCH_SIZE: usize = 50;
+
+custom_error! {pub TranslateError
+    NotConfigured = "Translation is not configured",
+    ClientError {err: reqwest::Error} = "Translation request failed: {err}",
+    InvalidResponse = "Invalid translation response",
+}
+
+impl From<reqwest::Error> for TranslateError {
+    fn from(e: reqwest::Error) -> Self {
+        TranslateError::ClientError { err: e }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Translator {
+    /// Any api compatible with OpenAI chat completions, endpoint is like https://api.openai.com/v1
+    Openai {
+        endpoint: String,
+        api_key: String,
+        model: String,
+    },
+    /// Replace words by a local dictionary, no network needed
+    Dictionary { words: HashMap<String, String> },
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TranslationConfig {
+    /// language code of translated track, also used in its file name
+    pub target_lang: String,
+    pub translator: Translator,
+}
+
+fn translate_by_dictionary(words: &HashMap<String, String>, content: &str) -> String {
+    // longer words first, so phrases win over the words inside them
+    let mut keys: Vec<&String> = words.keys().collect();
+    keys.sort_by_key(|k| std::cmp::Reverse(k.chars().count()));
+    let mut translated = content.to_string();
+    for key in keys {
+        if !key.is_empty() {
+            translated = translated.replace(key.as_str(), &words[key]);
+        }
+    }
+    translated
+}
+
+async fn translate_batch(
+    client: &reqwest::Client,
+    endpoint: &str,
+    api_key: &str,
+    model: &str,
+    target_lang: &str,
+    lines: &[&str],
+) -> Result<Vec<String>, TranslateError> {
+    let prompt = format!(
+        "Translate each line of the following live chat messages into {}. \
+        Output exactly one line per input line in the same order, without numbering or explanation.",
+        target_lang
+    );
+    let body = json!({
+        "model": model,
+        "messages": [
+            { "role": "system", "content": prompt },
+            { "role": "user", "content": lines.join("\n") },
+        ],
+    });
+    let resp: serde_json::Value = client
+        .post(format!(
+            "{}/chat/completions",
+            endpoint.trim_end_matches('/')
+        ))
+        .bearer_auth(api_key)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let content = resp["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or(TranslateError::InvalidResponse)?;
+    Ok(content.lines().map(|l| l.trim().to_string()).collect())
+}
+
+/// Produce a translated copy of entries with the same timestamps.
+/// Lines that translation api fails to give back are kept as original.
+pub async fn translate(
+    config: &TranslationConfig,
+    entries: &[DanmuEntry],
+    proxy: Option<&str>,
+) -> Result<Vec<DanmuEntry>, TranslateError> {
+    match &config.translator {
+        Translator::Dictionary { words } => Ok(entries
+            .iter()
+            .map(|e| DanmuEntry {
+                ts: e.ts,
+                content: translate_by_dictionary(words, &e.content),
+            })
+            .collect()),
+        Translator::Openai {
+            endpoint,
+            api_key,
+            model,
+        } => {
+            let mut builder = reqwest::Client::builder();
+            if let Some(proxy) = proxy {
+                builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+            }
+            let client = builder.build()?;
+            let mut translated = Vec::with_capacity(entries.len());
+            for batch in entries.chunks(BATCH_SIZE) {
+                // newlines would break line matching between input and output
+                let lines: Vec<String> =
+                    batch.iter().map(|e| e.content.replace('\n', " ")).collect();
+                let lines: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+                let results = translate_batch(
+                    &client,
+                    endpoint,
+                    api_key,
+                    model,
+                    &config.target_lang,
+                    &lines,
+                )
+                .await?;
+                if results.len() != batch.len() {
+                    log::warn!(
+                        "Translation returned {} lines for {} danmu",
+                        results.len(),
+                        batch.len()
+                    );
+                }
+                for (i, e) in batch.iter().enumerate() {
+                    let content = match results.get(i) {
+                        Some(r) if !r.is_empty() && results.len() == batch.len() => r.clone(),
+                        _ => e.content.clone(),
+                    };
+                    translated.push(DanmuEntry { ts: e.ts, content });
+                }
+            }
+            Ok(translated)
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Shared by downloads of all rooms for the global limit
+pub static GLOBAL: RateLimiter = RateLimiter::new();
+
+/// Limits throughput of downloads sharing it, each chunk waits for its turn at the rate.
+/// Rate is given on every call, so a changed limit applies from the next chunk.
+pub struct RateLimiter {
+    /// when bandwidth is free again
+    next: Mutex<Option<Instant>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        RateLimiter {
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Wait until `bytes` fits in `rate` bytes/s, 0 means unlimited
+    pub async fn acquire(&self, bytes: u64, rate: u64) {
+        if rate == 0 {
+            return;
+        }
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = next.map_or(now, |n| n.max(now));
+            *next = Some(start + Duration::from_secs_f64(bytes as f64 / rate as f64));
+            start - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire_waits_for_rate() {
+        let limiter = RateLimiter::new();
+        tauri::async_runtime::block_on(async {
+            let begin = Instant::now();
+            limiter.acquire(1000, 0).await;
+            assert!(begin.elapsed() < Duration::from_millis(50));
+            // first chunk goes right away, the second waits for the first one at 10 KB/s
+            limiter.acquire(1000, 10_000).await;
+            limiter.acquire(1000, 10_000).await;
+            assert!(begin.elapsed() >= Duration::from_millis(100));
+        });
+    }
+}
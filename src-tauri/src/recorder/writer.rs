@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How segment writes of all recordings are coalesced. Writes wait until pending ones add up
+/// to `flush_bytes` or the first of them waited `flush_interval_ms`, then they are written one
+/// after another grouped by file, instead of recordings writing into disk at the same time.
+/// Interval 0 writes every segment right away.
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct WritePolicy {
+    pub flush_bytes: u64,
+    /// segments are served once written, so this delays them in playlists as well
+    pub flush_interval_ms: u64,
+}
+
+impl Default for WritePolicy {
+    fn default() -> Self {
+        WritePolicy {
+            flush_bytes: 8 * 1024 * 1024,
+            flush_interval_ms: 200,
+        }
+    }
+}
+
+struct Write {
+    path: String,
+    content: Vec<u8>,
+    policy: WritePolicy,
+    done: oneshot::Sender<std::io::Result<()>>,
+}
+
+static WRITER: OnceLock<mpsc::UnboundedSender<Write>> = OnceLock::new();
+
+/// Write `content` into `path` in the next flush, returns once it is on disk. Policy is given
+/// on every call, a changed one applies from the next batch.
+pub async fn write(path: &str, content: Vec<u8>, policy: WritePolicy) -> std::io::Result<()> {
+    if policy.flush_interval_ms == 0 {
+        return tokio::fs::write(path, &content).await;
+    }
+    let (done, written) = oneshot::channel();
+    let sender = WRITER.get_or_init(|| {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(run(receiver));
+        sender
+    });
+    let write = Write {
+        path: path.to_string(),
+        content,
+        policy,
+        done,
+    };
+    if let Err(mpsc::error::SendError(write)) = sender.send(write) {
+        return tokio::fs::write(path, &write.content).await;
+    }
+    written
+        .await
+        .unwrap_or_else(|_| Err(std::io::Error::other("segment writer stopped")))
+}
+
+async fn run(mut receiver: mpsc::UnboundedReceiver<Write>) {
+    while let Some(first) = receiver.recv().await {
+        let policy = first.policy;
+        let deadline =
+            tokio::time::Instant::now() + Duration::from_millis(policy.flush_interval_ms);
+        let mut size = first.content.len() as u64;
+        let mut batch = vec![first];
+        while size < policy.flush_bytes {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(write)) => {
+                    size += write.content.len() as u64;
+                    batch.push(write);
+                }
+                _ => break,
+            }
+        }
+        // segments of a recording are next to each other, so disk doesn't seek between them
+        batch.sort_by(|a, b| a.path.cmp(&b.path));
+        for write in batch {
+            let result = tokio::fs::write(&write.path, &write.content).await;
+            let _ = write.done.send(result);
+        }
+    }
+}
@@ -1,8 +1,14 @@
 use crate::database::{account::AccountRow, record::RecordRow, Database};
+use crate::ffmpeg::{ArchiveProfile, AudioFormat};
 use crate::recorder::bilibili::UserInfo;
+use crate::recorder::danmu::export::ExportDanmuOptions;
+use crate::recorder::danmu::heatmap::{HeatmapBucket, Highlight};
 use crate::recorder::danmu::DanmuEntry;
-use crate::recorder::RecorderError;
-use crate::recorder::{bilibili::RoomInfo, BiliRecorder};
+use crate::recorder::{
+    bilibili::RoomInfo, BiliRecorder, CoverSource, PipelineMetrics, RepairReport, StreamStatus,
+};
+use crate::recorder::{RecordWindow, RecorderError, RecordingSlots, Snapshot};
+use crate::torrent;
 use crate::Config;
 use custom_error::custom_error;
 use dashmap::DashMap;
@@ -11,11 +17,16 @@ use hyper::{
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server,
 };
+use std::io::SeekFrom;
 use std::net::SocketAddr;
 use std::{convert::Infallible, sync::Arc};
 use tauri::AppHandle;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio::{net::TcpListener, sync::RwLock};
 
+/// Contact sheet served by hls server is a grid of this many thumbnails on each side
+const CONTACT_SHEET_GRID: u32 = 4;
+
 #[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 pub struct RecorderList {
     pub count: usize,
@@ -30,6 +41,18 @@ pub struct RecorderInfo {
     pub total_length: f64,
     pub current_ts: u64,
     pub live_status: bool,
+    /// current live is only kept in rolling buffer
+    pub buffering: bool,
+    /// None if room is not streaming
+    pub stream: Option<StreamStatus>,
+    pub pipeline: PipelineMetrics,
+    pub quality: u64,
+    pub schedule: Vec<RecordWindow>,
+    pub priority: i64,
+    /// live but waiting for a free recording slot
+    pub queued: bool,
+    /// segment downloads are paused by user
+    pub paused: bool,
 }
 
 pub struct RecorderManager {
@@ -37,6 +60,7 @@ pub struct RecorderManager {
     config: Arc<RwLock<Config>>,
     recorders: Arc<DashMap<u64, BiliRecorder>>,
     hls_server_addr: Arc<RwLock<Option<SocketAddr>>>,
+    slots: Arc<RecordingSlots>,
 }
 
 custom_error! {pub RecorderManagerError
@@ -45,6 +69,9 @@ custom_error! {pub RecorderManagerError
     RecorderError { err: RecorderError } = "Recorder error",
     IOError {err: std::io::Error } = "IO error",
     HLSError { err: hyper::Error } = "HLS server error",
+    UnsupportedPlatform { platform: String } = "Platform {platform} is not supported",
+    NoLiveRoom = "No room is streaming",
+    TorrentError { err: String } = "Create torrent failed: {err}",
 }
 
 impl From<hyper::Error> for RecorderManagerError {
@@ -78,6 +105,7 @@ impl RecorderManager {
             config,
             recorders: Arc::new(DashMap::new()),
             hls_server_addr: Arc::new(RwLock::new(None)),
+            slots: Arc::new(RecordingSlots::default()),
         }
     }
 
@@ -109,6 +137,7 @@ impl RecorderManager {
             room_id,
             account,
             self.config.clone(),
+            self.slots.clone(),
         )
         .await?;
         self.recorders.insert(room_id, recorder);
@@ -131,6 +160,13 @@ impl RecorderManager {
         Ok(())
     }
 
+    /// stop all recorders, buffered data is flushed before return
+    pub async fn stop_all(&self) {
+        for recorder in self.recorders.iter() {
+            recorder.value().stop().await;
+        }
+    }
+
     pub async fn clip(
         &self,
         output_path: &str,
@@ -152,6 +188,7 @@ impl RecorderManager {
         ts: u64,
         start: f64,
         end: f64,
+        codec: Option<String>,
     ) -> Result<String, RecorderManagerError> {
         let recorder = self.recorders.get(&room_id);
         if recorder.is_none() {
@@ -160,10 +197,178 @@ impl RecorderManager {
         let recorder = recorder.unwrap();
         Ok(recorder
             .value()
-            .clip_range(ts, start, end, output_path)
+            .clip_range(ts, start, end, output_path, codec)
             .await?)
     }
 
+    pub async fn generate_contact_sheet(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        cols: u32,
+        rows: u32,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.generate_contact_sheet(live_id, cols, rows).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn detect_scenes(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<f64>, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.detect_scenes(live_id).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn export_audio(
+        &self,
+        output_path: &str,
+        room_id: u64,
+        live_id: u64,
+        format: AudioFormat,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.export_audio(live_id, format, output_path).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    /// Recording quality of a room, takes effect from next live
+    pub async fn set_quality(
+        &self,
+        db: &Database,
+        room_id: u64,
+        quality: u64,
+    ) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            db.set_recorder_quality(room_id, quality)
+                .await
+                .map_err(RecorderError::from)?;
+            *recorder.quality.write().await = quality;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    /// Priority of a room for free recording slots, higher goes first
+    pub async fn set_priority(
+        &self,
+        db: &Database,
+        room_id: u64,
+        priority: i64,
+    ) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            db.set_recorder_priority(room_id, priority)
+                .await
+                .map_err(RecorderError::from)?;
+            *recorder.priority.write().await = priority;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    /// Windows a room is recorded in, empty to always record
+    pub async fn set_schedule(
+        &self,
+        db: &Database,
+        room_id: u64,
+        schedule: Vec<RecordWindow>,
+    ) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            db.set_recorder_schedule(room_id, &schedule)
+                .await
+                .map_err(RecorderError::from)?;
+            *recorder.schedule.write().await = schedule;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn remove_archive_package(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            recorder.remove_package(live_id).await;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn export_archive(
+        &self,
+        output_path: &str,
+        room_id: u64,
+        live_id: u64,
+        nfo: bool,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.export_archive(live_id, output_path, nfo).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    /// Package an archive and write a torrent of it into output dir, with web seed under
+    /// `web_seed_base`, a public url hls server is reachable at. Package is kept in work dir
+    /// for the web seed until archive changes. Returns path of the torrent.
+    pub async fn create_archive_torrent(
+        &self,
+        output_path: &str,
+        room_id: u64,
+        live_id: u64,
+        web_seed_base: &str,
+    ) -> Result<String, RecorderManagerError> {
+        let recorder = match self.recorders.get(&room_id) {
+            Some(recorder) => recorder.clone(),
+            None => return Err(RecorderManagerError::NotFound { room_id }),
+        };
+        let name = BiliRecorder::seed_package(live_id);
+        let package = recorder.package_archive(live_id, &name).await?;
+        let base = web_seed_base.trim_end_matches('/');
+        let web_seed = format!("{}/{}/{}/{}", base, room_id, live_id, name);
+        let torrent = tokio::task::spawn_blocking(move || {
+            torrent::create_torrent(&package, &name, &web_seed)
+        })
+        .await
+        .map_err(|e| RecorderManagerError::TorrentError { err: e.to_string() })?
+        .map_err(|err| RecorderManagerError::TorrentError { err })?;
+        let file = format!("{}/{}_{}.torrent", output_path, room_id, live_id);
+        tokio::fs::write(&file, torrent).await?;
+        Ok(file)
+    }
+
+    /// Speedtest needs a live stream to get candidate hosts, so the first streaming room is used
+    pub async fn cdn_speedtest(
+        &self,
+        platform: &str,
+    ) -> Result<Vec<(String, u64)>, RecorderManagerError> {
+        if platform != "bilibili" {
+            return Err(RecorderManagerError::UnsupportedPlatform {
+                platform: platform.into(),
+            });
+        }
+        for recorder in self.recorders.iter() {
+            if let Some(results) = recorder.value().cdn_speedtest().await {
+                return Ok(results);
+            }
+        }
+        Err(RecorderManagerError::NoLiveRoom)
+    }
+
     pub async fn get_recorder_list(&self) -> RecorderList {
         let mut summary = RecorderList {
             count: self.recorders.len(),
@@ -179,6 +384,14 @@ impl RecorderManager {
                 total_length: *recorder.ts_length.read().await,
                 current_ts: *recorder.timestamp.read().await,
                 live_status: *recorder.live_status.read().await,
+                buffering: *recorder.buffering.read().await,
+                stream: recorder.stream_status().await,
+                pipeline: recorder.pipeline_metrics().await,
+                quality: *recorder.quality.read().await,
+                schedule: recorder.schedule.read().await.clone(),
+                priority: *recorder.priority.read().await,
+                queued: recorder.is_queued(),
+                paused: recorder.is_paused().await,
             };
             summary.recorders.push(room_info);
         }
@@ -197,6 +410,14 @@ impl RecorderManager {
                 total_length: *recorder.ts_length.read().await,
                 current_ts: *recorder.timestamp.read().await,
                 live_status: *recorder.live_status.read().await,
+                buffering: *recorder.buffering.read().await,
+                stream: recorder.stream_status().await,
+                pipeline: recorder.pipeline_metrics().await,
+                quality: *recorder.quality.read().await,
+                schedule: recorder.schedule.read().await.clone(),
+                priority: *recorder.priority.read().await,
+                queued: recorder.is_queued(),
+                paused: recorder.is_paused().await,
             };
             Some(room_info)
         } else {
@@ -224,6 +445,68 @@ impl RecorderManager {
         }
     }
 
+    pub async fn get_snapshots(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<Vec<Snapshot>, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.get_snapshots(live_id).await)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn set_archive_cover(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        source: CoverSource,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.set_archive_cover(live_id, source).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn repair_archive(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<RepairReport, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.repair_archive(live_id).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn compress_archive(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        profile: &ArchiveProfile,
+    ) -> Result<RecordRow, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.compress_archive(live_id, profile).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn merge_archives(
+        &self,
+        room_id: u64,
+        live_ids: Vec<u64>,
+    ) -> Result<RecordRow, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.merge_archives(live_ids).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
     pub async fn delete_archive(&self, room_id: u64, ts: u64) -> Result<(), RecorderManagerError> {
         log::info!("Deleting {}:{}", room_id, ts);
         if let Some(recorder) = self.recorders.get(&room_id) {
@@ -246,6 +529,117 @@ impl RecorderManager {
         }
     }
 
+    pub async fn promote_buffer(&self, room_id: u64) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            recorder.promote_buffer().await;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    /// Rebuild clients of all recorders with proxy from config
+    pub async fn apply_proxy(&self) -> Result<(), RecorderManagerError> {
+        for recorder in self.recorders.iter() {
+            let proxy = self.config.read().await.proxy_for(recorder.room_id);
+            recorder.set_proxy(proxy.as_deref()).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn pause_recording(&self, room_id: u64) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            recorder.pause().await;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn resume_recording(&self, room_id: u64) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            recorder.resume().await;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn reload_keyword_rules(&self, room_id: u64) -> Result<(), RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            recorder.reload_keyword_rules().await;
+            Ok(())
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn get_danmu_heatmap(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        bucket_secs: u64,
+    ) -> Result<Vec<HeatmapBucket>, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.get_danmu_heatmap(live_id, bucket_secs).await)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn get_danmu_highlights(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        bucket_secs: u64,
+        count: usize,
+    ) -> Result<Vec<Highlight>, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder
+                .get_danmu_highlights(live_id, bucket_secs, count)
+                .await)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn export_danmu(
+        &self,
+        room_id: u64,
+        live_id: u64,
+        options: &ExportDanmuOptions,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.export_danmu(live_id, options).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn translate_danmu(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<usize, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.translate_danmu(live_id).await?)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
+    pub async fn export_chat_replay(
+        &self,
+        room_id: u64,
+        live_id: u64,
+    ) -> Result<String, RecorderManagerError> {
+        if let Some(recorder) = self.recorders.get(&room_id) {
+            Ok(recorder.export_chat_replay(live_id).await)
+        } else {
+            Err(RecorderManagerError::NotFound { room_id })
+        }
+    }
+
     async fn start_hls_server(
         &self,
         listener: TcpListener,
@@ -311,6 +705,100 @@ impl RecorderManager {
                                     .body(Body::from(m3u8_content))
                                     .unwrap(),
                             )
+                        } else if path_segs[3] == "monitor.m3u8" {
+                            // low bitrate monitor playlist, its chunks are served as cache files below
+                            let m3u8_file = format!("{}/{}", cache_path, path);
+                            match tokio::fs::read(m3u8_file).await {
+                                Ok(content) => Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(200)
+                                        .header("Content-Type", "application/vnd.apple.mpegurl")
+                                        .header("Access-Control-Allow-Origin", "*")
+                                        .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                                        .body(Body::from(content))
+                                        .unwrap(),
+                                ),
+                                Err(_) => Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(404)
+                                        .body(Body::from("Monitor Stream Not Found"))
+                                        .unwrap(),
+                                ),
+                            }
+                        } else if path_segs[3] == "contact_sheet.jpg" {
+                            // /room_id/{live_id}/contact_sheet.jpg, thumbnails of the live in a default grid
+                            let recorder = recorders.get(&room_id);
+                            if recorder.is_none() {
+                                return Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(404)
+                                        .body(Body::from("Recorder Not Found"))
+                                        .unwrap(),
+                                );
+                            }
+                            let recorder = recorder.unwrap();
+                            let sheet = recorder
+                                .value()
+                                .generate_contact_sheet(
+                                    timestamp,
+                                    CONTACT_SHEET_GRID,
+                                    CONTACT_SHEET_GRID,
+                                )
+                                .await;
+                            match sheet {
+                                Ok(sheet) => match tokio::fs::read(sheet).await {
+                                    Ok(content) => Ok::<_, Infallible>(
+                                        Response::builder()
+                                            .status(200)
+                                            .header("Content-Type", "image/jpeg")
+                                            .header("Access-Control-Allow-Origin", "*")
+                                            .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                                            .body(Body::from(content))
+                                            .unwrap(),
+                                    ),
+                                    Err(e) => Ok::<_, Infallible>(
+                                        Response::builder()
+                                            .status(500)
+                                            .body(Body::from(e.to_string()))
+                                            .unwrap(),
+                                    ),
+                                },
+                                Err(e) => Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(500)
+                                        .body(Body::from(e.to_string()))
+                                        .unwrap(),
+                                ),
+                            }
+                        } else if path_segs[3] == "danmu_statistics.json" {
+                            // /room_id/{live_id}/danmu_statistics.json, danmu count per minute for charts
+                            let recorder = recorders.get(&room_id);
+                            if recorder.is_none() {
+                                return Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(404)
+                                        .body(Body::from("Recorder Not Found"))
+                                        .unwrap(),
+                                );
+                            }
+                            let recorder = recorder.unwrap();
+                            match recorder.value().get_danmu_statistics(timestamp).await {
+                                Ok(stats) => Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(200)
+                                        .header("Content-Type", "application/json")
+                                        .header("Access-Control-Allow-Origin", "*")
+                                        .header("Access-Control-Allow-Methods", "GET, OPTIONS")
+                                        .body(Body::from(serde_json::to_string(&stats).unwrap()))
+                                        .unwrap(),
+                                ),
+                                Err(e) => Ok::<_, Infallible>(
+                                    Response::builder()
+                                        .status(500)
+                                        .body(Body::from(e.to_string()))
+                                        .unwrap(),
+                                ),
+                            }
                         } else {
                             // try to find requested ts file in recorder's cache
                             // cache files are stored in {cache_dir}/{room_id}/{timestamp}/{ts_file}
@@ -324,6 +812,44 @@ impl RecorderManager {
                                         .unwrap(),
                                 );
                             }
+                            let content_type = if path.ends_with(".jpg") {
+                                "image/jpeg"
+                            } else if path.ends_with(".mp4") {
+                                "video/mp4"
+                            } else {
+                                "video/MP2T"
+                            };
+                            // packaged archives are downloaded by torrent web seeds in ranges
+                            let range = req
+                                .headers()
+                                .get("Range")
+                                .and_then(|v| v.to_str().ok())
+                                .map(|v| v.to_string());
+                            if let Some(range) = range {
+                                return Ok::<_, Infallible>(
+                                    match read_range(&ts_file, &range).await {
+                                        Ok(Some((body, start, end, total))) => Response::builder()
+                                            .status(206)
+                                            .header("Content-Type", content_type)
+                                            .header("Content-Length", end - start + 1)
+                                            .header(
+                                                "Content-Range",
+                                                format!("bytes {}-{}/{}", start, end, total),
+                                            )
+                                            .header("Access-Control-Allow-Origin", "*")
+                                            .body(body)
+                                            .unwrap(),
+                                        Ok(None) => Response::builder()
+                                            .status(416)
+                                            .body(Body::from("Range Not Satisfiable"))
+                                            .unwrap(),
+                                        Err(_) => Response::builder()
+                                            .status(404)
+                                            .body(Body::from("TS File Not Found"))
+                                            .unwrap(),
+                                    },
+                                );
+                            }
                             let ts_file_content = tokio::fs::read(ts_file).await;
                             if ts_file_content.is_err() {
                                 return Ok::<_, Infallible>(
@@ -337,7 +863,8 @@ impl RecorderManager {
                             Ok::<_, Infallible>(
                                 Response::builder()
                                     .status(200)
-                                    .header("Content-Type", "video/MP2T")
+                                    .header("Content-Type", content_type)
+                                    .header("Accept-Ranges", "bytes")
                                     .header("Access-Control-Allow-Origin", "*")
                                     .header("Access-Control-Allow-Methods", "GET, OPTIONS")
                                     .body(Body::from(ts_file_content))
@@ -364,3 +891,45 @@ impl RecorderManager {
         *self.hls_server_addr.read().await
     }
 }
+
+/// Bytes read from file at once when streaming a range
+const RANGE_CHUNK: usize = 256 * 1024;
+
+/// Stream the part of file asked by a `bytes=start-end` range header, both ends inclusive.
+/// Returns None if range is invalid for the file, with body, start, end and file size otherwise.
+async fn read_range(file: &str, range: &str) -> std::io::Result<Option<(Body, u64, u64, u64)>> {
+    let mut file = tokio::fs::File::open(file).await?;
+    let total = file.metadata().await?.len();
+    let Some((start, end)) = range
+        .strip_prefix("bytes=")
+        .and_then(|r| r.split(',').next())
+        .and_then(|r| r.trim().split_once('-'))
+    else {
+        return Ok(None);
+    };
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) => (start, end.min(total.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, total.saturating_sub(1)),
+        // suffix range, the last n bytes
+        (Err(_), Ok(n)) if start.is_empty() => (total.saturating_sub(n), total.saturating_sub(1)),
+        _ => return Ok(None),
+    };
+    if start > end || start >= total {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(start)).await?;
+    // packages are gigabytes, so range is sent in chunks instead of read into memory
+    let reader = file.take(end - start + 1);
+    let chunks = futures::stream::unfold(reader, |mut reader| async move {
+        let mut buffer = vec![0u8; RANGE_CHUNK];
+        match reader.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buffer.truncate(n);
+                Some((Ok::<_, std::io::Error>(buffer), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    });
+    Ok(Some((Body::wrap_stream(chunks), start, end, total)))
+}
@@ -0,0 +1,82 @@
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::Read;
+
+/// Pieces are sized so a torrent has about this many, within the bounds below
+const TARGET_PIECES: u64 = 1500;
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+
+fn piece_length(size: u64) -> u64 {
+    let mut length = MIN_PIECE_LENGTH;
+    while length < MAX_PIECE_LENGTH && size / length > TARGET_PIECES {
+        length *= 2;
+    }
+    length
+}
+
+fn bencode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+fn bencode_int(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(format!("i{}e", value).as_bytes());
+}
+
+/// Build a single file torrent without tracker, peers are found by web seed (BEP 19),
+/// so whoever can reach `web_seed` url of the file can download it.
+/// This reads the whole file, call it through a blocking thread.
+pub fn create_torrent(file: &str, name: &str, web_seed: &str) -> Result<Vec<u8>, String> {
+    let mut input = File::open(file).map_err(|e| e.to_string())?;
+    let size = input.metadata().map_err(|e| e.to_string())?.len();
+    let length = piece_length(size);
+    let mut pieces = Vec::new();
+    let mut buffer = vec![0u8; length as usize];
+    loop {
+        // fill a whole piece, read may return less than asked
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let n = input
+                .read(&mut buffer[filled..])
+                .map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            break;
+        }
+        pieces.extend_from_slice(&Sha1::digest(&buffer[..filled]));
+        if filled < buffer.len() {
+            break;
+        }
+    }
+    // keys of a bencoded dict must be sorted
+    let mut info = Vec::new();
+    info.push(b'd');
+    bencode_bytes(&mut info, b"length");
+    bencode_int(&mut info, size);
+    bencode_bytes(&mut info, b"name");
+    bencode_bytes(&mut info, name.as_bytes());
+    bencode_bytes(&mut info, b"piece length");
+    bencode_int(&mut info, length);
+    bencode_bytes(&mut info, b"pieces");
+    bencode_bytes(&mut info, &pieces);
+    info.push(b'e');
+
+    let mut torrent = Vec::new();
+    torrent.push(b'd');
+    bencode_bytes(&mut torrent, b"created by");
+    bencode_bytes(&mut torrent, b"BiliShadowReplay");
+    bencode_bytes(&mut torrent, b"creation date");
+    bencode_int(&mut torrent, chrono::Utc::now().timestamp() as u64);
+    bencode_bytes(&mut torrent, b"info");
+    torrent.extend_from_slice(&info);
+    bencode_bytes(&mut torrent, b"url-list");
+    bencode_bytes(&mut torrent, web_seed.as_bytes());
+    torrent.push(b'e');
+    Ok(torrent)
+}
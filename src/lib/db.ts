@@ -21,6 +21,8 @@ export interface AccountItem {
   csrf: string;
   cookies: string;
   created_at: string;
+  keepalive: boolean;
+  last_keepalive: string | null;
 }
 
 export interface MessageItem {
@@ -39,6 +41,7 @@ export interface RecordItem {
   length: number;
   size: number;
   created_at: string;
+  cover: string | null;
 }
 
 export interface AccountInfo {
@@ -5,6 +5,7 @@ export interface RoomInfo {
   room_keyframe_url: string;
   room_title: string;
   user_id: string;
+  online: number;
 }
 
 export interface UserInfo {
@@ -21,6 +22,35 @@ export interface RecorderInfo {
   total_length: number;
   current_ts: number;
   live_status: boolean;
+  buffering: boolean;
+  stream: StreamStatus | null;
+  pipeline: PipelineMetrics;
+  // qn of play url: 10000 原画, 400 蓝光, 250 超清, 150 高清
+  quality: number;
+  schedule: RecordWindow[];
+  priority: number;
+  queued: boolean;
+  paused: boolean;
+}
+
+// days are 1 (Monday) to 7 (Sunday), empty for every day; times are "HH:MM"
+export interface RecordWindow {
+  days: number[];
+  start: string;
+  end: string;
+}
+
+export interface PipelineMetrics {
+  pending_downloads: number;
+  pending_writes: number;
+  danmu_backlog: number;
+}
+
+export interface StreamStatus {
+  host: string;
+  expire: number;
+  renew_in: number;
+  last_error: string | null;
 }
 
 export interface RecorderList {
@@ -89,6 +119,16 @@ export interface Config {
   live_end_notify: boolean;
   clip_notify: boolean;
   post_notify: boolean;
+  quiet_hours: QuietHours | null;
+  room_quiet_hours: { [room_id: string]: QuietHours };
+  recording_mode: "full" | "rolling";
+  locale: "zh" | "en";
+  rolling_buffer_minutes: number;
+}
+
+export interface QuietHours {
+  start: string;
+  end: string;
 }
 
 export interface DiskInfo {
@@ -137,3 +177,170 @@ export interface Marker {
   realtime: number;
   content: string;
 }
+
+export interface Interaction {
+  id: number;
+  live_id: number;
+  room_id: number;
+  kind: "superchat" | "gift" | "guard";
+  uid: number;
+  user_name: string;
+  content: string;
+  gift_name: string;
+  num: number;
+  value: number;
+  ts: number;
+}
+
+export interface HeatmapBucket {
+  offset: number;
+  count: number;
+}
+
+export interface Highlight {
+  start: number;
+  end: number;
+  count: number;
+}
+
+export interface KeywordRule {
+  id: number;
+  room_id: number;
+  pattern: string;
+  is_regex: boolean;
+  created_at: string;
+}
+
+export interface RevenueStat {
+  period: string;
+  superchat: number;
+  gift: number;
+  guard: number;
+  total: number;
+}
+
+export interface DanmuStatistic {
+  id: number;
+  live_id: number;
+  room_id: number;
+  value: number;
+  time_point: string;
+}
+
+export interface RoomMetric {
+  id: number;
+  room_id: number;
+  live_id: number;
+  online: number;
+  ts: number;
+}
+
+export interface GiftSummary {
+  gift_name: string;
+  count: number;
+  value: number;
+}
+
+export interface FormatPolicy {
+  codec: "h264" | "hevc" | "av1";
+  container: string;
+  max_height: number | null;
+  skip: number[];
+}
+
+export interface ReencodeProgress {
+  total: number;
+  done: number;
+  current: number | null;
+  failed: number[];
+}
+
+export interface Watermark {
+  content:
+    | { kind: "image"; path: string }
+    | { kind: "text"; text: string; font_size: number };
+  position: "top_left" | "top_right" | "bottom_left" | "bottom_right";
+  opacity: number;
+  margin: number;
+}
+
+export interface PlatformCapabilities {
+  platform: string;
+  danmu: boolean;
+  gifts: boolean;
+  quality_selection: boolean;
+  login_methods: string[];
+  send_danmaku: boolean;
+}
+
+export interface TaskFinished {
+  kind: "clip" | "upload" | "reencode" | "export";
+  room_id: number | null;
+  ok: boolean;
+  message: string;
+  target: string | null;
+}
+
+export interface ArchiveProfile {
+  codec: "h264" | "hevc" | "av1";
+  max_height: number | null;
+  // empty for constant quality crf
+  bitrate: string;
+  crf: number;
+  // cap of video bitrate, empty for no cap
+  max_bitrate: string;
+  preset: string;
+  audio_bitrate: string;
+  // hardware encoder of codec if one is found
+  hwaccel: boolean;
+}
+
+export interface MediaInfo {
+  container: string;
+  codec: string;
+  width: number;
+  height: number;
+}
+
+export interface S3Config {
+  endpoint: string;
+  region: string;
+  bucket: string;
+  access_key: string;
+  secret_key: string;
+  public_url: string | null;
+}
+
+export interface UploadEncode {
+  codec: "h264" | "hevc";
+  target_mb: number;
+}
+
+export interface Interruption {
+  id: number;
+  room_id: number;
+  live_id: number;
+  start: number;
+  end: number;
+  created_at: string;
+}
+
+// retry delays double from base_ms up to max_ms, each spread randomly within jitter (0-1) of it;
+// segment downloads start from segment_base_ms
+export interface BackoffPolicy {
+  base_ms: number;
+  max_ms: number;
+  jitter: number;
+  segment_base_ms: number;
+}
+
+// segment writes of recordings are flushed together, interval 0 writes right away
+export interface WritePolicy {
+  flush_bytes: number;
+  flush_interval_ms: number;
+}
+
+export interface Snapshot {
+  offset: number;
+  url: string;
+}